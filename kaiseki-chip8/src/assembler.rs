@@ -0,0 +1,432 @@
+//! Assembles Chip-8, SUPER-CHIP and XO-CHIP source into program bytes.
+//!
+//! Source uses the mnemonics printed by the [disassembler](crate::disassembler), so listings
+//! assemble back into the program they came from. Each line holds an optional `label:`, then
+//! an optional instruction or data directive, then an optional `;` comment:
+//!
+//! ```text
+//! loop:
+//!     LD V0, 0x05         ; registers are V0 - VF
+//!     CALL draw           ; labels can be used wherever an address is expected
+//!     JP loop
+//! draw:
+//!     LD I, sprite
+//!     DRW V1, V2, 2
+//!     RET
+//! sprite:
+//!     db 0b11110000, 0x90 ; bytes
+//!     dw 0x1234, draw     ; big-endian words
+//! ```
+//!
+//! Mnemonics, registers and keywords are case-insensitive; labels aren't, and can't share a
+//! name with a register or keyword such as `I` or `DT`. Numbers may be decimal, or hexadecimal
+//! or binary with a `0x` or `0b` prefix.
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::instruction::Chip8Instruction;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AssemblerError {
+    #[error("line {0}: unknown mnemonic '{1}'")]
+    UnknownMnemonic(usize, String),
+    #[error("line {0}: invalid operands for '{1}'")]
+    InvalidOperands(usize, String),
+    #[error("line {0}: invalid label '{1}'")]
+    InvalidLabel(usize, String),
+    #[error("line {0}: label '{1}' is already defined")]
+    DuplicateLabel(usize, String),
+    #[error("line {0}: label '{1}' is never defined")]
+    UndefinedLabel(usize, String),
+    #[error("line {0}: 0x{1:X} doesn't fit in {2} bits")]
+    OutOfRange(usize, usize, usize),
+    #[error("line {0}: program runs past the end of the address space")]
+    ProgramTooLarge(usize),
+}
+
+pub type Result<T> = std::result::Result<T, AssemblerError>;
+
+/// A number, or a label standing for an address.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(usize),
+    Label(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Register(u8),
+    /// `I`
+    Index,
+    /// `[I]`
+    IndexedMemory,
+    /// `DT`
+    Delay,
+    /// `ST`
+    Sound,
+    /// `K`
+    Key,
+    /// `F`
+    Font,
+    /// `HF`
+    BigFont,
+    /// `B`
+    Bcd,
+    /// `R`
+    Flags,
+    /// `LONG` followed by a value.
+    Long(Value),
+    Value(Value),
+}
+
+/// A line with its label removed and its operands parsed, but not yet resolved.
+struct Statement {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+impl Statement {
+    fn invalid_operands(&self) -> AssemblerError {
+        AssemblerError::InvalidOperands(self.line, self.mnemonic.clone())
+    }
+
+    /// Number of bytes the statement assembles to, which must be known before labels are.
+    fn length(&self) -> Result<usize> {
+        match self.mnemonic.as_str() {
+            "DB" => Ok(self.operands.len()),
+            "DW" => Ok(self.operands.len() * 2),
+            "LD" if matches!(self.operands.as_slice(), [Operand::Index, Operand::Long(_)]) => Ok(4),
+            _ => Ok(2),
+        }
+    }
+}
+
+/// Assembles `source` into the bytes of a program that will be loaded at `origin`.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>> {
+    // First pass: find every label's address.
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut address = origin as usize;
+    for (idx, text) in source.lines().enumerate() {
+        let line = idx + 1;
+        let text = text.split(';').next().unwrap_or_default().trim();
+        let (label, rest) = match text.split_once(':') {
+            Some((label, rest)) => (Some(label.trim()), rest.trim()),
+            None => (None, text),
+        };
+        if let Some(label) = label {
+            // Names like `I` or `VA` would read as operands rather than the label.
+            if !matches!(parse_operand(label), Some(Operand::Value(Value::Label(_)))) {
+                return Err(AssemblerError::InvalidLabel(line, String::from(label)));
+            }
+            if labels.insert(String::from(label), address).is_some() {
+                return Err(AssemblerError::DuplicateLabel(line, String::from(label)));
+            }
+        }
+        if rest.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(line, rest)?;
+        address += statement.length()?;
+        if address > 0x10000 {
+            return Err(AssemblerError::ProgramTooLarge(line));
+        }
+        statements.push(statement);
+    }
+
+    // Second pass: encode everything with labels resolved.
+    let mut program = Vec::new();
+    for statement in &statements {
+        program.extend(encode_statement(statement, &labels)?);
+    }
+    Ok(program)
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_statement(line: usize, text: &str) -> Result<Statement> {
+    let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => (mnemonic, operands.trim()),
+        None => (text, ""),
+    };
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    let operands = match operands.is_empty() {
+        true => Vec::new(),
+        false => operands
+            .split(',')
+            .map(|operand| parse_operand(operand.trim()))
+            .collect::<Option<Vec<Operand>>>()
+            .ok_or_else(|| AssemblerError::InvalidOperands(line, mnemonic.clone()))?,
+    };
+    Ok(Statement {
+        line,
+        mnemonic,
+        operands,
+    })
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let upper = text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::Index,
+        "[I]" => Operand::IndexedMemory,
+        "DT" => Operand::Delay,
+        "ST" => Operand::Sound,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::BigFont,
+        "B" => Operand::Bcd,
+        "R" => Operand::Flags,
+        _ if upper.len() == 2 && upper.starts_with('V') => {
+            Operand::Register(u8::from_str_radix(&upper[1..], 16).ok()?)
+        }
+        _ if upper.starts_with("LONG ") => Operand::Long(parse_value(text[5..].trim())?),
+        _ => Operand::Value(parse_value(text)?),
+    };
+    Some(operand)
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    let lower = text.to_ascii_lowercase();
+    let number = if let Some(hex) = lower.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        usize::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else if is_label(text) {
+        return Some(Value::Label(String::from(text)));
+    } else {
+        None
+    };
+    number.map(Value::Number)
+}
+
+/// Resolves `value`, checking that it fits in `bits` bits.
+fn resolve(
+    line: usize,
+    value: &Value,
+    bits: usize,
+    labels: &HashMap<String, usize>,
+) -> Result<usize> {
+    let resolved = match value {
+        Value::Number(number) => *number,
+        Value::Label(label) => *labels
+            .get(label)
+            .ok_or_else(|| AssemblerError::UndefinedLabel(line, label.clone()))?,
+    };
+    match resolved < (1 << bits) {
+        true => Ok(resolved),
+        false => Err(AssemblerError::OutOfRange(line, resolved, bits)),
+    }
+}
+
+fn encode_statement(statement: &Statement, labels: &HashMap<String, usize>) -> Result<Vec<u8>> {
+    let line = statement.line;
+    let value = |operand: &Operand, bits: usize| match operand {
+        Operand::Value(value) => resolve(line, value, bits, labels),
+        _ => Err(statement.invalid_operands()),
+    };
+    let address = |operand: &Operand| value(operand, 12).map(|address| address as u16);
+    let byte = |operand: &Operand| value(operand, 8).map(|byte| byte as u8);
+    let nybble = |operand: &Operand| value(operand, 4).map(|nybble| nybble as u8);
+
+    match statement.mnemonic.as_str() {
+        "DB" => {
+            return statement.operands.iter().map(byte).collect();
+        }
+        "DW" => {
+            let mut bytes = Vec::new();
+            for operand in &statement.operands {
+                bytes.extend((value(operand, 16)? as u16).to_be_bytes());
+            }
+            return Ok(bytes);
+        }
+        _ => {}
+    }
+
+    use Chip8Instruction::*;
+    use Operand::{Register as V, *};
+    let instruction = match (statement.mnemonic.as_str(), statement.operands.as_slice()) {
+        ("CLS", []) => Clear,
+        ("RET", []) => Return,
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => LowRes,
+        ("HIGH", []) => HighRes,
+        ("AUDIO", []) => LoadAudio,
+        ("SCD", [rows]) => ScrollDown(nybble(rows)?),
+        ("SCU", [rows]) => ScrollUp(nybble(rows)?),
+        ("PLANE", [planes]) => SelectPlanes(nybble(planes)?),
+        ("SYS", [target]) => Sys(address(target)?),
+        ("JP", [V(0), target]) => JumpOffset(address(target)?),
+        ("JP", [target]) => Jump(address(target)?),
+        ("CALL", [target]) => Call(address(target)?),
+        ("SE", [V(x), V(y)]) => SkipEqual(*x, *y),
+        ("SE", [V(x), nn]) => SkipEqualByte(*x, byte(nn)?),
+        ("SNE", [V(x), V(y)]) => SkipNotEqual(*x, *y),
+        ("SNE", [V(x), nn]) => SkipNotEqualByte(*x, byte(nn)?),
+        ("SAVE", [V(x), V(y)]) => SaveRange(*x, *y),
+        ("LOAD", [V(x), V(y)]) => LoadRange(*x, *y),
+        ("LD", [Index, Long(target)]) => {
+            let target = resolve(line, target, 16, labels)? as u16;
+            let mut bytes = LoadIndexLong.encode().to_be_bytes().to_vec();
+            bytes.extend(target.to_be_bytes());
+            return Ok(bytes);
+        }
+        ("LD", [Index, target]) => LoadIndex(address(target)?),
+        ("LD", [V(x), V(y)]) => Move(*x, *y),
+        ("LD", [V(x), Delay]) => LoadDelay(*x),
+        ("LD", [V(x), Key]) => WaitKey(*x),
+        ("LD", [V(x), IndexedMemory]) => LoadRegisters(*x),
+        ("LD", [V(x), Flags]) => LoadFlags(*x),
+        ("LD", [V(x), nn]) => LoadByte(*x, byte(nn)?),
+        ("LD", [Delay, V(x)]) => SetDelay(*x),
+        ("LD", [Sound, V(x)]) => SetSound(*x),
+        ("LD", [Font, V(x)]) => LoadFont(*x),
+        ("LD", [BigFont, V(x)]) => LoadBigFont(*x),
+        ("LD", [Bcd, V(x)]) => StoreBcd(*x),
+        ("LD", [IndexedMemory, V(x)]) => StoreRegisters(*x),
+        ("LD", [Flags, V(x)]) => StoreFlags(*x),
+        ("ADD", [Index, V(x)]) => AddIndex(*x),
+        ("ADD", [V(x), V(y)]) => Add(*x, *y),
+        ("ADD", [V(x), nn]) => AddByte(*x, byte(nn)?),
+        ("OR", [V(x), V(y)]) => Or(*x, *y),
+        ("AND", [V(x), V(y)]) => And(*x, *y),
+        ("XOR", [V(x), V(y)]) => Xor(*x, *y),
+        ("SUB", [V(x), V(y)]) => Sub(*x, *y),
+        ("SUBN", [V(x), V(y)]) => SubReverse(*x, *y),
+        ("SHR", [V(x)]) => ShiftRight(*x, *x),
+        ("SHR", [V(x), V(y)]) => ShiftRight(*x, *y),
+        ("SHL", [V(x)]) => ShiftLeft(*x, *x),
+        ("SHL", [V(x), V(y)]) => ShiftLeft(*x, *y),
+        ("RND", [V(x), nn]) => Random(*x, byte(nn)?),
+        ("DRW", [V(x), V(y), rows]) => Draw(*x, *y, nybble(rows)?),
+        ("SKP", [V(x)]) => SkipKeyPressed(*x),
+        ("SKNP", [V(x)]) => SkipKeyNotPressed(*x),
+        ("PITCH", [V(x)]) => SetPitch(*x),
+        (
+            "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "AUDIO" | "SCD" | "SCU"
+            | "PLANE" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "SAVE" | "LOAD" | "LD" | "ADD"
+            | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP"
+            | "SKNP" | "PITCH",
+            _,
+        ) => return Err(statement.invalid_operands()),
+        (mnemonic, _) => {
+            return Err(AssemblerError::UnknownMnemonic(
+                line,
+                String::from(mnemonic),
+            ))
+        }
+    };
+    Ok(instruction.encode().to_be_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assemble, AssemblerError};
+    use crate::disassembler::disassemble;
+    use crate::instruction::Chip8Instruction;
+    use crate::machine::Chip8Variant;
+
+    #[test]
+    fn assemble_works() {
+        let source = "
+            start:  LD V0, 5        ; a comment
+                    ld v1, 0b101
+            loop:
+                    CALL draw
+                    JP loop
+                    JP V0, table
+            draw:   LD I, sprite
+                    DRW V0, V1, 2
+                    SHR VA
+                    LD I, LONG sprite
+                    RET
+            sprite: db 0xF0, 0x90
+            table:  dw 0x1234, start
+        ";
+        let program = assemble(source, 0x200).unwrap();
+        assert_eq!(
+            program,
+            vec![
+                0x60, 0x05, 0x61, 0x05, 0x22, 0x0A, 0x12, 0x04, 0xB2, 0x18, 0xA2, 0x16, 0xD0, 0x12,
+                0x8A, 0xA6, 0xF0, 0x00, 0x02, 0x16, 0x00, 0xEE, 0xF0, 0x90, 0x12, 0x34, 0x02, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn assemble_reports_errors() {
+        assert_eq!(
+            assemble("CLS\nFOO V0", 0x200),
+            Err(AssemblerError::UnknownMnemonic(2, String::from("FOO")))
+        );
+        assert_eq!(
+            assemble("DRW V0, V1", 0x200),
+            Err(AssemblerError::InvalidOperands(1, String::from("DRW")))
+        );
+        assert_eq!(
+            assemble("LD V0, VG", 0x200),
+            Err(AssemblerError::InvalidOperands(1, String::from("LD")))
+        );
+        assert_eq!(
+            assemble("a: CLS\na: CLS", 0x200),
+            Err(AssemblerError::DuplicateLabel(2, String::from("a")))
+        );
+        assert_eq!(
+            assemble("JP nowhere", 0x200),
+            Err(AssemblerError::UndefinedLabel(1, String::from("nowhere")))
+        );
+        assert_eq!(
+            assemble("LD V0, 256", 0x200),
+            Err(AssemblerError::OutOfRange(1, 256, 8))
+        );
+        assert_eq!(
+            assemble("2nd: CLS", 0x200),
+            Err(AssemblerError::InvalidLabel(1, String::from("2nd")))
+        );
+        assert_eq!(
+            assemble("r: JP r", 0x200),
+            Err(AssemblerError::InvalidLabel(1, String::from("r")))
+        );
+    }
+
+    #[test]
+    fn every_instruction_round_trips() {
+        for opcode in 0..=u16::MAX {
+            let Ok(instruction) = Chip8Instruction::decode(opcode) else {
+                continue;
+            };
+            let mut program = opcode.to_be_bytes().to_vec();
+            if instruction == Chip8Instruction::LoadIndexLong {
+                program.extend([0xAB, 0xCD]);
+            }
+            let listing = disassemble(&program, 0x200, Chip8Variant::XoChip).to_string();
+            assert_eq!(assemble(&listing, 0x200), Ok(program), "{}", listing);
+        }
+    }
+
+    #[test]
+    fn listings_round_trip() {
+        let program = [
+            0x00, 0xE0, 0xA2, 0x0C, 0x60, 0x00, 0x22, 0x0A, 0x12, 0x02, 0xD0, 0x05, 0x00, 0xEE,
+            0xF0, 0x90, 0xF0, 0x00, 0x02, 0x00, 0x5A, 0xB1, 0x42,
+        ];
+        for variant in [Chip8Variant::Chip8, Chip8Variant::XoChip] {
+            let listing = disassemble(&program, 0x200, variant).to_string();
+            assert_eq!(assemble(&listing, 0x200).unwrap(), program, "{}", listing);
+        }
+    }
+}
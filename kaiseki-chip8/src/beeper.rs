@@ -0,0 +1,230 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use kaiseki_core::audio::AudioSink;
+use kaiseki_core::{Component, ComponentId, ResettableComponent};
+
+use super::registers::{Chip8Registers, DEFAULT_PITCH};
+use super::timers::TIMER_FREQUENCY_HZ;
+
+/// Frequency of the square wave played while the sound timer is running.
+pub const BEEP_FREQUENCY_HZ: u32 = 440;
+/// Rate in bits per second at which an audio pattern plays back at the default pitch.
+const PATTERN_RATE_HZ: f64 = 4000.0;
+const BEEP_AMPLITUDE: i16 = i16::MAX / 4;
+
+#[derive(Debug, Default)]
+struct Chip8BeeperState {
+    phase: u64,
+    pattern_position: f64,
+}
+
+/// Renders one timer tick's worth of audio at a time while the sound timer is non-zero: the
+/// XO-CHIP audio pattern if one is loaded, or a square wave otherwise. Renders silence while
+/// the sound timer is zero.
+#[derive(Clone)]
+pub struct Chip8Beeper {
+    id: ComponentId,
+    sink: Arc<dyn AudioSink>,
+    state: Arc<Mutex<Chip8BeeperState>>,
+}
+
+impl Component for Chip8Beeper {
+    fn id(&self) -> &ComponentId {
+        &self.id
+    }
+}
+
+impl fmt::Debug for Chip8Beeper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Chip8Beeper[{}]", self.sink.id())
+    }
+}
+
+#[async_trait]
+impl ResettableComponent for Chip8Beeper {
+    /// Starts the square wave and audio pattern over, so the next beep doesn't carry on from
+    /// wherever the last one left off.
+    async fn reset(&self) {
+        *self.state.lock().unwrap() = Chip8BeeperState::default();
+    }
+}
+
+impl Chip8Beeper {
+    pub fn new(sink: impl AudioSink) -> Self {
+        Self {
+            id: ComponentId::new("Chip-8 Beeper"),
+            sink: Arc::new(sink),
+            state: Arc::new(Mutex::new(Chip8BeeperState::default())),
+        }
+    }
+
+    /// Writes a single timer tick of audio to the sink.
+    pub fn render_frame(&self, regs: &Chip8Registers) {
+        let sample_rate = self.sink.sample_rate() as u64;
+        let samples_per_frame = sample_rate as usize / TIMER_FREQUENCY_HZ;
+        let half_period = (sample_rate / (2 * BEEP_FREQUENCY_HZ as u64)).max(1);
+
+        let mut state = self.state.lock().unwrap();
+        let samples: Vec<i16> = match (regs.ST, regs.PATTERN) {
+            (0, _) => {
+                state.phase = 0;
+                state.pattern_position = 0.0;
+                vec![0; samples_per_frame]
+            }
+            (_, None) => (0..samples_per_frame)
+                .map(|_| {
+                    let high = (state.phase / half_period).is_multiple_of(2);
+                    state.phase += 1;
+                    match high {
+                        true => BEEP_AMPLITUDE,
+                        false => -BEEP_AMPLITUDE,
+                    }
+                })
+                .collect(),
+            (_, Some(pattern)) => {
+                let exponent = (regs.PITCH as f64 - DEFAULT_PITCH as f64) / 48.0;
+                let step = PATTERN_RATE_HZ * 2f64.powf(exponent) / sample_rate as f64;
+                (0..samples_per_frame)
+                    .map(|_| {
+                        let bit = state.pattern_position as usize % 128;
+                        state.pattern_position = (state.pattern_position + step) % 128.0;
+                        match (pattern[bit / 8] >> (7 - (bit % 8))) & 0x01 {
+                            1 => BEEP_AMPLITUDE,
+                            _ => -BEEP_AMPLITUDE,
+                        }
+                    })
+                    .collect()
+            }
+        };
+
+        if let Err(e) = self.sink.write(&samples) {
+            tracing::error!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use kaiseki_core::audio::{AudioSink, NullAudioSink, Result};
+    use kaiseki_core::{Component, ComponentId, ResettableComponent};
+
+    use super::{Chip8Beeper, Chip8Registers, BEEP_AMPLITUDE};
+
+    #[derive(Clone)]
+    struct TestSink {
+        id: ComponentId,
+        samples: Arc<Mutex<Vec<i16>>>,
+    }
+
+    impl Component for TestSink {
+        fn id(&self) -> &ComponentId {
+            &self.id
+        }
+    }
+
+    impl AudioSink for TestSink {
+        fn sample_rate(&self) -> u32 {
+            // 8 samples per half-period of the 440hz beep, 120 samples per frame.
+            7200
+        }
+
+        fn write(&self, samples: &[i16]) -> Result<()> {
+            self.samples.lock().unwrap().extend_from_slice(samples);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn render_frame_writes_silence_when_timer_is_zero() {
+        let sink = NullAudioSink::new(44100);
+        let beeper = Chip8Beeper::new(sink.clone());
+        beeper.render_frame(&Chip8Registers::new());
+        assert_eq!(sink.samples_written(), 735);
+    }
+
+    fn test_sink() -> TestSink {
+        TestSink {
+            id: ComponentId::new("test sink"),
+            samples: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    #[test]
+    fn render_frame_writes_square_wave_when_timer_is_running() {
+        let sink = test_sink();
+        let beeper = Chip8Beeper::new(sink.clone());
+        let mut regs = Chip8Registers::new();
+        beeper.render_frame(&regs);
+        regs.ST = 5;
+        beeper.render_frame(&regs);
+        regs.ST = 4;
+        beeper.render_frame(&regs);
+
+        let samples = sink.samples.lock().unwrap();
+        assert_eq!(samples.len(), 360);
+        assert!(samples[..120].iter().all(|s| *s == 0));
+
+        // The wave alternates every 8 samples, and stays in phase across frames.
+        let wave = &samples[120..];
+        for (idx, sample) in wave.iter().enumerate() {
+            let expected = match (idx / 8) % 2 {
+                0 => BEEP_AMPLITUDE,
+                _ => -BEEP_AMPLITUDE,
+            };
+            assert_eq!(*sample, expected, "sample {} is wrong", idx);
+        }
+    }
+
+    #[tokio::test]
+    async fn reset_starts_the_wave_over() {
+        let sink = test_sink();
+        let beeper = Chip8Beeper::new(sink.clone());
+        let mut regs = Chip8Registers::new();
+        regs.ST = 5;
+        // A frame is 15 half-periods, so the next would start low if the wave carried on.
+        beeper.render_frame(&regs);
+        beeper.reset().await;
+        sink.samples.lock().unwrap().clear();
+        beeper.render_frame(&regs);
+
+        let samples = sink.samples.lock().unwrap();
+        assert!(samples[..8].iter().all(|s| *s == BEEP_AMPLITUDE));
+        assert!(samples[8..16].iter().all(|s| *s == -BEEP_AMPLITUDE));
+    }
+
+    #[test]
+    fn render_frame_plays_audio_pattern() {
+        let sink = test_sink();
+        let beeper = Chip8Beeper::new(sink.clone());
+        let mut regs = Chip8Registers::new();
+        regs.ST = 1;
+        // Alternate bytes of all-ones and all-zeroes.
+        regs.PATTERN = Some([0xFF, 0x00].repeat(8).try_into().unwrap());
+
+        // At 7200 samples per second and pitch 64, the pattern advances 5/9ths of a bit per
+        // sample, so each 8-bit run lasts 14 or 15 samples.
+        beeper.render_frame(&regs);
+        {
+            let samples = sink.samples.lock().unwrap();
+            assert!(samples[..14].iter().all(|s| *s == BEEP_AMPLITUDE));
+            assert!(samples[15..28].iter().all(|s| *s == -BEEP_AMPLITUDE));
+        }
+
+        // Raising the pitch by 48 doubles the playback rate.
+        sink.samples.lock().unwrap().clear();
+        regs.ST = 0;
+        beeper.render_frame(&regs);
+        regs.ST = 1;
+        regs.PITCH = 112;
+        sink.samples.lock().unwrap().clear();
+        beeper.render_frame(&regs);
+        let samples = sink.samples.lock().unwrap();
+        assert!(samples[..7].iter().all(|s| *s == BEEP_AMPLITUDE));
+        assert!(samples[8..14].iter().all(|s| *s == -BEEP_AMPLITUDE));
+    }
+}
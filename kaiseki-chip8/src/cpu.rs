@@ -1,22 +1,45 @@
+use std::future::Future;
 use std::sync::Arc;
-use std::u8;
 
 use async_trait::async_trait;
 use thiserror::Error;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, MutexGuard, RwLock};
 
+use kaiseki_core::debugger::Debugger;
+use kaiseki_core::snapshot::{self, StateReader, StateWriter, StatefulComponent};
 use kaiseki_core::{
     AddressableBus, AddressableComponent, AddressableComponentError, Component, ComponentId,
-    ExecutableComponent, OscillatorBus, OscillatorBusMessage,
+    ExecutableComponent, Fault, Lifecycle, OscillatorBus, OscillatorBusMessage,
+    ResettableComponent,
 };
 
+use super::disassembler::Chip8Line;
+use super::display::PlanarDisplay;
+use super::font::{
+    BIG_FONT_ADDRESS, BIG_FONT_SPRITE_LENGTH, SMALL_FONT_ADDRESS, SMALL_FONT_SPRITE_LENGTH,
+};
+use super::instruction::{Chip8Instruction, DecodeError};
+use super::keypad::Chip8Keypad;
+use super::machine::Chip8Variant;
+use super::quirks::{Chip8IndexIncrement, Chip8Quirks};
 use super::registers::Chip8Registers;
-use super::stack::Chip8Stack;
+use super::rng::Chip8Rng;
+use super::stack::{Chip8Stack, Chip8StackError};
 
 #[derive(Debug, Error, PartialEq)]
 pub enum Chip8CpuError {
     #[error("failed to fetch next instruction")]
     InstructionFetch(#[from] AddressableComponentError),
+    #[error("failed to access memory")]
+    MemoryAccess(AddressableComponentError),
+    #[error(transparent)]
+    InvalidInstruction(#[from] DecodeError),
+    #[error("invalid register index 0x{0:02X}")]
+    InvalidRegister(u8),
+    #[error(transparent)]
+    Stack(#[from] Chip8StackError),
+    #[error("failed to draw sprite")]
+    Draw(AddressableComponentError),
 }
 
 pub type Result<T> = std::result::Result<T, Chip8CpuError>;
@@ -26,8 +49,18 @@ pub struct Chip8CPU {
     id: ComponentId,
     clock_bus: OscillatorBus,
     memory_bus: AddressableBus,
+    display: PlanarDisplay,
+    keypad: Chip8Keypad,
+    variant: Chip8Variant,
+    quirks: Chip8Quirks,
+    initial_pc: u16,
     regs: Arc<RwLock<Chip8Registers>>,
     stack: Arc<RwLock<Chip8Stack>>,
+    rng: Chip8Rng,
+    debugger: Debugger,
+    /// Held for the whole of each cycle, so the machine can be captured, restored or reset
+    /// between cycles while it runs.
+    cycle_lock: Arc<Mutex<()>>,
 }
 
 impl Component for Chip8CPU {
@@ -38,9 +71,93 @@ impl Component for Chip8CPU {
 
 #[async_trait]
 impl ExecutableComponent for Chip8CPU {
-    async fn start(&self) {
+    async fn start(&self, lifecycle: &Lifecycle) -> std::result::Result<(), Fault> {
+        self.run(lifecycle, |_| async {}).await
+    }
+}
+
+#[async_trait]
+impl StatefulComponent for Chip8CPU {
+    async fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        self.regs.read().await.save_state(&mut writer);
+        self.stack.read().await.save_state(&mut writer);
+        writer.finish()
+    }
+
+    async fn load_state(&self, state: &[u8]) -> snapshot::Result<()> {
+        // Restore into copies so a bad snapshot leaves the CPU untouched.
+        let mut regs = Chip8Registers::new();
+        let mut stack = Chip8Stack::new();
+        let mut reader = StateReader::new(state);
+        regs.load_state(&mut reader)?;
+        stack.load_state(&mut reader)?;
+        reader.finish()?;
+
+        *self.regs.write().await = regs;
+        *self.stack.write().await = stack;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ResettableComponent for Chip8CPU {
+    /// Clears the registers, timers and stack, and points PC back at the start of the program.
+    async fn reset(&self) {
+        let mut regs = Chip8Registers::new();
+        regs.PC = self.initial_pc;
+        *self.regs.write().await = regs;
+        *self.stack.write().await = Chip8Stack::new();
+    }
+}
+
+impl Chip8CPU {
+    pub fn new(
+        clock_bus: &OscillatorBus,
+        memory_bus: &AddressableBus,
+        display: &PlanarDisplay,
+        keypad: &Chip8Keypad,
+        variant: Chip8Variant,
+        quirks: Chip8Quirks,
+        initial_pc: u16,
+    ) -> Self {
+        let id = ComponentId::new("Chip-8 CPU");
+        let mut regs = Chip8Registers::new();
+        regs.PC = initial_pc;
+        Chip8CPU {
+            id,
+            clock_bus: clock_bus.clone(),
+            memory_bus: memory_bus.clone(),
+            display: display.clone(),
+            keypad: keypad.clone(),
+            variant,
+            quirks,
+            initial_pc,
+            regs: Arc::new(RwLock::new(regs)),
+            stack: Arc::new(RwLock::new(Chip8Stack::new())),
+            rng: Chip8Rng::new(rand::random()),
+            debugger: Debugger::new(),
+            cycle_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Executes the cycles handed out by the system clock until `lifecycle` stops, like
+    /// [`ExecutableComponent::start`], awaiting `cycle_ended` with the number of each cycle
+    /// executed before starting the next.
+    pub(crate) async fn run<F, Fut>(
+        &self,
+        lifecycle: &Lifecycle,
+        cycle_ended: F,
+    ) -> std::result::Result<(), Fault>
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future<Output = ()>,
+    {
         loop {
-            let (message, responder) = self.clock_bus.recv(&self.id).await.unwrap();
+            let (message, responder) = tokio::select! {
+                result = self.clock_bus.recv(&self.id) => result.unwrap(),
+                _ = lifecycle.stopped() => break,
+            };
             if let OscillatorBusMessage::CycleBatchStart {
                 start_cycle,
                 cycle_budget,
@@ -48,60 +165,147 @@ impl ExecutableComponent for Chip8CPU {
             {
                 let end_cycle = start_cycle + cycle_budget;
                 tracing::info!("executing cycles {} - {}", start_cycle, end_cycle);
+                let mut cycles_spent = 0;
+                let mut fault = None;
                 for current_cycle in start_cycle..end_cycle {
-                    self.execute_cycle(current_cycle).await.unwrap();
+                    // Hand the rest of the batch back if paused or stopped partway through.
+                    if cycles_spent > 0 && !lifecycle.is_running() {
+                        break;
+                    }
+                    if self.check_breakpoints().await {
+                        lifecycle.pause();
+                        break;
+                    }
+                    if let Err(e) = self.run_cycle(current_cycle).await {
+                        // Fault before responding so the oscillator sees why the batch ended.
+                        lifecycle.fault();
+                        fault = Some(e);
+                        break;
+                    }
+                    cycles_spent += 1;
+                    cycle_ended(current_cycle).await;
                 }
                 let response = OscillatorBusMessage::CycleBatchEnd {
                     start_cycle,
-                    cycles_spent: cycle_budget,
+                    cycles_spent,
                 };
-                responder.unwrap().send(response).unwrap();
+                // A stopped oscillator no longer waits for the response.
+                let _ = responder.unwrap().send(response);
+                if let Some(fault) = fault {
+                    return Err(fault);
+                }
             }
         }
+
+        tracing::info!("CPU stopped");
+        Ok(())
     }
-}
 
-impl Chip8CPU {
-    pub fn new(clock_bus: &OscillatorBus, memory_bus: &AddressableBus, initial_pc: u16) -> Self {
-        let id = ComponentId::new("Chip-8 CPU");
-        let mut regs = Chip8Registers::new();
-        regs.PC = initial_pc;
-        Chip8CPU {
-            id,
-            clock_bus: clock_bus.clone(),
-            memory_bus: memory_bus.clone(),
-            regs: Arc::new(RwLock::new(regs)),
-            stack: Arc::new(RwLock::new(Chip8Stack::new())),
+    /// Keeps any more cycles from starting until the guard is dropped, waiting for the current
+    /// one to finish.
+    pub(crate) async fn lock_cycles(&self) -> MutexGuard<'_, ()> {
+        self.cycle_lock.lock().await
+    }
+
+    /// Draws the numbers for CXNN from `rng` from now on, rather than from a randomly seeded
+    /// source of its own.
+    pub fn attach_rng(&mut self, rng: &Chip8Rng) {
+        self.rng = rng.clone();
+    }
+
+    /// Checks `debugger`'s breakpoints before every instruction from now on.
+    pub fn attach_debugger(&mut self, debugger: &Debugger) {
+        self.debugger = debugger.clone();
+    }
+
+    /// Checks the breakpoints against the instruction about to be executed, returning whether
+    /// the debugger has halted the machine.
+    pub(crate) async fn check_breakpoints(&self) -> bool {
+        if !self.debugger.has_breakpoints() {
+            return self.debugger.halted().is_some();
         }
+        let pc = self.regs.read().await.PC as usize;
+        let registers = self.register_values().await;
+        self.debugger.check_breakpoints(pc, &registers)
     }
 
-    fn draw_sprite(&self, address: u16, length: u8, x_pos: usize, y_pos: usize) -> bool {
-        let sprite = self.memory_bus.read(address.into(), length.into()).unwrap();
-        let mut pixel_flipped = false;
-        for (sprite_row, sprite_byte) in sprite.iter().enumerate() {
-            let display_row_offset = (y_pos + sprite_row) * 8;
-            for sprite_col in 0..=7 {
-                let sprite_bit = (sprite_byte >> (7 - sprite_col)) & 0x01;
-                // Absolute 0 - 63 column index into the display for the current pixel.
-                let display_col = (x_pos + sprite_col) % 64;
-                // Offset that the column index provides to the final display byte index.
-                let display_col_offset = display_col / 8;
-                let display_byte_idx = display_row_offset + display_col_offset;
-                let display_byte = self.memory_bus.read(0x1000 + display_byte_idx, 1).unwrap()[0];
-                let display_bit_idx = 7 - (display_col % 8);
-                let display_bitmask = 0x01 << display_bit_idx;
-                let display_bit = (display_byte & display_bitmask) >> display_bit_idx;
-                let new_bit = display_bit ^ sprite_bit;
-                if new_bit != display_bit {
-                    pixel_flipped = true;
-                }
-                let new_byte = (display_byte & !display_bitmask) | (new_bit << display_bit_idx);
-                self.memory_bus
-                    .write(0x1000 + display_byte_idx, &[new_byte])
-                    .unwrap();
-            }
+    pub(crate) fn registers(&self) -> &Arc<RwLock<Chip8Registers>> {
+        &self.regs
+    }
+
+    /// Names and values of every register, including PC and the stack pointer.
+    pub(crate) async fn register_values(&self) -> Vec<(String, usize)> {
+        let regs = self.regs.read().await;
+        let stack = self.stack.read().await;
+        let mut values = regs.named_values();
+        values.push((String::from("PC"), regs.PC as usize));
+        values.push((String::from("SP"), stack.stack_pointer() as usize));
+        values
+    }
+
+    /// Draws the sprite for DXYN, returning the value VF should take. That's whether the
+    /// sprite collided with anything lit, except on SUPER-CHIP in high resolution, where it's
+    /// the number of rows that collided or were clipped off the bottom of the display.
+    fn draw_sprite(&self, address: u16, length: u8, x_pos: usize, y_pos: usize) -> Result<u8> {
+        let superchip_hires =
+            self.variant == Chip8Variant::SuperChip && self.display.resolution().0 > 64;
+        // For DXY0, SUPER-CHIP draws a 16x16 sprite, two bytes per row, in high resolution
+        // and an 8x16 one in low resolution; XO-CHIP draws 16x16 in both.
+        let (length, row_bytes) = match length {
+            0 if self.variant == Chip8Variant::SuperChip && !superchip_hires => (16, 1),
+            0 if self.variant.supports(Chip8Variant::SuperChip) => (32, 2),
+            _ => (length as usize, 1),
+        };
+        // XO-CHIP reads one sprite for each selected plane, one after the other.
+        let length = length * self.display.selected_plane_count();
+        let sprite = self
+            .memory_bus
+            .read(address.into(), length)
+            .map_err(Chip8CpuError::Draw)?;
+        let drawn =
+            self.display
+                .draw_sprite(x_pos, y_pos, &sprite, row_bytes, self.quirks.sprites_wrap);
+        Ok(match superchip_hires {
+            true => (drawn.collided_rows + drawn.clipped_rows) as u8,
+            false => drawn.collided() as u8,
+        })
+    }
+
+    fn increment_index(&self, regs: &mut Chip8Registers, vx_id: u8) {
+        let increment = match self.quirks.index_increment {
+            Chip8IndexIncrement::Unchanged => 0,
+            Chip8IndexIncrement::ByX => vx_id as u16,
+            Chip8IndexIncrement::ByXPlusOne => vx_id as u16 + 1,
+        };
+        regs.VI = regs.VI.wrapping_add(increment);
+    }
+
+    /// Length in bytes of the instruction at `address`, which is 4 for the XO-CHIP F000 NNNN
+    /// long load and 2 for everything else.
+    fn instruction_length(&self, address: u16) -> Result<u16> {
+        if self.variant.supports(Chip8Variant::XoChip) && self.fetch(address)? == 0xF000 {
+            return Ok(4);
         }
-        pixel_flipped
+        Ok(2)
+    }
+
+    /// IDs of the registers from VX to VY inclusive, in descending order if X > Y.
+    fn register_range(vx_id: u8, vy_id: u8) -> Vec<u8> {
+        match vx_id <= vy_id {
+            true => (vx_id..=vy_id).collect(),
+            false => (vy_id..=vx_id).rev().collect(),
+        }
+    }
+
+    fn register(regs: &Chip8Registers, vx_id: u8) -> Result<u8> {
+        regs.get_register_ref(vx_id)
+            .copied()
+            .ok_or(Chip8CpuError::InvalidRegister(vx_id))
+    }
+
+    fn register_mut(regs: &mut Chip8Registers, vx_id: u8) -> Result<&mut u8> {
+        regs.get_register_mut(vx_id)
+            .ok_or(Chip8CpuError::InvalidRegister(vx_id))
     }
 
     fn fetch(&self, address: u16) -> Result<u16> {
@@ -112,250 +316,982 @@ impl Chip8CPU {
         Ok(u16::from_be_bytes(slice))
     }
 
-    async fn execute_cycle(&self, cycle_number: usize) -> Result<()> {
+    /// Executes one cycle, turning any error into a fault at the instruction that caused it,
+    /// which is left at PC.
+    pub(crate) async fn run_cycle(&self, cycle_number: usize) -> std::result::Result<(), Fault> {
+        let _cycle = self.lock_cycles().await;
+        self.keypad.apply_input(cycle_number);
+        match self.execute_cycle(cycle_number).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let pc = self.regs.read().await.PC;
+                Err(Fault::new(&self.id, &e.to_string(), Some(pc.into())))
+            }
+        }
+    }
+
+    pub(crate) async fn execute_cycle(&self, cycle_number: usize) -> Result<()> {
         let mut regs = self.regs.write().await;
 
-        let opcode = self.fetch(regs.PC)?;
-        let embedded_address = opcode & 0x0FFF;
-        let embedded_byte = (opcode & 0x00FF) as u8;
-        let embedded_nybble = (opcode & 0x000F) as u8;
-        let vx_id = ((opcode & 0x0F00) >> 8) as u8;
-        let vy_id = ((opcode & 0x00F0) >> 4) as u8;
-        let desc: String;
-        match opcode {
-            0x0000..=0x0FFF => match opcode {
-                0x00E0 => {
-                    regs.PC += 2;
-                    self.memory_bus.write(0x1000, &[0; 2048]).unwrap();
-                    desc = String::from("clear screen");
-                }
-                0x00EE => {
-                    let mut stack = self.stack.write().await;
-                    regs.PC = stack.pop();
-                    desc = String::from("return from subroutine");
-                }
-                _ => {
-                    regs.PC = embedded_address;
-                    desc = format!(
-                        "execute machine language subroutine at 0x{:04X}",
-                        embedded_address
-                    );
-                }
-            },
-            0x1000..=0x1FFF => {
-                regs.PC = embedded_address;
-                desc = format!("jump to address 0x{:04X}", embedded_address);
-            }
-            0x2000..=0x2FFF => {
-                let mut stack = self.stack.write().await;
-                stack.push(regs.PC + 2);
-                regs.PC = embedded_address;
-                desc = format!("execute subroutine at address 0x{:04X}", embedded_address);
-            }
-            0x3000..=0x3FFF => {
-                let vx = regs.get_register_ref(vx_id);
-                if *vx == embedded_byte {
-                    regs.PC += 4;
-                } else {
-                    regs.PC += 2;
-                }
-                desc = format!(
-                    "skip next instruction if V{} == 0x{:02X}",
-                    vx_id, embedded_byte
-                );
-            }
-            0x4000..=0x4FFF => {
-                let vx = regs.get_register_ref(vx_id);
-                if *vx != embedded_byte {
-                    regs.PC += 4;
-                } else {
-                    regs.PC += 2;
-                }
-                desc = format!(
-                    "skip next instruction if V{} != 0x{:02X}",
-                    vx_id, embedded_byte
-                );
-            }
-            0x5000..=0x5FFF => match embedded_nybble {
-                0x0 => {
-                    let vx = regs.get_register_ref(vx_id);
-                    let vy = regs.get_register_ref(vy_id);
-                    if *vx == *vy {
-                        regs.PC += 4;
-                    } else {
-                        regs.PC += 2;
-                    }
-                    desc = format!("skip next instruction if V{} == V{}", vx_id, vy_id);
-                }
-                _ => panic!("invalid 0x5XY0 opcode"),
-            },
-            0x6000..=0x6FFF => {
-                let vx = regs.get_register_mut(vx_id);
-                *vx = embedded_byte;
-                regs.PC += 2;
-                desc = format!("store 0x{:02X} in V{}", embedded_byte, vx_id);
-            }
-            0x7000..=0x7FFF => {
-                let vx = regs.get_register_mut(vx_id);
-                *vx += embedded_byte;
-                regs.PC += 2;
-                desc = format!("add 0x{:02X} to V{}", embedded_byte, vx_id);
-            }
-            0x8000..=0x8FFF => match embedded_nybble {
-                0x0 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    *vx = vy_value;
-                    regs.PC += 2;
-                    desc = format!("store V{} in V{}", vy_id, vx_id);
+        let pc = regs.PC;
+        let opcode = self.fetch(pc)?;
+        let instruction = Chip8Instruction::decode_for(opcode, self.variant)?;
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            let bytes = self
+                .memory_bus
+                .inspect(pc.into(), instruction.length().into())?;
+            // Listings are the same under every quirk, so say when BNNN adds VX instead of V0.
+            let note = match instruction {
+                Chip8Instruction::JumpOffset(address) if self.quirks.jump_uses_vx => {
+                    format!(" (adds V{:X})", address >> 8)
                 }
-                0x1 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    *vx |= vy_value;
-                    regs.PC += 2;
-                    desc = format!("store V{} | V{} in V{}", vx_id, vy_id, vx_id);
+                _ => String::new(),
+            };
+            tracing::debug!(
+                "cycle {} | PC: 0x{:04X} | 0x{:04X} {}{}",
+                cycle_number,
+                pc,
+                opcode,
+                Chip8Line::decode(&bytes, pc, self.variant),
+                note,
+            );
+        }
+        self.execute(&mut regs, instruction).await
+    }
+
+    /// Executes `instruction`, which was fetched from the address in PC.
+    async fn execute(
+        &self,
+        regs: &mut Chip8Registers,
+        instruction: Chip8Instruction,
+    ) -> Result<()> {
+        use Chip8Instruction::*;
+
+        let mut next_pc = regs.PC.wrapping_add(instruction.length());
+        let mut skip = false;
+        match instruction {
+            // There's no machine language to run, so treat these as jumps like kaiseki always
+            // has.
+            Sys(address) => next_pc = address,
+            ScrollDown(rows) => self.display.scroll_down(rows.into()),
+            ScrollUp(rows) => self.display.scroll_up(rows.into()),
+            Clear => self.display.clear(),
+            Return => next_pc = self.stack.write().await.pop()?,
+            ScrollRight => self.display.scroll_right(4),
+            ScrollLeft => self.display.scroll_left(4),
+            // There's no interpreter to return to, so spin on this instruction.
+            Exit => next_pc = regs.PC,
+            LowRes => self.display.set_resolution(64, 32),
+            HighRes => {
+                let (width, height) = self.variant.max_resolution();
+                self.display.set_resolution(width, height);
+            }
+            Jump(address) => next_pc = address,
+            Call(address) => {
+                self.stack.write().await.push(next_pc)?;
+                next_pc = address;
+            }
+            SkipEqualByte(vx_id, byte) => skip = Self::register(regs, vx_id)? == byte,
+            SkipNotEqualByte(vx_id, byte) => skip = Self::register(regs, vx_id)? != byte,
+            SkipEqual(vx_id, vy_id) => {
+                skip = Self::register(regs, vx_id)? == Self::register(regs, vy_id)?;
+            }
+            SaveRange(vx_id, vy_id) => {
+                let values = Self::register_range(vx_id, vy_id)
+                    .into_iter()
+                    .map(|idx| Self::register(regs, idx))
+                    .collect::<Result<Vec<u8>>>()?;
+                self.memory_bus
+                    .write(regs.VI.into(), &values)
+                    .map_err(Chip8CpuError::MemoryAccess)?;
+            }
+            LoadRange(vx_id, vy_id) => {
+                let ids = Self::register_range(vx_id, vy_id);
+                let values = self
+                    .memory_bus
+                    .read(regs.VI.into(), ids.len())
+                    .map_err(Chip8CpuError::MemoryAccess)?;
+                for (idx, value) in ids.into_iter().zip(values) {
+                    *Self::register_mut(regs, idx)? = value;
                 }
-                0x2 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    *vx &= vy_value;
-                    regs.PC += 2;
-                    desc = format!("store V{} & V{} in V{}", vx_id, vy_id, vx_id);
+            }
+            LoadByte(vx_id, byte) => *Self::register_mut(regs, vx_id)? = byte,
+            AddByte(vx_id, byte) => {
+                let vx = Self::register_mut(regs, vx_id)?;
+                *vx = vx.wrapping_add(byte);
+            }
+            Move(vx_id, vy_id) => *Self::register_mut(regs, vx_id)? = Self::register(regs, vy_id)?,
+            Or(vx_id, vy_id) | And(vx_id, vy_id) | Xor(vx_id, vy_id) => {
+                let vy = Self::register(regs, vy_id)?;
+                let vx = Self::register_mut(regs, vx_id)?;
+                match instruction {
+                    Or(_, _) => *vx |= vy,
+                    And(_, _) => *vx &= vy,
+                    _ => *vx ^= vy,
                 }
-                0x3 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    *vx ^= vy_value;
-                    regs.PC += 2;
-                    desc = format!("store V{} ^ V{} in V{}", vx_id, vy_id, vx_id);
+                if self.quirks.logic_resets_vf {
+                    regs.VF = 0x00;
                 }
-                0x4 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    let result = *vx as u16 + vy_value as u16;
-                    *vx = result as u8;
-                    if result > u8::MAX as u16 {
-                        regs.VF = 0x01;
-                    } else {
-                        regs.VF = 0x00;
-                    }
-                    regs.PC += 2;
-                    desc = format!("add V{} + V{} with carry", vx_id, vy_id);
+            }
+            Add(vx_id, vy_id) => {
+                let vy = Self::register(regs, vy_id)?;
+                let vx = Self::register_mut(regs, vx_id)?;
+                let (result, carry) = vx.overflowing_add(vy);
+                *vx = result;
+                regs.VF = carry as u8;
+            }
+            Sub(vx_id, vy_id) => {
+                let vy = Self::register(regs, vy_id)?;
+                let vx = Self::register_mut(regs, vx_id)?;
+                let (result, borrow) = vx.overflowing_sub(vy);
+                *vx = result;
+                regs.VF = !borrow as u8;
+            }
+            SubReverse(vx_id, vy_id) => {
+                let vy = Self::register(regs, vy_id)?;
+                let vx = Self::register_mut(regs, vx_id)?;
+                let (result, borrow) = vy.overflowing_sub(*vx);
+                *vx = result;
+                regs.VF = !borrow as u8;
+            }
+            ShiftRight(vx_id, vy_id) | ShiftLeft(vx_id, vy_id) => {
+                let source_id = match self.quirks.shift_uses_vy {
+                    true => vy_id,
+                    false => vx_id,
+                };
+                let source_value = Self::register(regs, source_id)?;
+                let (result, shifted_out) = match instruction {
+                    ShiftRight(_, _) => (source_value >> 1, source_value & 0x01),
+                    _ => (source_value << 1, (source_value & 0x80) >> 7),
+                };
+                *Self::register_mut(regs, vx_id)? = result;
+                regs.VF = shifted_out;
+            }
+            SkipNotEqual(vx_id, vy_id) => {
+                skip = Self::register(regs, vx_id)? != Self::register(regs, vy_id)?;
+            }
+            LoadIndex(address) => regs.VI = address,
+            JumpOffset(address) => {
+                let offset_id = match self.quirks.jump_uses_vx {
+                    true => (address >> 8) as u8,
+                    false => 0x0,
+                };
+                next_pc = address + Self::register(regs, offset_id)? as u16;
+            }
+            Random(vx_id, mask) => *Self::register_mut(regs, vx_id)? = self.rng.next_u8() & mask,
+            Draw(vx_id, vy_id, rows) => {
+                let vx = Self::register(regs, vx_id)?;
+                let vy = Self::register(regs, vy_id)?;
+                regs.VF = self.draw_sprite(regs.VI, rows, vx.into(), vy.into())?;
+            }
+            SkipKeyPressed(vx_id) => skip = self.keypad.is_pressed(Self::register(regs, vx_id)?),
+            SkipKeyNotPressed(vx_id) => {
+                skip = !self.keypad.is_pressed(Self::register(regs, vx_id)?);
+            }
+            LoadIndexLong => regs.VI = self.fetch(regs.PC.wrapping_add(2))?,
+            SelectPlanes(planes) => self.display.select_planes(planes),
+            LoadAudio => {
+                let pattern = self
+                    .memory_bus
+                    .read(regs.VI.into(), 16)
+                    .map_err(Chip8CpuError::MemoryAccess)?;
+                regs.PATTERN = Some(pattern.try_into().unwrap());
+            }
+            LoadDelay(vx_id) => *Self::register_mut(regs, vx_id)? = regs.DT,
+            WaitKey(vx_id) => {
+                // Re-execute this instruction until a key has been pressed and released.
+                match self.keypad.wait_for_key() {
+                    Some(key) => *Self::register_mut(regs, vx_id)? = key,
+                    None => next_pc = regs.PC,
                 }
-                0x5 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    let borrow = vy_value > *vx;
-                    let result = *vx as u16 - vy_value as u16;
-                    *vx = result as u8;
-                    if borrow {
-                        regs.VF = 0x01;
-                    } else {
-                        regs.VF = 0x00;
-                    }
-                    regs.PC += 2;
-                    desc = format!("subtract V{} - V{} with borrow", vx_id, vy_id);
+            }
+            SetDelay(vx_id) => regs.DT = Self::register(regs, vx_id)?,
+            SetSound(vx_id) => regs.ST = Self::register(regs, vx_id)?,
+            AddIndex(vx_id) => {
+                let vx = Self::register(regs, vx_id)?;
+                regs.VI = regs.VI.wrapping_add(vx as u16);
+            }
+            LoadFont(vx_id) => {
+                let digit = Self::register(regs, vx_id)? & 0x0F;
+                regs.VI = SMALL_FONT_ADDRESS + (digit as u16 * SMALL_FONT_SPRITE_LENGTH);
+            }
+            LoadBigFont(vx_id) => {
+                let digit = Self::register(regs, vx_id)? & 0x0F;
+                regs.VI = BIG_FONT_ADDRESS + (digit as u16 * BIG_FONT_SPRITE_LENGTH);
+            }
+            StoreBcd(vx_id) => {
+                let vx = Self::register(regs, vx_id)?;
+                let bcd = [vx / 100, (vx / 10) % 10, vx % 10];
+                self.memory_bus
+                    .write(regs.VI.into(), &bcd)
+                    .map_err(Chip8CpuError::MemoryAccess)?;
+            }
+            SetPitch(vx_id) => regs.PITCH = Self::register(regs, vx_id)?,
+            StoreRegisters(vx_id) => {
+                let values = (0..=vx_id)
+                    .map(|idx| Self::register(regs, idx))
+                    .collect::<Result<Vec<u8>>>()?;
+                self.memory_bus
+                    .write(regs.VI.into(), &values)
+                    .map_err(Chip8CpuError::MemoryAccess)?;
+                self.increment_index(regs, vx_id);
+            }
+            LoadRegisters(vx_id) => {
+                let values = self
+                    .memory_bus
+                    .read(regs.VI.into(), vx_id as usize + 1)
+                    .map_err(Chip8CpuError::MemoryAccess)?;
+                for (idx, value) in values.into_iter().enumerate() {
+                    *Self::register_mut(regs, idx as u8)? = value;
                 }
-                0x6 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    let lsb = vy_value & 0x01;
-                    *vx = vy_value >> 1;
-                    regs.VF = lsb;
-                    regs.PC += 2;
-                    desc = format!("store V{} >> 1 in V{} with lsb in VF", vy_id, vx_id);
+                self.increment_index(regs, vx_id);
+            }
+            StoreFlags(vx_id) => {
+                for idx in 0..=vx_id {
+                    regs.RPL[idx as usize] = Self::register(regs, idx)?;
                 }
-                0x7 => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    let borrow = *vx > vy_value;
-                    let result = vy_value as u16 - *vx as u16;
-                    *vx = result as u8;
-                    if borrow {
-                        regs.VF = 0x01;
-                    } else {
-                        regs.VF = 0x00;
-                    }
-                    regs.PC += 2;
-                    desc = format!("subtract V{} - V{} with borrow", vy_id, vx_id);
+            }
+            LoadFlags(vx_id) => {
+                for idx in 0..=vx_id {
+                    *Self::register_mut(regs, idx)? = regs.RPL[idx as usize];
                 }
-                0xE => {
-                    let vy = regs.get_register_ref(vy_id);
-                    let vy_value = *vy;
-                    let vx = regs.get_register_mut(vx_id);
-                    let msb = (vy_value & 0x80) >> 8;
-                    *vx = vy_value << 1;
-                    regs.VF = msb;
-                    regs.PC += 2;
-                    desc = format!("store V{} << 1 in V{} with msb in VF", vy_id, vx_id);
+            }
+        }
+
+        if skip {
+            next_pc = next_pc.wrapping_add(self.instruction_length(next_pc)?);
+        }
+        regs.PC = next_pc;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kaiseki_core::{AddressableBus, AddressableComponent, OscillatorBus, RAM};
+
+    use super::{
+        Chip8CPU, Chip8CpuError, Chip8IndexIncrement, Chip8Keypad, Chip8Quirks, Chip8Variant,
+        PlanarDisplay, BIG_FONT_ADDRESS, SMALL_FONT_ADDRESS,
+    };
+    use crate::instruction::DecodeError;
+    use crate::stack::Chip8StackError;
+
+    fn setup(program: &[u8]) -> (Chip8CPU, AddressableBus, Chip8Keypad) {
+        setup_variant(program, Chip8Variant::Chip8, Chip8Quirks::default())
+    }
+
+    fn setup_with_quirks(
+        program: &[u8],
+        quirks: Chip8Quirks,
+    ) -> (Chip8CPU, AddressableBus, Chip8Keypad) {
+        setup_variant(program, Chip8Variant::Chip8, quirks)
+    }
+
+    fn setup_schip(program: &[u8]) -> (Chip8CPU, AddressableBus, Chip8Keypad) {
+        setup_variant(program, Chip8Variant::SuperChip, Chip8Quirks::schip())
+    }
+
+    fn setup_xo(program: &[u8]) -> (Chip8CPU, AddressableBus, Chip8Keypad) {
+        setup_variant(program, Chip8Variant::XoChip, Chip8Quirks::modern())
+    }
+
+    fn setup_variant(
+        program: &[u8],
+        variant: Chip8Variant,
+        quirks: Chip8Quirks,
+    ) -> (Chip8CPU, AddressableBus, Chip8Keypad) {
+        let clock_bus = OscillatorBus::new("test clock bus");
+        let memory_bus = AddressableBus::new("test memory bus");
+        let ram = RAM::<0x10000>::new("test RAM");
+        let (max_width, max_height) = variant.max_resolution();
+        let display = PlanarDisplay::new(variant.num_planes(), 64, 32, max_width, max_height);
+        let display_start = variant.memory_size();
+        memory_bus.map(0x0000..=display_start - 1, ram).unwrap();
+        memory_bus
+            .map(
+                display_start..=display_start + display.max_size() - 1,
+                display.clone(),
+            )
+            .unwrap();
+        memory_bus.write(0x200, program).unwrap();
+        let keypad = Chip8Keypad::new();
+        let cpu = Chip8CPU::new(
+            &clock_bus,
+            &memory_bus,
+            &display,
+            &keypad,
+            variant,
+            quirks,
+            0x200,
+        );
+        (cpu, memory_bus, keypad)
+    }
+
+    #[tokio::test]
+    async fn logic_resets_vf_quirk_works() {
+        for (quirks, expected_vf) in [
+            (Chip8Quirks::cosmac_vip(), 0x00),
+            (Chip8Quirks::schip(), 0x07),
+        ] {
+            let (cpu, _, _) = setup_with_quirks(&[0x81, 0x21, 0x81, 0x22, 0x81, 0x23], quirks);
+            for cycle in 0..3 {
+                cpu.regs.write().await.VF = 0x07;
+                cpu.execute_cycle(cycle).await.unwrap();
+                assert_eq!(cpu.regs.read().await.VF, expected_vf);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn shift_uses_vy_quirk_works() {
+        for (quirks, expected_v1) in [
+            (Chip8Quirks::cosmac_vip(), 0x02),
+            (Chip8Quirks::chip48(), 0x40),
+        ] {
+            let (cpu, _, _) = setup_with_quirks(&[0x81, 0x26], quirks);
+            {
+                let mut regs = cpu.regs.write().await;
+                regs.V1 = 0x81;
+                regs.V2 = 0x05;
+            }
+            cpu.execute_cycle(0).await.unwrap();
+
+            let regs = cpu.regs.read().await;
+            assert_eq!(regs.V1, expected_v1);
+            assert_eq!(regs.VF, 0x01);
+        }
+    }
+
+    #[tokio::test]
+    async fn shift_left_sets_vf_to_msb() {
+        let (cpu, _, _) = setup_with_quirks(&[0x81, 0x1E, 0x81, 0x1E], Chip8Quirks::schip());
+        cpu.regs.write().await.V1 = 0xC0;
+        cpu.execute_cycle(0).await.unwrap();
+        {
+            let regs = cpu.regs.read().await;
+            assert_eq!((regs.V1, regs.VF), (0x80, 0x01));
+        }
+
+        cpu.regs.write().await.V1 = 0x40;
+        cpu.execute_cycle(1).await.unwrap();
+        let regs = cpu.regs.read().await;
+        assert_eq!((regs.V1, regs.VF), (0x80, 0x00));
+    }
+
+    #[tokio::test]
+    async fn jump_uses_vx_quirk_works() {
+        for (quirks, expected_pc) in [
+            (Chip8Quirks::cosmac_vip(), 0x0311),
+            (Chip8Quirks::schip(), 0x0322),
+        ] {
+            let (cpu, _, _) = setup_with_quirks(&[0xB3, 0x00], quirks);
+            {
+                let mut regs = cpu.regs.write().await;
+                regs.V0 = 0x11;
+                regs.V3 = 0x22;
+            }
+            cpu.execute_cycle(0).await.unwrap();
+            assert_eq!(cpu.regs.read().await.PC, expected_pc);
+        }
+    }
+
+    #[tokio::test]
+    async fn index_increment_quirk_works() {
+        let increments = [
+            (Chip8IndexIncrement::Unchanged, 0x0300),
+            (Chip8IndexIncrement::ByX, 0x0303),
+            (Chip8IndexIncrement::ByXPlusOne, 0x0304),
+        ];
+        for (index_increment, expected_vi) in increments {
+            let quirks = Chip8Quirks {
+                index_increment,
+                ..Chip8Quirks::default()
+            };
+            let (cpu, _, _) = setup_with_quirks(&[0xF3, 0x55, 0xF3, 0x65], quirks);
+            for cycle in 0..2 {
+                cpu.regs.write().await.VI = 0x0300;
+                cpu.execute_cycle(cycle).await.unwrap();
+                assert_eq!(cpu.regs.read().await.VI, expected_vi);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn index_increment_wraps_at_end_of_address_space() {
+        let (cpu, _, _) = setup_variant(&[0xF0, 0x55], Chip8Variant::XoChip, Chip8Quirks::modern());
+        cpu.regs.write().await.VI = 0xFFFF;
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.VI, 0x0000);
+    }
+
+    #[tokio::test]
+    async fn sprites_wrap_quirk_works() {
+        // Draw a 2-row, 8-pixel-wide sprite at (60, 31), so it overhangs the right and bottom
+        // edges of the display.
+        let program = [0xD0, 0x12, 0xFF, 0xFF];
+        for (quirks, wraps) in [
+            (Chip8Quirks::cosmac_vip(), false),
+            (Chip8Quirks::modern(), true),
+        ] {
+            let (cpu, memory_bus, _) = setup_with_quirks(&program, quirks);
+            {
+                let mut regs = cpu.regs.write().await;
+                regs.V0 = 60;
+                regs.V1 = 31;
+                regs.VI = 0x0202;
+            }
+            cpu.execute_cycle(0).await.unwrap();
+
+            let display = memory_bus.read(0x1000, 0x100).unwrap();
+            assert_eq!(display[31 * 8 + 7], 0x0F);
+            match wraps {
+                true => {
+                    assert_eq!(display[31 * 8], 0xF0);
+                    assert_eq!(display[7], 0x0F);
+                    assert_eq!(display[0], 0xF0);
                 }
-                _ => panic!("invalid 0x8XYN opcode"),
-            },
-            0x9000..=0x9FFF => match embedded_nybble {
-                0x0 => {
-                    let vx = regs.get_register_ref(vx_id);
-                    let vy = regs.get_register_ref(vy_id);
-                    if *vx != *vy {
-                        regs.PC += 4;
-                    } else {
-                        regs.PC += 2;
-                    }
-                    desc = format!("skip next instruction if V{} != V{}", vx_id, vy_id);
+                false => {
+                    assert_eq!(display[31 * 8], 0x00);
+                    assert_eq!(display[7], 0x00);
+                    assert_eq!(display[0], 0x00);
                 }
-                _ => panic!("invalid 0x9XY0 opcode"),
-            },
-            0xA000..=0xAFFF => {
-                regs.VI = embedded_address;
-                regs.PC += 2;
-                desc = format!("store 0x{:04X} in VI", embedded_address);
-            }
-            0xB000..=0xBFFF => {
-                regs.PC = embedded_address + regs.V0 as u16;
-                desc = format!("jump to address 0x{:04X} + V0", embedded_address);
-            }
-            0xC000..=0xCFFF => {
-                let vx = regs.get_register_mut(vx_id);
-                let random = rand::random::<u8>();
-                *vx = random & embedded_byte;
-                regs.PC += 2;
-                desc = format!(
-                    "set V{} to a random number with mask 0x{:02X}",
-                    vx_id, embedded_byte
-                );
-            }
-            0xD000..=0xDFFF => {
-                let vx = *regs.get_register_ref(vx_id);
-                let vy = *regs.get_register_ref(vy_id);
-                regs.VF = match self.draw_sprite(regs.VI, embedded_nybble, vx.into(), vy.into()) {
-                    true => 1,
-                    false => 0,
-                };
-                regs.PC += 2;
-                desc = format!(
-                    "draw {}-byte sprite in memory at 0x{:04X} to (V{}: {}, V{}: {})",
-                    embedded_nybble, regs.VI, vx_id, vx, vy_id, vy
-                );
             }
-            _ => panic!("invalid opcode: 0x{:04X}", opcode),
+        }
+    }
+
+    #[tokio::test]
+    async fn sprite_origin_always_wraps() {
+        let (cpu, memory_bus, _) = setup(&[0xD0, 0x11, 0x80]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V0 = 64 + 1;
+            regs.V1 = 32 + 2;
+            regs.VI = 0x0202;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+
+        let display = memory_bus.read(0x1000, 0x100).unwrap();
+        assert_eq!(display[2 * 8], 0x40);
+    }
+
+    #[tokio::test]
+    async fn arithmetic_wraps_and_sets_vf() {
+        // ADD V1, 0xFF; ADD V1, V2; SUB V3, V4; SUBN V5, V6
+        let (cpu, _, _) = setup(&[0x71, 0xFF, 0x81, 0x24, 0x83, 0x45, 0x85, 0x67]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V1 = 0x02;
+            regs.V2 = 0xFF;
+            regs.V3 = 0x10;
+            regs.V4 = 0x20;
+            regs.V5 = 0x20;
+            regs.V6 = 0x10;
+            regs.VF = 0x07;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+        {
+            let regs = cpu.regs.read().await;
+            assert_eq!(regs.V1, 0x01);
+            assert_eq!(regs.VF, 0x07);
+        }
+
+        cpu.execute_cycle(1).await.unwrap();
+        {
+            let regs = cpu.regs.read().await;
+            assert_eq!(regs.V1, 0x00);
+            assert_eq!(regs.VF, 0x01);
+        }
+
+        // VF is 0 when subtracting borrows.
+        cpu.execute_cycle(2).await.unwrap();
+        {
+            let regs = cpu.regs.read().await;
+            assert_eq!(regs.V3, 0xF0);
+            assert_eq!(regs.VF, 0x00);
         }
 
-        tracing::debug!(
-            "cycle {} | PC: 0x{:04X} | 0x{:04X} {}",
-            cycle_number,
-            regs.PC,
-            opcode,
-            desc,
+        cpu.execute_cycle(3).await.unwrap();
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.V5, 0xF0);
+        assert_eq!(regs.VF, 0x00);
+        assert_eq!(regs.PC, 0x208);
+    }
+
+    #[tokio::test]
+    async fn invalid_opcodes_are_errors() {
+        let (cpu, _, _) = setup(&[0x51, 0x21]);
+        assert_eq!(
+            cpu.execute_cycle(0).await,
+            Err(Chip8CpuError::InvalidInstruction(
+                DecodeError::InvalidOpcode(0x5121)
+            ))
         );
+        assert_eq!(cpu.regs.read().await.PC, 0x200);
 
-        Ok(())
+        // Opcodes from later variants aren't executed either.
+        let (cpu, _, _) = setup(&[0xF1, 0x30]);
+        assert_eq!(
+            cpu.execute_cycle(0).await,
+            Err(Chip8CpuError::InvalidInstruction(
+                DecodeError::UnsupportedOpcode(0xF130, Chip8Variant::Chip8)
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn call_and_return_work() {
+        let (cpu, _, _) = setup(&[0x22, 0x04, 0x00, 0x00, 0x00, 0xEE]);
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x204);
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x202);
+
+        // Returning with nothing on the stack is an error, and leaves PC alone.
+        cpu.regs.write().await.PC = 0x204;
+        assert_eq!(
+            cpu.execute_cycle(2).await,
+            Err(Chip8CpuError::Stack(Chip8StackError::Underflow))
+        );
+        assert_eq!(cpu.regs.read().await.PC, 0x204);
+    }
+
+    #[tokio::test]
+    async fn stack_overflow_is_an_error() {
+        let (cpu, _, _) = setup(&[0x22, 0x00]);
+        for cycle in 0..16 {
+            cpu.execute_cycle(cycle).await.unwrap();
+        }
+        assert_eq!(
+            cpu.execute_cycle(16).await,
+            Err(Chip8CpuError::Stack(Chip8StackError::Overflow))
+        );
+        assert_eq!(cpu.regs.read().await.PC, 0x200);
+    }
+
+    #[tokio::test]
+    async fn ex9e_works() {
+        let (cpu, _, keypad) = setup(&[0xE3, 0x9E, 0x00, 0x00, 0xE3, 0x9E]);
+        cpu.regs.write().await.V3 = 0x7;
+        keypad.key_down(0x7);
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x204);
+
+        keypad.key_up(0x7);
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x206);
+    }
+
+    #[tokio::test]
+    async fn exa1_works() {
+        let (cpu, _, keypad) = setup(&[0xE3, 0xA1, 0x00, 0x00, 0xE3, 0xA1]);
+        cpu.regs.write().await.V3 = 0x7;
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x204);
+
+        keypad.key_down(0x7);
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x206);
+    }
+
+    #[tokio::test]
+    async fn fx07_works() {
+        let (cpu, _, _) = setup(&[0xF3, 0x07]);
+        cpu.regs.write().await.DT = 0x2A;
+        cpu.execute_cycle(0).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.V3, 0x2A);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn fx0a_works() {
+        let (cpu, _, keypad) = setup(&[0xF3, 0x0A]);
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x200);
+
+        keypad.key_down(0xC);
+        cpu.execute_cycle(2).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x200);
+
+        keypad.key_up(0xC);
+        cpu.execute_cycle(3).await.unwrap();
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.V3, 0xC);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn fx15_works() {
+        let (cpu, _, _) = setup(&[0xF5, 0x15]);
+        cpu.regs.write().await.V5 = 0x3C;
+        cpu.execute_cycle(0).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.DT, 0x3C);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn fx18_works() {
+        let (cpu, _, _) = setup(&[0xFA, 0x18]);
+        cpu.regs.write().await.VA = 0x10;
+        cpu.execute_cycle(0).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.ST, 0x10);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn fx1e_works() {
+        let (cpu, _, _) = setup(&[0xF1, 0x1E]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V1 = 0x20;
+            regs.VI = 0x0300;
+            regs.VF = 0x07;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.VI, 0x0320);
+        assert_eq!(regs.VF, 0x07);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn fx29_works() {
+        let (cpu, _, _) = setup(&[0xF2, 0x29, 0xF2, 0x29]);
+        cpu.regs.write().await.V2 = 0x0A;
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.VI, SMALL_FONT_ADDRESS + 50);
+
+        // Only the low nybble of VX selects the digit.
+        cpu.regs.write().await.V2 = 0xF3;
+        cpu.execute_cycle(1).await.unwrap();
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.VI, SMALL_FONT_ADDRESS + 15);
+        assert_eq!(regs.PC, 0x204);
+    }
+
+    #[tokio::test]
+    async fn fx33_works() {
+        let (cpu, memory_bus, _) = setup(&[0xF4, 0x33]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V4 = 254;
+            regs.VI = 0x0300;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+
+        assert_eq!(memory_bus.read(0x0300, 3).unwrap(), vec![2, 5, 4]);
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.VI, 0x0300);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn fx55_works() {
+        let (cpu, memory_bus, _) = setup(&[0xF3, 0x55]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V0 = 0x11;
+            regs.V1 = 0x22;
+            regs.V2 = 0x33;
+            regs.V3 = 0x44;
+            regs.V4 = 0x55;
+            regs.VI = 0x0300;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+
+        assert_eq!(
+            memory_bus.read(0x0300, 5).unwrap(),
+            vec![0x11, 0x22, 0x33, 0x44, 0x00]
+        );
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.VI, 0x0304);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn fx65_works() {
+        let (cpu, memory_bus, _) = setup(&[0xF2, 0x65]);
+        memory_bus.write(0x0300, &[0xAA, 0xBB, 0xCC, 0xDD]).unwrap();
+        cpu.regs.write().await.VI = 0x0300;
+        cpu.execute_cycle(0).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.V0, 0xAA);
+        assert_eq!(regs.V1, 0xBB);
+        assert_eq!(regs.V2, 0xCC);
+        assert_eq!(regs.V3, 0x00);
+        assert_eq!(regs.VI, 0x0303);
+        assert_eq!(regs.PC, 0x202);
+    }
+
+    #[tokio::test]
+    async fn schip_resolution_switching_works() {
+        let (cpu, _, _) = setup_schip(&[0x00, 0xFF, 0x00, 0xFE]);
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.display.resolution(), (128, 64));
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.display.resolution(), (64, 32));
+        assert_eq!(cpu.regs.read().await.PC, 0x204);
+    }
+
+    #[tokio::test]
+    async fn schip_scrolling_works() {
+        let (cpu, memory_bus, _) = setup_schip(&[0x00, 0xC3, 0x00, 0xFB, 0x00, 0xFC, 0x00, 0xFC]);
+        cpu.display.draw_sprite(8, 0, &[0x80], 1, false);
+
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(memory_bus.read(0x1000 + 3 * 8 + 1, 1).unwrap(), vec![0x80]);
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(memory_bus.read(0x1000 + 3 * 8 + 1, 1).unwrap(), vec![0x08]);
+        cpu.execute_cycle(2).await.unwrap();
+        cpu.execute_cycle(3).await.unwrap();
+        assert_eq!(memory_bus.read(0x1000 + 3 * 8, 1).unwrap(), vec![0x08]);
+    }
+
+    #[tokio::test]
+    async fn schip_exit_halts() {
+        let (cpu, _, _) = setup_schip(&[0x00, 0xFD]);
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x200);
+    }
+
+    #[tokio::test]
+    async fn schip_dxy0_draws_16x16_sprite() {
+        let mut program = vec![0x00, 0xFF, 0xD0, 0x10];
+        program.extend((0..16).flat_map(|_| [0xFF, 0xFF]));
+        let (cpu, memory_bus, _) = setup_schip(&program);
+        cpu.regs.write().await.VI = 0x0204;
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+
+        let display = memory_bus.read(0x1000, 1024).unwrap();
+        for row in 0..64 {
+            let expected: &[u8] = match row {
+                0..=15 => &[0xFF, 0xFF, 0x00],
+                _ => &[0x00, 0x00, 0x00],
+            };
+            assert_eq!(&display[row * 16..row * 16 + 3], expected, "row {}", row);
+        }
+        assert_eq!(cpu.regs.read().await.VF, 0x00);
+    }
+
+    #[tokio::test]
+    async fn schip_dxy0_draws_8x16_sprite_in_low_resolution() {
+        let mut program = vec![0xD0, 0x10];
+        program.extend((0..16).flat_map(|_| [0xFF, 0xAA]));
+        let (cpu, memory_bus, _) = setup_schip(&program);
+        cpu.regs.write().await.VI = 0x0202;
+        cpu.execute_cycle(0).await.unwrap();
+
+        // Each row is a single byte, so the sprite's data alternates 0xFF and 0xAA rows.
+        let display = memory_bus.read(0x1000, 256).unwrap();
+        for row in 0..32 {
+            let expected: &[u8] = match row {
+                0..=15 if row % 2 == 0 => &[0xFF, 0x00],
+                0..=15 => &[0xAA, 0x00],
+                _ => &[0x00, 0x00],
+            };
+            assert_eq!(&display[row * 8..row * 8 + 2], expected, "row {}", row);
+        }
+    }
+
+    #[tokio::test]
+    async fn schip_hires_vf_counts_collided_and_clipped_rows() {
+        // Draw a 4-row sprite at the bottom, then again one row lower.
+        let (cpu, _, _) = setup_schip(&[
+            0x00, 0xFF, 0xD0, 0x14, 0xD0, 0x24, 0x12, 0x08, 0xFF, 0xFF, 0xFF, 0xFF,
+        ]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.VI = 0x0208;
+            regs.V1 = 60;
+            regs.V2 = 61;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.VF, 0);
+
+        // Rows 0 - 2 land on lit rows 61 - 63, and row 3 is clipped.
+        cpu.execute_cycle(2).await.unwrap();
+        assert_eq!(cpu.regs.read().await.VF, 4);
+    }
+
+    #[tokio::test]
+    async fn schip_lores_vf_is_a_flag() {
+        let (cpu, _, _) = setup_schip(&[0xD0, 0x14, 0xD0, 0x24, 0xFF, 0xFF, 0xFF, 0xFF]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.VI = 0x0204;
+            regs.V1 = 28;
+            regs.V2 = 29;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.VF, 1);
+    }
+
+    #[tokio::test]
+    async fn schip_fx30_works() {
+        let (cpu, _, _) = setup_schip(&[0xF2, 0x30]);
+        cpu.regs.write().await.V2 = 0x7;
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.VI, BIG_FONT_ADDRESS + 70);
+    }
+
+    #[tokio::test]
+    async fn schip_fx75_fx85_work() {
+        let (cpu, _, _) = setup_schip(&[0xF2, 0x75, 0xF3, 0x85]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V0 = 0x10;
+            regs.V1 = 0x20;
+            regs.V2 = 0x30;
+            regs.V3 = 0x40;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+        {
+            let mut regs = cpu.regs.write().await;
+            assert_eq!(&regs.RPL[0..4], &[0x10, 0x20, 0x30, 0x00]);
+            regs.V0 = 0x00;
+            regs.V1 = 0x00;
+            regs.V2 = 0x00;
+        }
+        cpu.execute_cycle(1).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        assert_eq!(
+            (regs.V0, regs.V1, regs.V2, regs.V3),
+            (0x10, 0x20, 0x30, 0x00)
+        );
+    }
+
+    #[tokio::test]
+    async fn schip_opcodes_are_ignored_on_chip8() {
+        // On Chip-8, 00FF is a call to a machine language subroutine instead.
+        let (cpu, _, _) = setup(&[0x00, 0xFF]);
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.display.resolution(), (64, 32));
+        assert_eq!(cpu.regs.read().await.PC, 0x00FF);
+    }
+
+    #[tokio::test]
+    async fn xo_long_load_works() {
+        let (cpu, _, _) = setup_xo(&[0xF0, 0x00, 0xAB, 0xCD]);
+        cpu.execute_cycle(0).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        assert_eq!(regs.VI, 0xABCD);
+        assert_eq!(regs.PC, 0x204);
+    }
+
+    #[tokio::test]
+    async fn xo_skip_steps_over_long_load() {
+        let (cpu, _, _) = setup_xo(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x30, 0x00]);
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x206);
+
+        // Anything else is still skipped as a 2-byte instruction.
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x20A);
+    }
+
+    #[tokio::test]
+    async fn xo_memory_above_4k_is_addressable() {
+        let (cpu, memory_bus, _) = setup_xo(&[0xF0, 0x00, 0xE0, 0x00, 0xF1, 0x55]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V0 = 0x12;
+            regs.V1 = 0x34;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(memory_bus.read(0xE000, 2).unwrap(), vec![0x12, 0x34]);
+    }
+
+    #[tokio::test]
+    async fn xo_5xy2_5xy3_work() {
+        let (cpu, memory_bus, _) = setup_xo(&[0x51, 0x32, 0x53, 0x12, 0x54, 0x63]);
+        {
+            let mut regs = cpu.regs.write().await;
+            regs.V1 = 0x11;
+            regs.V2 = 0x22;
+            regs.V3 = 0x33;
+            regs.VI = 0x0300;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(memory_bus.read(0x0300, 3).unwrap(), vec![0x11, 0x22, 0x33]);
+
+        // With X > Y, the registers are stored in reverse order.
+        cpu.regs.write().await.VI = 0x0310;
+        cpu.execute_cycle(1).await.unwrap();
+        assert_eq!(memory_bus.read(0x0310, 3).unwrap(), vec![0x33, 0x22, 0x11]);
+
+        cpu.regs.write().await.VI = 0x0300;
+        cpu.execute_cycle(2).await.unwrap();
+        let regs = cpu.regs.read().await;
+        assert_eq!((regs.V4, regs.V5, regs.V6), (0x11, 0x22, 0x33));
+        assert_eq!(regs.VI, 0x0300);
+        assert_eq!(regs.PC, 0x206);
+    }
+
+    #[tokio::test]
+    async fn xo_plane_selection_works() {
+        // Select both planes and draw a 1-row sprite, which takes one byte per plane.
+        let (cpu, memory_bus, _) = setup_xo(&[0xF3, 0x01, 0xD0, 0x01, 0xF0, 0x0F]);
+        cpu.regs.write().await.VI = 0x0204;
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+
+        let plane_size = cpu.display.plane_size();
+        assert_eq!(memory_bus.read(0x10000, 1).unwrap(), vec![0xF0]);
+        assert_eq!(
+            memory_bus.read(0x10000 + plane_size, 1).unwrap(),
+            vec![0x0F]
+        );
+    }
+
+    #[tokio::test]
+    async fn xo_00dn_scrolls_up() {
+        let (cpu, memory_bus, _) = setup_xo(&[0x00, 0xD2]);
+        cpu.display.draw_sprite(0, 3, &[0x80], 1, false);
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(memory_bus.read(0x10000 + 8, 1).unwrap(), vec![0x80]);
+    }
+
+    #[tokio::test]
+    async fn xo_audio_instructions_work() {
+        let mut program = vec![0xF0, 0x02, 0xF4, 0x3A];
+        program.extend(0..16);
+        let (cpu, _, _) = setup_xo(&program);
+        {
+            let mut regs = cpu.regs.write().await;
+            assert_eq!(regs.PATTERN, None);
+            regs.VI = 0x0204;
+            regs.V4 = 0x70;
+        }
+        cpu.execute_cycle(0).await.unwrap();
+        cpu.execute_cycle(1).await.unwrap();
+
+        let regs = cpu.regs.read().await;
+        let expected: Vec<u8> = (0..16).collect();
+        assert_eq!(regs.PATTERN.unwrap().to_vec(), expected);
+        assert_eq!(regs.PITCH, 0x70);
+    }
+
+    #[tokio::test]
+    async fn xo_opcodes_are_ignored_on_schip() {
+        // On SUPER-CHIP, 00DN is a call to a machine language subroutine instead.
+        let (cpu, _, _) = setup_schip(&[0x00, 0xD1]);
+        cpu.execute_cycle(0).await.unwrap();
+        assert_eq!(cpu.regs.read().await.PC, 0x00D1);
     }
 }
@@ -0,0 +1,206 @@
+//! Turns Chip-8 program bytes into structured instructions and textual listings.
+//!
+//! Listings are valid assembler source: each line holds an instruction, or a `db` directive
+//! for bytes that don't decode, followed by a comment with its address and bytes. Jump and
+//! call targets that start a line get a label named after their address, such as `L0204`.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::instruction::Chip8Instruction;
+use crate::machine::Chip8Variant;
+
+/// An instruction, or bytes that don't decode as one, at an address in a program.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Chip8Line {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// `None` if `bytes` are data.
+    pub instruction: Option<Chip8Instruction>,
+}
+
+impl Chip8Line {
+    /// Decodes the instruction at the start of `bytes`, which lives at `address`. Bytes that
+    /// don't decode for `variant` become a line of data, two bytes long where possible to
+    /// keep later instructions aligned.
+    pub fn decode(bytes: &[u8], address: u16, variant: Chip8Variant) -> Self {
+        let instruction = match bytes {
+            [high, low, ..] => {
+                Chip8Instruction::decode_for(u16::from_be_bytes([*high, *low]), variant)
+                    .ok()
+                    .filter(|instruction| instruction.length() as usize <= bytes.len())
+            }
+            _ => None,
+        };
+        let length = instruction.map_or(2, |instruction| instruction.length() as usize);
+        Self {
+            address,
+            bytes: bytes[..length.min(bytes.len())].to_vec(),
+            instruction,
+        }
+    }
+
+    /// Formats the line's instruction or data, naming its jump or call target `target` if
+    /// given.
+    fn text(&self, target: Option<&str>) -> String {
+        match (self.instruction, target) {
+            (Some(Chip8Instruction::LoadIndexLong), _) => {
+                let address = u16::from_be_bytes([self.bytes[2], self.bytes[3]]);
+                format!("{} 0x{:04X}", Chip8Instruction::LoadIndexLong, address)
+            }
+            (Some(instruction), Some(target)) => instruction.format_with_target(target),
+            (Some(instruction), None) => instruction.to_string(),
+            (None, _) => {
+                let bytes: Vec<String> = self
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("0x{:02X}", byte))
+                    .collect();
+                format!("db {}", bytes.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Chip8Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text(None))
+    }
+}
+
+/// A whole program's worth of lines, with labels for jump and call targets.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Chip8Disassembly {
+    lines: Vec<Chip8Line>,
+    labels: BTreeSet<u16>,
+}
+
+impl Chip8Disassembly {
+    pub fn lines(&self) -> &[Chip8Line] {
+        &self.lines
+    }
+
+    /// Addresses that are both the target of a jump or call and the start of a line.
+    pub fn labels(&self) -> &BTreeSet<u16> {
+        &self.labels
+    }
+
+    pub fn label_name(address: u16) -> String {
+        format!("L{:04X}", address)
+    }
+}
+
+impl fmt::Display for Chip8Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            if self.labels.contains(&line.address) {
+                writeln!(f, "{}:", Self::label_name(line.address))?;
+            }
+            let target = line
+                .instruction
+                .and_then(|instruction| instruction.target())
+                .filter(|target| self.labels.contains(target))
+                .map(Chip8Disassembly::label_name);
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            writeln!(
+                f,
+                "    {:<24}; {:04X}: {}",
+                line.text(target.as_deref()),
+                line.address,
+                bytes.join(" ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Disassembles `program` as loaded at `origin`, decoding instructions as `variant` would
+/// execute them. Every byte is decoded in order, so sprites and other data in a program show
+/// up as whatever instructions they happen to encode. Bytes that would be loaded past the end
+/// of the 16-bit address space are left out.
+pub fn disassemble(program: &[u8], origin: u16, variant: Chip8Variant) -> Chip8Disassembly {
+    let program = &program[..program.len().min(0x10000 - origin as usize)];
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < program.len() {
+        let address = (origin as usize + offset) as u16;
+        let line = Chip8Line::decode(&program[offset..], address, variant);
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+
+    let starts: BTreeSet<u16> = lines.iter().map(|line| line.address).collect();
+    let labels = lines
+        .iter()
+        .filter_map(|line| {
+            line.instruction
+                .and_then(|instruction| instruction.target())
+        })
+        .filter(|target| starts.contains(target))
+        .collect();
+    Chip8Disassembly { lines, labels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disassemble, Chip8Line};
+    use crate::instruction::Chip8Instruction;
+    use crate::machine::Chip8Variant;
+
+    #[test]
+    fn disassemble_stops_at_end_of_address_space() {
+        let program = vec![0x00; 0x10000];
+        let disassembly = disassemble(&program, 0x200, Chip8Variant::XoChip);
+        let last = disassembly.lines.last().unwrap();
+        assert_eq!(last.address, 0xFFFE);
+        assert_eq!(disassembly.lines.len(), 0xFE00 / 2);
+    }
+
+    #[test]
+    fn disassemble_labels_targets() {
+        let program = [
+            0x6A, 0x05, // LD VA, 0x05
+            0x22, 0x08, // CALL L0208
+            0x12, 0x04, // JP L0204
+            0x13, 0x00, // JP 0x300, outside the program
+            0x7A, 0xFF, // ADD VA, 0xFF
+            0x00, 0xEE, // RET
+            0x5A, 0xB1, // not an instruction
+            0x42, // trailing byte
+        ];
+        let listing = disassemble(&program, 0x200, Chip8Variant::Chip8).to_string();
+        let expected = "    LD VA, 0x05             ; 0200: 6A 05
+    CALL L0208              ; 0202: 22 08
+L0204:
+    JP L0204                ; 0204: 12 04
+    JP 0x300                ; 0206: 13 00
+L0208:
+    ADD VA, 0xFF            ; 0208: 7A FF
+    RET                     ; 020A: 00 EE
+    db 0x5A, 0xB1           ; 020C: 5A B1
+    db 0x42                 ; 020E: 42
+";
+        assert_eq!(listing, expected);
+    }
+
+    #[test]
+    fn disassemble_decodes_for_variant() {
+        let program = [0x00, 0xFF, 0xF0, 0x00, 0x12, 0x34];
+        let disassembly = disassemble(&program, 0x200, Chip8Variant::XoChip);
+        let lines: Vec<String> = disassembly.lines().iter().map(|l| l.to_string()).collect();
+        assert_eq!(lines, vec!["HIGH", "LD I, LONG 0x1234"]);
+
+        let disassembly = disassemble(&program, 0x200, Chip8Variant::Chip8);
+        let lines: Vec<String> = disassembly.lines().iter().map(|l| l.to_string()).collect();
+        assert_eq!(lines, vec!["SYS 0x0FF", "db 0xF0, 0x00", "JP 0x234"]);
+
+        // A long load without its address is data.
+        let line = Chip8Line::decode(&[0xF0, 0x00, 0x12], 0x200, Chip8Variant::XoChip);
+        assert_eq!(line.instruction, None);
+        assert_eq!(line.bytes, vec![0xF0, 0x00]);
+        assert_eq!(
+            Chip8Line::decode(&[0x00, 0xE0], 0x200, Chip8Variant::Chip8).instruction,
+            Some(Chip8Instruction::Clear)
+        );
+    }
+}
@@ -1,35 +1,122 @@
 use std::sync::{Arc, Mutex};
 
-use kaiseki_core::{AddressableBus, AddressableComponent, Component, ComponentId, Result};
+use async_trait::async_trait;
 
+use kaiseki_core::snapshot::{self, SnapshotError, StateReader, StateWriter, StatefulComponent};
+use kaiseki_core::{
+    AddressableComponent, AddressableComponentError, Component, ComponentId, ResettableComponent,
+    Result,
+};
+
+/// Each plane is a one-bit-per-pixel bitmap, most-significant bit leftmost, in rows of
+/// `width / 8` bytes at the current resolution. Planes are stored back-to-back, each taking
+/// `plane_size` bytes, so a pixel's color index has bit N set if it is lit in plane N.
 #[derive(Clone, Debug)]
-pub struct MonochromeDisplayState<const N: usize> {
-    #[allow(dead_code)]
-    memory_bus: AddressableBus,
-    #[allow(dead_code)]
+pub struct PlanarDisplayState {
     width: usize,
-    #[allow(dead_code)]
     height: usize,
-    pixels: [u8; N],
+    /// Resolution the display was created at, which a reset returns it to.
+    initial_width: usize,
+    initial_height: usize,
+    max_width: usize,
+    max_height: usize,
+    num_planes: usize,
+    plane_size: usize,
+    selected_planes: u8,
+    pixels: Vec<u8>,
+}
+
+impl PlanarDisplayState {
+    fn pixel_index(&self, plane: usize, x: usize, y: usize) -> (usize, u8) {
+        let byte = plane * self.plane_size + (y * self.width + x) / 8;
+        (byte, 0x01 << (7 - (x % 8)))
+    }
+
+    fn get_pixel(&self, plane: usize, x: usize, y: usize) -> bool {
+        let (byte, bitmask) = self.pixel_index(plane, x, y);
+        self.pixels[byte] & bitmask != 0
+    }
+
+    fn set_pixel(&mut self, plane: usize, x: usize, y: usize, value: bool) {
+        let (byte, bitmask) = self.pixel_index(plane, x, y);
+        match value {
+            true => self.pixels[byte] |= bitmask,
+            false => self.pixels[byte] &= !bitmask,
+        }
+    }
+
+    fn selected(&self) -> Vec<usize> {
+        (0..self.num_planes)
+            .filter(|plane| self.selected_planes & (0x01 << plane) != 0)
+            .collect()
+    }
+
+    fn clear_plane(&mut self, plane: usize) {
+        let start = plane * self.plane_size;
+        self.pixels[start..start + self.plane_size].fill(0);
+    }
+
+    /// Moves every pixel of the selected planes by the given offset, filling vacated pixels
+    /// with zeroes.
+    fn shift(&mut self, dx: isize, dy: isize) {
+        for plane in self.selected() {
+            let original = self.clone();
+            self.clear_plane(plane);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let (new_x, new_y) = (x as isize + dx, y as isize + dy);
+                    if (0..self.width as isize).contains(&new_x)
+                        && (0..self.height as isize).contains(&new_y)
+                    {
+                        let value = original.get_pixel(plane, x, y);
+                        self.set_pixel(plane, new_x as usize, new_y as usize, value);
+                    }
+                }
+            }
+        }
+    }
 }
 
+/// What drawing a sprite with [`PlanarDisplay::draw_sprite`] ran into.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DrawnSprite {
+    /// Rows of the sprite that turned off a lit pixel in any plane.
+    pub collided_rows: usize,
+    /// Rows of the sprite clipped off the bottom of the display.
+    pub clipped_rows: usize,
+}
+
+impl DrawnSprite {
+    /// Whether any lit pixel was turned off.
+    pub fn collided(&self) -> bool {
+        self.collided_rows > 0
+    }
+}
+
+/// A bitmapped display made of one or more planes, of which any combination can be selected
+/// for drawing, clearing and scrolling. Only the first plane is selected initially.
 #[derive(Clone, Debug)]
-pub struct MonochromeDisplay<const N: usize> {
+pub struct PlanarDisplay {
     id: ComponentId,
-    state: Arc<Mutex<MonochromeDisplayState<N>>>,
+    state: Arc<Mutex<PlanarDisplayState>>,
 }
 
-impl<const N: usize> Component for MonochromeDisplay<N> {
+impl Component for PlanarDisplay {
     fn id(&self) -> &ComponentId {
         &self.id
     }
 }
 
-impl<const N: usize> AddressableComponent for MonochromeDisplay<N> {
+impl AddressableComponent for PlanarDisplay {
     fn read(&self, address: usize, length: usize) -> Result<Vec<u8>> {
         tracing::trace!("reading {} bytes from 0x{:08X}", length, address);
         let state = self.state.lock().unwrap();
-        let slice = &state.pixels[address..address + length];
+        let slice = state
+            .pixels
+            .get(address..address.saturating_add(length))
+            .ok_or_else(|| {
+                AddressableComponentError::ComponentReadFailed(self.id.clone(), address, length)
+            })?;
         Ok(Vec::from(slice))
     }
 
@@ -38,31 +125,370 @@ impl<const N: usize> AddressableComponent for MonochromeDisplay<N> {
             "writing 0x{:X} bytes to 0x{:04X} - 0x{:04X}",
             data.len(),
             address,
-            address + data.len()
+            address.saturating_add(data.len())
         );
         let mut state = self.state.lock().unwrap();
-        state.pixels[address..address + data.len()].clone_from_slice(data);
+        state
+            .pixels
+            .get_mut(address..address.saturating_add(data.len()))
+            .ok_or_else(|| {
+                AddressableComponentError::ComponentWriteFailed(
+                    self.id.clone(),
+                    address,
+                    data.len(),
+                )
+            })?
+            .clone_from_slice(data);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StatefulComponent for PlanarDisplay {
+    async fn save_state(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut writer = StateWriter::new();
+        writer.write_usize(state.width);
+        writer.write_usize(state.height);
+        writer.write_u8(state.selected_planes);
+        writer.write_bytes(&state.pixels);
+        writer.finish()
+    }
+
+    async fn load_state(&self, data: &[u8]) -> snapshot::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let mut reader = StateReader::new(data);
+        let width = reader.read_usize()?;
+        let height = reader.read_usize()?;
+        if width > state.max_width || height > state.max_height {
+            return Err(SnapshotError::InvalidState(format!(
+                "resolution {}x{} exceeds the maximum of {}x{}",
+                width, height, state.max_width, state.max_height
+            )));
+        }
+        if width == 0 || height == 0 || !width.is_multiple_of(8) {
+            return Err(SnapshotError::InvalidState(format!(
+                "resolution {}x{} is empty or not a whole number of bytes wide",
+                width, height
+            )));
+        }
+        let selected_planes = reader.read_u8()?;
+        let pixels = reader.read_bytes_exact(state.pixels.len())?;
+        reader.finish()?;
+
+        state.width = width;
+        state.height = height;
+        state.selected_planes = selected_planes;
+        state.pixels.copy_from_slice(pixels);
         Ok(())
     }
 }
 
-impl<const N: usize> MonochromeDisplay<N> {
-    pub fn new(memory_bus: &AddressableBus, width: usize, height: usize) -> Self {
-        if width * height != N {
+#[async_trait]
+impl ResettableComponent for PlanarDisplay {
+    async fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.width = state.initial_width;
+        state.height = state.initial_height;
+        state.selected_planes = 0x01;
+        state.pixels.fill(0);
+    }
+}
+
+impl PlanarDisplay {
+    /// Creates a display of `num_planes` planes at `width` x `height`, which can later be
+    /// switched to any resolution up to `max_width` x `max_height`.
+    pub fn new(
+        num_planes: usize,
+        width: usize,
+        height: usize,
+        max_width: usize,
+        max_height: usize,
+    ) -> Self {
+        if width > max_width || height > max_height || !max_width.is_multiple_of(8) {
             panic!(
-                "width * height of MonochromeDisplay<{}> must equal {}",
-                N, N
+                "PlanarDisplay of {}x{} must fit in a maximum of {}x{}, with a maximum width divisible by 8",
+                width, height, max_width, max_height
             );
         }
+        assert!((1..=8).contains(&num_planes));
 
+        let plane_size = max_width * max_height / 8;
         Self {
-            id: ComponentId::new("Monochrome Display"),
-            state: Arc::new(Mutex::new(MonochromeDisplayState {
-                memory_bus: memory_bus.clone(),
+            id: ComponentId::new("Planar Display"),
+            state: Arc::new(Mutex::new(PlanarDisplayState {
                 width,
                 height,
-                pixels: [0; N],
+                initial_width: width,
+                initial_height: height,
+                max_width,
+                max_height,
+                num_planes,
+                plane_size,
+                selected_planes: 0x01,
+                pixels: vec![0; num_planes * plane_size],
             })),
         }
     }
+
+    pub fn num_planes(&self) -> usize {
+        self.state.lock().unwrap().num_planes
+    }
+
+    /// Number of bytes each plane takes up, enough to hold it at the maximum resolution.
+    pub fn plane_size(&self) -> usize {
+        self.state.lock().unwrap().plane_size
+    }
+
+    /// Number of bytes needed to hold every plane at the maximum resolution.
+    pub fn max_size(&self) -> usize {
+        self.state.lock().unwrap().pixels.len()
+    }
+
+    pub fn resolution(&self) -> (usize, usize) {
+        let state = self.state.lock().unwrap();
+        (state.width, state.height)
+    }
+
+    /// Switches to a new resolution, clearing every plane.
+    pub fn set_resolution(&self, width: usize, height: usize) {
+        let mut state = self.state.lock().unwrap();
+        assert!(width <= state.max_width && height <= state.max_height);
+        tracing::debug!("switching resolution to {}x{}", width, height);
+        state.width = width;
+        state.height = height;
+        state.pixels.fill(0);
+    }
+
+    /// Selects the planes affected by later drawing, clearing and scrolling, as a bitmask
+    /// where bit N selects plane N.
+    pub fn select_planes(&self, mask: u8) {
+        let mut state = self.state.lock().unwrap();
+        state.selected_planes = mask & ((0x01u16 << state.num_planes) - 1) as u8;
+    }
+
+    /// Number of planes currently selected.
+    pub fn selected_plane_count(&self) -> usize {
+        self.state.lock().unwrap().selected().len()
+    }
+
+    /// Clears the selected planes.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        for plane in state.selected() {
+            state.clear_plane(plane);
+        }
+    }
+
+    /// XORs a sprite onto the selected planes with its top-left corner at (`x`, `y`),
+    /// reporting which of its rows turned off a lit pixel or fell off the display. Each row
+    /// of the sprite is `row_bytes` bytes wide, and `sprite` holds an equal-length sprite for
+    /// each selected plane in turn.
+    ///
+    /// The sprite's origin always wraps around the display; pixels past the edges of the
+    /// display wrap if `wrap` is set, and are clipped otherwise.
+    pub fn draw_sprite(
+        &self,
+        x: usize,
+        y: usize,
+        sprite: &[u8],
+        row_bytes: usize,
+        wrap: bool,
+    ) -> DrawnSprite {
+        let mut state = self.state.lock().unwrap();
+        let (width, height) = (state.width, state.height);
+        let (x, y) = (x % width, y % height);
+        let planes = state.selected();
+        let mut drawn = DrawnSprite::default();
+        if planes.is_empty() || sprite.is_empty() {
+            return drawn;
+        }
+
+        let plane_sprite_len = sprite.len() / planes.len();
+        let rows = plane_sprite_len.div_ceil(row_bytes);
+        let mut collided_rows = vec![false; rows];
+        for (plane, plane_sprite) in planes.into_iter().zip(sprite.chunks(plane_sprite_len)) {
+            for (sprite_row, row) in plane_sprite.chunks(row_bytes).enumerate() {
+                let display_y = match (y + sprite_row, wrap) {
+                    (row, true) => row % height,
+                    (row, false) if row < height => row,
+                    _ => {
+                        drawn.clipped_rows = rows - sprite_row;
+                        break;
+                    }
+                };
+                for sprite_col in 0..row.len() * 8 {
+                    let display_x = match (x + sprite_col, wrap) {
+                        (col, true) => col % width,
+                        (col, false) if col < width => col,
+                        _ => break,
+                    };
+                    let sprite_bit = (row[sprite_col / 8] >> (7 - (sprite_col % 8))) & 0x01;
+                    if sprite_bit == 0 {
+                        continue;
+                    }
+                    let display_bit = state.get_pixel(plane, display_x, display_y);
+                    collided_rows[sprite_row] |= display_bit;
+                    state.set_pixel(plane, display_x, display_y, !display_bit);
+                }
+            }
+        }
+        drawn.collided_rows = collided_rows
+            .into_iter()
+            .filter(|collided| *collided)
+            .count();
+        drawn
+    }
+
+    pub fn scroll_down(&self, rows: usize) {
+        self.state.lock().unwrap().shift(0, rows as isize);
+    }
+
+    pub fn scroll_up(&self, rows: usize) {
+        self.state.lock().unwrap().shift(0, -(rows as isize));
+    }
+
+    pub fn scroll_left(&self, columns: usize) {
+        self.state.lock().unwrap().shift(-(columns as isize), 0);
+    }
+
+    pub fn scroll_right(&self, columns: usize) {
+        self.state.lock().unwrap().shift(columns as isize, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kaiseki_core::snapshot::{SnapshotError, StateWriter, StatefulComponent};
+    use kaiseki_core::AddressableComponent;
+
+    use super::{DrawnSprite, PlanarDisplay};
+
+    #[test]
+    fn draw_sprite_works() {
+        let display = PlanarDisplay::new(1, 64, 32, 64, 32);
+        assert!(!display.draw_sprite(8, 1, &[0xC3], 1, false).collided());
+        let pixels = display.read(0, 256).unwrap();
+        assert_eq!(pixels[8 + 1], 0xC3);
+
+        // Drawing the same sprite again erases it and reports a collision.
+        assert!(display.draw_sprite(8, 1, &[0xC3], 1, false).collided());
+        assert!(display.read(0, 256).unwrap().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn draw_sprite_counts_collided_and_clipped_rows() {
+        let display = PlanarDisplay::new(1, 64, 32, 64, 32);
+        display.draw_sprite(0, 28, &[0x80, 0x00, 0x80, 0x80], 1, false);
+        let drawn = display.draw_sprite(0, 29, &[0x80; 5], 1, false);
+        assert_eq!(
+            drawn,
+            DrawnSprite {
+                collided_rows: 2,
+                clipped_rows: 2
+            }
+        );
+        assert!(drawn.collided());
+
+        // Wrapped rows aren't clipped.
+        let drawn = display.draw_sprite(0, 29, &[0x80; 5], 1, true);
+        assert_eq!(drawn.clipped_rows, 0);
+    }
+
+    #[tokio::test]
+    async fn load_state_rejects_bad_resolutions() {
+        let display = PlanarDisplay::new(1, 64, 32, 128, 64);
+        for (width, height) in [(0, 32), (64, 0), (60, 32), (136, 64)] {
+            let mut writer = StateWriter::new();
+            writer.write_usize(width);
+            writer.write_usize(height);
+            writer.write_u8(0x01);
+            writer.write_bytes(&[0; 1024]);
+            assert!(matches!(
+                display.load_state(&writer.finish()).await,
+                Err(SnapshotError::InvalidState(_))
+            ));
+        }
+        assert_eq!(display.resolution(), (64, 32));
+    }
+
+    #[test]
+    fn out_of_range_accesses_fail() {
+        let display = PlanarDisplay::new(1, 64, 32, 64, 32);
+        assert!(display.read(0xFF, 2).is_err());
+        assert!(display.write(0x100, &[0x01]).is_err());
+        assert!(display.read(0x01, usize::MAX).is_err());
+        assert!(display.read(0, 256).unwrap().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn draw_sprite_supports_wide_sprites() {
+        let display = PlanarDisplay::new(1, 64, 32, 64, 32);
+        display.draw_sprite(4, 0, &[0xFF, 0x01, 0x80, 0x00], 2, false);
+        let pixels = display.read(0, 16).unwrap();
+        assert_eq!(&pixels[0..3], &[0x0F, 0xF0, 0x10]);
+        assert_eq!(&pixels[8..11], &[0x08, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn set_resolution_changes_row_layout() {
+        let display = PlanarDisplay::new(1, 64, 32, 128, 64);
+        assert_eq!(display.max_size(), 1024);
+        display.draw_sprite(0, 0, &[0xFF], 1, false);
+
+        display.set_resolution(128, 64);
+        assert_eq!(display.resolution(), (128, 64));
+        assert!(display.read(0, 1024).unwrap().iter().all(|b| *b == 0));
+
+        // Rows are now 16 bytes wide.
+        display.draw_sprite(120, 1, &[0xFF], 1, false);
+        assert_eq!(display.read(16 + 15, 1).unwrap(), vec![0xFF]);
+    }
+
+    #[test]
+    fn scrolling_works() {
+        let display = PlanarDisplay::new(1, 64, 32, 64, 32);
+        display.draw_sprite(0, 0, &[0x80], 1, false);
+
+        display.scroll_down(2);
+        assert_eq!(display.read(2 * 8, 1).unwrap(), vec![0x80]);
+
+        display.scroll_right(4);
+        assert_eq!(display.read(2 * 8, 1).unwrap(), vec![0x08]);
+
+        display.scroll_up(1);
+        assert_eq!(display.read(8, 1).unwrap(), vec![0x08]);
+
+        display.scroll_left(4);
+        display.scroll_left(1);
+        assert!(display.read(0, 256).unwrap().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn plane_selection_works() {
+        let display = PlanarDisplay::new(2, 64, 32, 64, 32);
+        assert_eq!(display.num_planes(), 2);
+        assert_eq!(display.plane_size(), 256);
+        assert_eq!(display.selected_plane_count(), 1);
+
+        // With both planes selected, the sprite data holds one sprite per plane.
+        display.select_planes(0x03);
+        assert_eq!(display.selected_plane_count(), 2);
+        display.draw_sprite(0, 0, &[0xF0, 0x0F], 1, false);
+        assert_eq!(display.read(0, 1).unwrap(), vec![0xF0]);
+        assert_eq!(display.read(256, 1).unwrap(), vec![0x0F]);
+
+        // Clearing and drawing only touch the selected plane.
+        display.select_planes(0x02);
+        assert!(display.draw_sprite(0, 0, &[0x01], 1, false).collided());
+        assert_eq!(display.read(256, 1).unwrap(), vec![0x0E]);
+        display.clear();
+        assert_eq!(display.read(0, 1).unwrap(), vec![0xF0]);
+        assert_eq!(display.read(256, 1).unwrap(), vec![0x00]);
+
+        // Selecting no planes makes drawing a no-op.
+        display.select_planes(0x00);
+        assert!(!display.draw_sprite(0, 0, &[0xFF], 1, false).collided());
+        assert_eq!(display.read(0, 1).unwrap(), vec![0xF0]);
+    }
 }
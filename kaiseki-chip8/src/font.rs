@@ -0,0 +1,145 @@
+//! Built-in hexadecimal digit sprites stored in the interpreter ROM.
+//!
+//! The interpreter ROM lays the fonts out as:
+//!
+//! | Address           | Contents                                        |
+//! |-------------------|-------------------------------------------------|
+//! | `0x0000 - 0x004F` | unused                                          |
+//! | `0x0050 - 0x009F` | small font: digits `0` - `F`, 5 bytes per digit |
+//! | `0x00A0 - 0x0103` | big font: digits `0` - `9`, 10 bytes per digit  |
+//!
+//! FX29 points VI at a small font digit.
+
+/// Address of the small font's `0` digit in the interpreter ROM.
+pub const SMALL_FONT_ADDRESS: u16 = 0x0050;
+/// Length in bytes of each small font digit sprite.
+pub const SMALL_FONT_SPRITE_LENGTH: u16 = 5;
+/// Address of the big font's `0` digit in the interpreter ROM.
+pub const BIG_FONT_ADDRESS: u16 = 0x00A0;
+/// Length in bytes of each big font digit sprite.
+pub const BIG_FONT_SPRITE_LENGTH: u16 = 10;
+
+const COSMAC_VIP_SMALL_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const CHIP48_SMALL_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+const SCHIP_BIG_FONT: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+
+/// Glyph set used for the built-in digit sprites.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Chip8FontSet {
+    /// Glyphs from the original COSMAC VIP interpreter.
+    CosmacVip,
+    /// Glyphs from CHIP-48 and SUPER-CHIP, used by most modern interpreters.
+    #[default]
+    Chip48,
+}
+
+impl Chip8FontSet {
+    pub fn small_font(&self) -> &'static [u8] {
+        match self {
+            Chip8FontSet::CosmacVip => &COSMAC_VIP_SMALL_FONT,
+            Chip8FontSet::Chip48 => &CHIP48_SMALL_FONT,
+        }
+    }
+
+    pub fn big_font(&self) -> &'static [u8] {
+        &SCHIP_BIG_FONT
+    }
+
+    /// Builds the contents of the interpreter ROM, with both fonts at their documented
+    /// addresses.
+    pub fn interpreter_rom(&self) -> Vec<u8> {
+        let small_start = SMALL_FONT_ADDRESS as usize;
+        let small_end = small_start + self.small_font().len();
+        let big_start = BIG_FONT_ADDRESS as usize;
+        let big_end = big_start + self.big_font().len();
+
+        let mut contents = vec![0; big_end];
+        contents[small_start..small_end].copy_from_slice(self.small_font());
+        contents[big_start..big_end].copy_from_slice(self.big_font());
+        contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Chip8FontSet, BIG_FONT_ADDRESS, BIG_FONT_SPRITE_LENGTH, SMALL_FONT_ADDRESS,
+        SMALL_FONT_SPRITE_LENGTH,
+    };
+
+    #[test]
+    fn interpreter_rom_layout_works() {
+        for font_set in [Chip8FontSet::CosmacVip, Chip8FontSet::Chip48] {
+            let rom = font_set.interpreter_rom();
+            assert!(rom.len() <= 0x200, "fonts must fit in the interpreter ROM");
+
+            let small_start = SMALL_FONT_ADDRESS as usize;
+            let small_len = 16 * SMALL_FONT_SPRITE_LENGTH as usize;
+            assert_eq!(
+                &rom[small_start..small_start + small_len],
+                font_set.small_font()
+            );
+
+            let big_start = BIG_FONT_ADDRESS as usize;
+            let big_len = 10 * BIG_FONT_SPRITE_LENGTH as usize;
+            assert_eq!(&rom[big_start..big_start + big_len], font_set.big_font());
+        }
+    }
+
+    #[test]
+    fn font_sets_differ() {
+        let vip = Chip8FontSet::CosmacVip.small_font();
+        let chip48 = Chip8FontSet::Chip48.small_font();
+
+        // Both sets draw `0` the same way, but disagree on the shape of `1`.
+        assert_eq!(vip[0..5], chip48[0..5]);
+        assert_ne!(vip[5..10], chip48[5..10]);
+    }
+}
@@ -0,0 +1,595 @@
+//! A GDB remote serial protocol stub, for debugging a running Chip-8 machine with existing
+//! tooling over a local TCP port.
+//!
+//! The stub supports reading registers, reading and writing memory, single-stepping,
+//! continuing and interrupting, and breakpoints and watchpoints backed by the machine's
+//! [`Debugger`](kaiseki_core::debugger::Debugger). Registers are described to the debugger by a
+//! target description and sent big-endian, like Chip-8 memory, in the order of
+//! [`GDB_REGISTERS`]. Attaching pauses the machine, and detaching resumes it.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use kaiseki_core::debugger::{Breakpoint, DebugEvent, Watchpoint};
+use kaiseki_core::{LifecycleState, Vex, VexError};
+
+/// Names of the registers sent to the debugger, as reported by
+/// [`Machine::registers`](kaiseki_core::machine::Machine::registers), and their sizes in bits.
+pub const GDB_REGISTERS: [(&str, usize); 21] = [
+    ("V0", 8),
+    ("V1", 8),
+    ("V2", 8),
+    ("V3", 8),
+    ("V4", 8),
+    ("V5", 8),
+    ("V6", 8),
+    ("V7", 8),
+    ("V8", 8),
+    ("V9", 8),
+    ("VA", 8),
+    ("VB", 8),
+    ("VC", 8),
+    ("VD", 8),
+    ("VE", 8),
+    ("VF", 8),
+    ("VI", 16),
+    ("PC", 16),
+    ("SP", 8),
+    ("DT", 8),
+    ("ST", 8),
+];
+
+/// Byte the debugger sends outside of any packet to interrupt a running machine.
+const INTERRUPT: u8 = 0x03;
+
+/// Listens for debugger connections to a Vex running a Chip-8 machine.
+pub struct GdbServer {
+    listener: std::net::TcpListener,
+    vex: Vex,
+}
+
+impl GdbServer {
+    /// Listens on `port` on the loopback interface, or on any free port if `port` is 0.
+    pub fn bind(vex: Vex, port: u16) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, vex })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves debugger connections one at a time. Continuing only makes progress once the Vex
+    /// has been started.
+    pub async fn serve(self) -> io::Result<()> {
+        let listener = TcpListener::from_std(self.listener)?;
+        tracing::info!("GDB stub listening on {}", listener.local_addr()?);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            tracing::info!("GDB client {} attached", peer);
+            let mut session = GdbSession::new(&self.vex, stream);
+            if let Err(e) = session.run().await {
+                tracing::warn!("GDB client {} disconnected: {}", peer, e);
+            }
+            session.detach();
+            tracing::info!("GDB client {} detached", peer);
+        }
+    }
+}
+
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+struct GdbSession<'a> {
+    vex: &'a Vex,
+    stream: BufReader<TcpStream>,
+    /// Debugger IDs of the breakpoints and watchpoints set by the client, keyed by their type,
+    /// address and kind as given in `Z` packets.
+    points: HashMap<(u8, usize, usize), usize>,
+}
+
+impl<'a> GdbSession<'a> {
+    fn new(vex: &'a Vex, stream: TcpStream) -> Self {
+        Self {
+            vex,
+            stream: BufReader::new(stream),
+            points: HashMap::new(),
+        }
+    }
+
+    async fn run(&mut self) -> io::Result<()> {
+        self.vex.pause();
+        while let Some(packet) = self.read_packet().await? {
+            let command = match packet {
+                Packet::Command(command) => command,
+                // Only meaningful while continuing, where it's handled separately.
+                Packet::Interrupt => continue,
+            };
+            tracing::debug!("GDB command '{}'", command);
+
+            let reply = match command.as_str() {
+                "?" => self.stop_reply(),
+                "g" => self.read_registers().await,
+                "c" => self.continue_execution().await?,
+                "s" => self.step().await,
+                "qAttached" => String::from("1"),
+                "qC" => String::from("QC1"),
+                "qfThreadInfo" => String::from("m1"),
+                "qsThreadInfo" => String::from("l"),
+                "D" => {
+                    self.send("OK").await?;
+                    return Ok(());
+                }
+                "k" => {
+                    self.vex.stop();
+                    return Ok(());
+                }
+                _ if command.starts_with("qSupported") => {
+                    String::from("PacketSize=1000;qXfer:features:read+;swbreak+;hwbreak+")
+                }
+                _ if command.starts_with("qXfer:features:read:target.xml:") => {
+                    Self::read_target_description(&command)
+                }
+                _ if command.starts_with('H') => String::from("OK"),
+                _ if command.starts_with('p') => self.read_register(&command[1..]).await,
+                _ if command.starts_with('m') => self.read_memory(&command[1..]),
+                _ if command.starts_with('M') => self.write_memory(&command[1..]),
+                _ if command.starts_with('Z') => self.insert_point(&command[1..]),
+                _ if command.starts_with('z') => self.remove_point(&command[1..]),
+                // An empty reply tells the client the command isn't supported.
+                _ => String::new(),
+            };
+            self.send(&reply).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the client's breakpoints and watchpoints and lets the machine run on.
+    fn detach(&mut self) {
+        let debugger = self.vex.debugger();
+        for ((point_type, _, _), id) in self.points.drain() {
+            match point_type {
+                0 | 1 => debugger.remove_breakpoint(id),
+                _ => debugger.remove_watchpoint(id),
+            };
+        }
+        self.vex.resume();
+    }
+
+    /// Reads the next packet, acknowledging it, or `None` once the client disconnects.
+    async fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            let byte = match self.stream.read_u8().await {
+                Ok(byte) => byte,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            match byte {
+                INTERRUPT => return Ok(Some(Packet::Interrupt)),
+                b'$' => {}
+                // Acknowledgements of our replies, and anything else between packets.
+                _ => continue,
+            }
+
+            let mut payload = Vec::new();
+            loop {
+                match self.stream.read_u8().await? {
+                    b'#' => break,
+                    byte => payload.push(byte),
+                }
+            }
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum).await?;
+            let checksum = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+            if checksum != Some(Self::checksum(&payload)) {
+                self.stream.get_mut().write_all(b"-").await?;
+                continue;
+            }
+            self.stream.get_mut().write_all(b"+").await?;
+            return Ok(Some(Packet::Command(
+                String::from_utf8_lossy(&payload).into_owned(),
+            )));
+        }
+    }
+
+    async fn send(&mut self, reply: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", reply, Self::checksum(reply.as_bytes()));
+        self.stream.get_mut().write_all(packet.as_bytes()).await
+    }
+
+    fn checksum(payload: &[u8]) -> u8 {
+        payload
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+    }
+
+    /// Describes why the machine isn't running.
+    fn stop_reply(&self) -> String {
+        if self.vex.state() == LifecycleState::Stopped {
+            return String::from("W00");
+        }
+        if self.vex.fault().is_some() {
+            // Report faults as SIGILL; a faulted machine can't continue until restarted.
+            return String::from("S04");
+        }
+        match self.vex.debugger().halted() {
+            Some(DebugEvent::Breakpoint { .. }) => String::from("T05swbreak:;"),
+            Some(DebugEvent::Watchpoint {
+                watchpoint,
+                address,
+                ..
+            }) => {
+                let kind = match (watchpoint.on_read, watchpoint.on_write) {
+                    (true, true) => "awatch",
+                    (true, false) => "rwatch",
+                    _ => "watch",
+                };
+                format!("T05{}:{:x};", kind, address)
+            }
+            None => String::from("S05"),
+        }
+    }
+
+    async fn continue_execution(&mut self) -> io::Result<String> {
+        self.vex.resume();
+        loop {
+            tokio::select! {
+                _ = self.vex.wait_while_running() => return Ok(self.stop_reply()),
+                byte = self.stream.read_u8() => {
+                    if byte? == INTERRUPT {
+                        self.vex.pause();
+                        return Ok(String::from("S02"));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn step(&mut self) -> String {
+        match self.vex.step().await {
+            Ok(()) | Err(VexError::Fault(_)) => self.stop_reply(),
+            Err(e) => {
+                tracing::warn!("GDB step failed: {}", e);
+                String::from("E01")
+            }
+        }
+    }
+
+    fn read_target_description(command: &str) -> String {
+        let xml = Self::target_description();
+        let range = command
+            .rsplit(':')
+            .next()
+            .and_then(|range| range.split_once(','))
+            .and_then(|(offset, length)| Some((parse_hex(offset)?, parse_hex(length)?)));
+        let Some((offset, length)) = range else {
+            return String::from("E01");
+        };
+        let start = offset.min(xml.len());
+        let end = start.saturating_add(length).min(xml.len());
+        let more = match end < xml.len() {
+            true => 'm',
+            false => 'l',
+        };
+        format!("{}{}", more, &xml[start..end])
+    }
+
+    fn target_description() -> String {
+        let registers: String = GDB_REGISTERS
+            .iter()
+            .enumerate()
+            .map(|(regnum, (name, bits))| {
+                let reg_type = match *name {
+                    "PC" => String::from("code_ptr"),
+                    "VI" => String::from("data_ptr"),
+                    _ => format!("uint{}", bits),
+                };
+                format!(
+                    "<reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\" type=\"{}\"/>",
+                    name.to_lowercase(),
+                    bits,
+                    regnum,
+                    reg_type
+                )
+            })
+            .collect();
+        format!(
+            "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+             <target version=\"1.0\"><feature name=\"org.kaiseki.chip8\">{}</feature></target>",
+            registers
+        )
+    }
+
+    async fn read_registers(&self) -> String {
+        let values = self.vex.registers().await;
+        GDB_REGISTERS
+            .iter()
+            .map(|register| Self::encode_register(register, &values))
+            .collect()
+    }
+
+    async fn read_register(&self, args: &str) -> String {
+        let Some(register) = parse_hex(args).and_then(|regnum| GDB_REGISTERS.get(regnum)) else {
+            return String::from("E01");
+        };
+        Self::encode_register(register, &self.vex.registers().await)
+    }
+
+    fn encode_register((name, bits): &(&str, usize), values: &[(String, usize)]) -> String {
+        let value = values
+            .iter()
+            .find(|(value_name, _)| value_name == name)
+            .map_or(0, |(_, value)| *value);
+        let bytes = value.to_be_bytes();
+        encode_hex(&bytes[bytes.len() - bits / 8..])
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        let Some((address, length)) = parse_address_length(args) else {
+            return String::from("E01");
+        };
+        if address.checked_add(length).is_none() {
+            return String::from("E02");
+        }
+        match self.vex.read_memory(address, length) {
+            Ok(bytes) => encode_hex(&bytes),
+            Err(_) => String::from("E02"),
+        }
+    }
+
+    fn write_memory(&self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| {
+            let (address, length) = parse_address_length(range)?;
+            let data = decode_hex(data)?;
+            (data.len() == length).then_some((address, data))
+        });
+        let Some((address, data)) = parsed else {
+            return String::from("E01");
+        };
+        if address.checked_add(data.len()).is_none() {
+            return String::from("E02");
+        }
+        match self.vex.write_memory(address, &data) {
+            Ok(()) => String::from("OK"),
+            Err(_) => String::from("E02"),
+        }
+    }
+
+    fn parse_point(args: &str) -> Option<(u8, usize, usize)> {
+        let mut fields = args.split(',');
+        let point_type = fields.next()?.parse().ok()?;
+        let address = parse_hex(fields.next()?)?;
+        let kind = parse_hex(fields.next()?.split(';').next()?)?;
+        Some((point_type, address, kind))
+    }
+
+    fn insert_point(&mut self, args: &str) -> String {
+        let Some(key @ (point_type, address, kind)) = Self::parse_point(args) else {
+            return String::from("E01");
+        };
+        if self.points.contains_key(&key) {
+            return String::from("OK");
+        }
+
+        let Some(last) = address.checked_add(kind.max(1) - 1) else {
+            return String::from("E01");
+        };
+        let debugger = self.vex.debugger();
+        let range = address..=last;
+        let id = match point_type {
+            0 | 1 => debugger.add_breakpoint(Breakpoint::at(address)),
+            2 => debugger.add_watchpoint(Watchpoint::writes(range)),
+            3 => debugger.add_watchpoint(Watchpoint::reads(range)),
+            4 => debugger.add_watchpoint(Watchpoint::accesses(range)),
+            _ => return String::new(),
+        };
+        self.points.insert(key, id);
+        String::from("OK")
+    }
+
+    fn remove_point(&mut self, args: &str) -> String {
+        let Some(key @ (point_type, _, _)) = Self::parse_point(args) else {
+            return String::from("E01");
+        };
+        if let Some(id) = self.points.remove(&key) {
+            let debugger = self.vex.debugger();
+            match point_type {
+                0 | 1 => debugger.remove_breakpoint(id),
+                _ => debugger.remove_watchpoint(id),
+            };
+        }
+        String::from("OK")
+    }
+}
+
+fn parse_hex(value: &str) -> Option<usize> {
+    usize::from_str_radix(value, 16).ok()
+}
+
+fn parse_address_length(args: &str) -> Option<(usize, usize)> {
+    let (address, length) = args.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(length)?))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(value.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use kaiseki_core::audio::NullAudioSink;
+    use kaiseki_core::{LifecycleState, Vex};
+
+    use super::{GdbServer, INTERRUPT};
+    use crate::font::Chip8FontSet;
+    use crate::machine::{Chip8Machine, Chip8Variant};
+    use crate::quirks::Chip8Quirks;
+
+    /// A minimal scripted RSP client.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        async fn request(&mut self, command: &str) -> String {
+            let checksum = command
+                .bytes()
+                .fold(0u8, |sum, byte| sum.wrapping_add(byte));
+            let packet = format!("${}#{:02x}", command, checksum);
+            self.stream.write_all(packet.as_bytes()).await.unwrap();
+            assert_eq!(self.stream.read_u8().await.unwrap(), b'+');
+            self.reply().await
+        }
+
+        async fn reply(&mut self) -> String {
+            assert_eq!(self.stream.read_u8().await.unwrap(), b'$');
+            let mut payload = Vec::new();
+            loop {
+                match self.stream.read_u8().await.unwrap() {
+                    b'#' => break,
+                    byte => payload.push(byte),
+                }
+            }
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum).await.unwrap();
+            self.stream.write_all(b"+").await.unwrap();
+            String::from_utf8(payload).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn scripted_client_debugs_running_machine() {
+        // Count in V0, storing each count at 0x300.
+        let path = std::env::temp_dir().join(format!("kaiseki-gdb-{}.ch8", std::process::id()));
+        let program = [0x63, 0x01, 0x80, 0x34, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x02];
+        fs::write(&path, program).unwrap();
+        let machine = Chip8Machine::new(
+            Chip8Variant::Chip8,
+            Chip8Quirks::default(),
+            Chip8FontSet::default(),
+            NullAudioSink::new(44100),
+            0,
+        )
+        .unwrap();
+        let vex = Vex::create(machine, path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let server = GdbServer::bind(vex.clone(), 0).unwrap();
+        let address = server.local_addr().unwrap();
+        let guest = vex.clone();
+        let running = tokio::spawn(async move { guest.start().await });
+        tokio::spawn(server.serve());
+
+        let script = async {
+            let stream = TcpStream::connect(address).await.unwrap();
+            let mut client = Client { stream };
+
+            assert!(client
+                .request("qSupported:swbreak+")
+                .await
+                .contains("qXfer:features:read+"));
+            let description = client.request("qXfer:features:read:target.xml:0,fff").await;
+            assert!(description.starts_with('l'));
+            assert!(description.contains("<reg name=\"pc\" bitsize=\"16\" regnum=\"17\""));
+
+            // Attaching pauses the machine.
+            assert_eq!(client.request("?").await, "S05");
+            assert_eq!(vex.state(), LifecycleState::Paused);
+            assert_eq!(client.request("m200,a").await, "63018034a300f0551202");
+
+            assert_eq!(client.request("Z0,206,2").await, "OK");
+            assert_eq!(client.request("c").await, "T05swbreak:;");
+            let registers = client.request("g").await;
+            assert_eq!(registers.len(), 2 * (16 + 2 + 2 + 3));
+            let v0 = u8::from_str_radix(&registers[0..2], 16).unwrap();
+            assert_eq!(&registers[32..40], "03000206");
+            assert_eq!(client.request("p11").await, "0206");
+
+            // Stepping onto the store trips a write watchpoint, as does continuing to the next.
+            assert_eq!(client.request("z0,206,2").await, "OK");
+            assert_eq!(client.request("Z2,300,1").await, "OK");
+            assert_eq!(client.request("s").await, "T05watch:300;");
+            assert_eq!(client.request("p11").await, "0208");
+            assert_eq!(client.request("m300,1").await, format!("{:02x}", v0));
+            assert_eq!(client.request("c").await, "T05watch:300;");
+            assert_eq!(client.request("m300,1").await, format!("{:02x}", v0 + 1));
+            assert_eq!(client.request("z2,300,1").await, "OK");
+
+            // Memory written by the client doesn't trip watchpoints.
+            assert_eq!(client.request("s").await, "S05");
+            assert_eq!(client.request("Z4,300,1").await, "OK");
+            assert_eq!(client.request("M300,1:aa").await, "OK");
+            assert_eq!(client.request("m300,1").await, "aa");
+            assert_eq!(client.request("?").await, "S05");
+            assert_eq!(client.request("z4,300,1").await, "OK");
+
+            // Requests past the end of memory or a mapping are refused without harming the
+            // machine.
+            assert_eq!(client.request("m200,ffff").await, "E02");
+            assert_eq!(client.request("mfff,2").await, "E02");
+            assert_eq!(client.request("Mfff,2:aabb").await, "E02");
+            assert_eq!(client.request("mffffffffffffffff,2").await, "E02");
+            assert_eq!(client.request("Z2,ffffffffffffffff,2").await, "E01");
+            assert_eq!(
+                client
+                    .request("qXfer:features:read:target.xml:10,ffffffffffffffff")
+                    .await
+                    .chars()
+                    .next(),
+                Some('l')
+            );
+            assert_eq!(client.request("m200,2").await, "6301");
+
+            // Interrupting a continue pauses the machine again.
+            client.stream.write_all(b"$c#63").await.unwrap();
+            assert_eq!(client.stream.read_u8().await.unwrap(), b'+');
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.stream.write_all(&[INTERRUPT]).await.unwrap();
+            assert_eq!(client.reply().await, "S02");
+            assert_eq!(vex.state(), LifecycleState::Paused);
+
+            // Detaching lets the machine run on.
+            assert_eq!(client.request("D").await, "OK");
+            tokio::time::timeout(Duration::from_secs(1), async {
+                while vex.state() != LifecycleState::Running {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("detaching didn't resume the machine");
+            assert!(vex.debugger().breakpoints().is_empty());
+            assert!(vex.debugger().watchpoints().is_empty());
+        };
+        tokio::time::timeout(Duration::from_secs(10), script)
+            .await
+            .expect("GDB session timed out");
+
+        vex.stop();
+        running.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,466 @@
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::machine::Chip8Variant;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DecodeError {
+    #[error("invalid opcode 0x{0:04X}")]
+    InvalidOpcode(u16),
+    #[error("opcode 0x{0:04X} isn't supported by {1:?}")]
+    UnsupportedOpcode(u16, Chip8Variant),
+}
+
+pub type Result<T> = std::result::Result<T, DecodeError>;
+
+/// A decoded Chip-8, SUPER-CHIP or XO-CHIP instruction. Register operands are register
+/// numbers, so `AddByte(3, 1)` adds 1 to V3. Mnemonics follow Cowgod's Chip-8 reference,
+/// extended for SUPER-CHIP and XO-CHIP.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Chip8Instruction {
+    /// 0NNN: call the machine language subroutine at NNN.
+    Sys(u16),
+    /// 00CN: scroll the display down N rows.
+    ScrollDown(u8),
+    /// 00DN: scroll the display up N rows.
+    ScrollUp(u8),
+    /// 00E0: clear the display.
+    Clear,
+    /// 00EE: return from a subroutine.
+    Return,
+    /// 00FB: scroll the display right 4 columns.
+    ScrollRight,
+    /// 00FC: scroll the display left 4 columns.
+    ScrollLeft,
+    /// 00FD: exit the interpreter.
+    Exit,
+    /// 00FE: switch to low-resolution mode.
+    LowRes,
+    /// 00FF: switch to high-resolution mode.
+    HighRes,
+    /// 1NNN: jump to NNN.
+    Jump(u16),
+    /// 2NNN: call the subroutine at NNN.
+    Call(u16),
+    /// 3XNN: skip the next instruction if VX == NN.
+    SkipEqualByte(u8, u8),
+    /// 4XNN: skip the next instruction if VX != NN.
+    SkipNotEqualByte(u8, u8),
+    /// 5XY0: skip the next instruction if VX == VY.
+    SkipEqual(u8, u8),
+    /// 5XY2: store VX - VY in memory at VI.
+    SaveRange(u8, u8),
+    /// 5XY3: load VX - VY from memory at VI.
+    LoadRange(u8, u8),
+    /// 6XNN: store NN in VX.
+    LoadByte(u8, u8),
+    /// 7XNN: add NN to VX without carry.
+    AddByte(u8, u8),
+    /// 8XY0: store VY in VX.
+    Move(u8, u8),
+    /// 8XY1: store VX | VY in VX.
+    Or(u8, u8),
+    /// 8XY2: store VX & VY in VX.
+    And(u8, u8),
+    /// 8XY3: store VX ^ VY in VX.
+    Xor(u8, u8),
+    /// 8XY4: add VY to VX with carry.
+    Add(u8, u8),
+    /// 8XY5: subtract VY from VX with borrow.
+    Sub(u8, u8),
+    /// 8XY6: shift right by one, with the shifted-out bit in VF.
+    ShiftRight(u8, u8),
+    /// 8XY7: store VY - VX in VX with borrow.
+    SubReverse(u8, u8),
+    /// 8XYE: shift left by one, with the shifted-out bit in VF.
+    ShiftLeft(u8, u8),
+    /// 9XY0: skip the next instruction if VX != VY.
+    SkipNotEqual(u8, u8),
+    /// ANNN: store NNN in VI.
+    LoadIndex(u16),
+    /// BNNN: jump to NNN plus V0, or VX depending on quirks. Always listed as `JP V0, NNN`,
+    /// since that's how the opcode is written whichever register it adds.
+    JumpOffset(u16),
+    /// CXNN: store a random number masked with NN in VX.
+    Random(u8, u8),
+    /// DXYN: draw an N-byte sprite from memory at VI at (VX, VY).
+    Draw(u8, u8, u8),
+    /// EX9E: skip the next instruction if the key in VX is pressed.
+    SkipKeyPressed(u8),
+    /// EXA1: skip the next instruction if the key in VX isn't pressed.
+    SkipKeyNotPressed(u8),
+    /// F000 NNNN: store the address in the word following the opcode in VI.
+    LoadIndexLong,
+    /// FN01: select the display planes in bitmask N for drawing.
+    SelectPlanes(u8),
+    /// F002: load a 16-byte audio pattern from memory at VI.
+    LoadAudio,
+    /// FX07: store DT in VX.
+    LoadDelay(u8),
+    /// FX0A: wait for a key press and release, and store the key in VX.
+    WaitKey(u8),
+    /// FX15: store VX in DT.
+    SetDelay(u8),
+    /// FX18: store VX in ST.
+    SetSound(u8),
+    /// FX1E: add VX to VI.
+    AddIndex(u8),
+    /// FX29: point VI at the small font sprite for the digit in VX.
+    LoadFont(u8),
+    /// FX30: point VI at the big font sprite for the digit in VX.
+    LoadBigFont(u8),
+    /// FX33: store the BCD of VX in memory at VI.
+    StoreBcd(u8),
+    /// FX3A: store VX in the audio pitch.
+    SetPitch(u8),
+    /// FX55: store V0 - VX in memory at VI.
+    StoreRegisters(u8),
+    /// FX65: load V0 - VX from memory at VI.
+    LoadRegisters(u8),
+    /// FX75: store V0 - VX in the RPL user flags.
+    StoreFlags(u8),
+    /// FX85: load V0 - VX from the RPL user flags.
+    LoadFlags(u8),
+}
+
+impl Chip8Instruction {
+    /// Decodes an opcode as any variant's instruction. Decoding only looks at the opcode, so
+    /// it's safe to call with arbitrary input.
+    pub fn decode(opcode: u16) -> Result<Self> {
+        let address = opcode & 0x0FFF;
+        let byte = (opcode & 0x00FF) as u8;
+        let nybble = (opcode & 0x000F) as u8;
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+
+        let instruction = match opcode >> 12 {
+            0x0 => match opcode {
+                0x00C0..=0x00CF => Self::ScrollDown(nybble),
+                0x00D0..=0x00DF => Self::ScrollUp(nybble),
+                0x00E0 => Self::Clear,
+                0x00EE => Self::Return,
+                0x00FB => Self::ScrollRight,
+                0x00FC => Self::ScrollLeft,
+                0x00FD => Self::Exit,
+                0x00FE => Self::LowRes,
+                0x00FF => Self::HighRes,
+                _ => Self::Sys(address),
+            },
+            0x1 => Self::Jump(address),
+            0x2 => Self::Call(address),
+            0x3 => Self::SkipEqualByte(x, byte),
+            0x4 => Self::SkipNotEqualByte(x, byte),
+            0x5 => match nybble {
+                0x0 => Self::SkipEqual(x, y),
+                0x2 => Self::SaveRange(x, y),
+                0x3 => Self::LoadRange(x, y),
+                _ => return Err(DecodeError::InvalidOpcode(opcode)),
+            },
+            0x6 => Self::LoadByte(x, byte),
+            0x7 => Self::AddByte(x, byte),
+            0x8 => match nybble {
+                0x0 => Self::Move(x, y),
+                0x1 => Self::Or(x, y),
+                0x2 => Self::And(x, y),
+                0x3 => Self::Xor(x, y),
+                0x4 => Self::Add(x, y),
+                0x5 => Self::Sub(x, y),
+                0x6 => Self::ShiftRight(x, y),
+                0x7 => Self::SubReverse(x, y),
+                0xE => Self::ShiftLeft(x, y),
+                _ => return Err(DecodeError::InvalidOpcode(opcode)),
+            },
+            0x9 if nybble == 0x0 => Self::SkipNotEqual(x, y),
+            0xA => Self::LoadIndex(address),
+            0xB => Self::JumpOffset(address),
+            0xC => Self::Random(x, byte),
+            0xD => Self::Draw(x, y, nybble),
+            0xE => match byte {
+                0x9E => Self::SkipKeyPressed(x),
+                0xA1 => Self::SkipKeyNotPressed(x),
+                _ => return Err(DecodeError::InvalidOpcode(opcode)),
+            },
+            0xF => match byte {
+                0x00 if x == 0x0 => Self::LoadIndexLong,
+                0x01 => Self::SelectPlanes(x),
+                0x02 if x == 0x0 => Self::LoadAudio,
+                0x07 => Self::LoadDelay(x),
+                0x0A => Self::WaitKey(x),
+                0x15 => Self::SetDelay(x),
+                0x18 => Self::SetSound(x),
+                0x1E => Self::AddIndex(x),
+                0x29 => Self::LoadFont(x),
+                0x30 => Self::LoadBigFont(x),
+                0x33 => Self::StoreBcd(x),
+                0x3A => Self::SetPitch(x),
+                0x55 => Self::StoreRegisters(x),
+                0x65 => Self::LoadRegisters(x),
+                0x75 => Self::StoreFlags(x),
+                0x85 => Self::LoadFlags(x),
+                _ => return Err(DecodeError::InvalidOpcode(opcode)),
+            },
+            _ => return Err(DecodeError::InvalidOpcode(opcode)),
+        };
+        Ok(instruction)
+    }
+
+    /// Decodes an opcode as `variant` would execute it. Opcodes from 0x0000 to 0x0FFF that
+    /// `variant` doesn't support are machine language subroutine calls.
+    pub fn decode_for(opcode: u16, variant: Chip8Variant) -> Result<Self> {
+        let instruction = Self::decode(opcode)?;
+        match variant.supports(instruction.variant()) {
+            true => Ok(instruction),
+            false if opcode <= 0x0FFF => Ok(Self::Sys(opcode)),
+            false => Err(DecodeError::UnsupportedOpcode(opcode, variant)),
+        }
+    }
+
+    pub fn encode(&self) -> u16 {
+        let xy = |x: &u8, y: &u8| ((*x as u16) << 8) | ((*y as u16) << 4);
+        let xnn = |x: &u8, byte: &u8| ((*x as u16) << 8) | *byte as u16;
+        let x = |x: &u8| (*x as u16) << 8;
+        match self {
+            Self::Sys(address) => *address,
+            Self::ScrollDown(rows) => 0x00C0 | *rows as u16,
+            Self::ScrollUp(rows) => 0x00D0 | *rows as u16,
+            Self::Clear => 0x00E0,
+            Self::Return => 0x00EE,
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::Exit => 0x00FD,
+            Self::LowRes => 0x00FE,
+            Self::HighRes => 0x00FF,
+            Self::Jump(address) => 0x1000 | address,
+            Self::Call(address) => 0x2000 | address,
+            Self::SkipEqualByte(vx, byte) => 0x3000 | xnn(vx, byte),
+            Self::SkipNotEqualByte(vx, byte) => 0x4000 | xnn(vx, byte),
+            Self::SkipEqual(vx, vy) => 0x5000 | xy(vx, vy),
+            Self::SaveRange(vx, vy) => 0x5002 | xy(vx, vy),
+            Self::LoadRange(vx, vy) => 0x5003 | xy(vx, vy),
+            Self::LoadByte(vx, byte) => 0x6000 | xnn(vx, byte),
+            Self::AddByte(vx, byte) => 0x7000 | xnn(vx, byte),
+            Self::Move(vx, vy) => 0x8000 | xy(vx, vy),
+            Self::Or(vx, vy) => 0x8001 | xy(vx, vy),
+            Self::And(vx, vy) => 0x8002 | xy(vx, vy),
+            Self::Xor(vx, vy) => 0x8003 | xy(vx, vy),
+            Self::Add(vx, vy) => 0x8004 | xy(vx, vy),
+            Self::Sub(vx, vy) => 0x8005 | xy(vx, vy),
+            Self::ShiftRight(vx, vy) => 0x8006 | xy(vx, vy),
+            Self::SubReverse(vx, vy) => 0x8007 | xy(vx, vy),
+            Self::ShiftLeft(vx, vy) => 0x800E | xy(vx, vy),
+            Self::SkipNotEqual(vx, vy) => 0x9000 | xy(vx, vy),
+            Self::LoadIndex(address) => 0xA000 | address,
+            Self::JumpOffset(address) => 0xB000 | address,
+            Self::Random(vx, byte) => 0xC000 | xnn(vx, byte),
+            Self::Draw(vx, vy, rows) => 0xD000 | xy(vx, vy) | *rows as u16,
+            Self::SkipKeyPressed(vx) => 0xE09E | x(vx),
+            Self::SkipKeyNotPressed(vx) => 0xE0A1 | x(vx),
+            Self::LoadIndexLong => 0xF000,
+            Self::SelectPlanes(planes) => 0xF001 | x(planes),
+            Self::LoadAudio => 0xF002,
+            Self::LoadDelay(vx) => 0xF007 | x(vx),
+            Self::WaitKey(vx) => 0xF00A | x(vx),
+            Self::SetDelay(vx) => 0xF015 | x(vx),
+            Self::SetSound(vx) => 0xF018 | x(vx),
+            Self::AddIndex(vx) => 0xF01E | x(vx),
+            Self::LoadFont(vx) => 0xF029 | x(vx),
+            Self::LoadBigFont(vx) => 0xF030 | x(vx),
+            Self::StoreBcd(vx) => 0xF033 | x(vx),
+            Self::SetPitch(vx) => 0xF03A | x(vx),
+            Self::StoreRegisters(vx) => 0xF055 | x(vx),
+            Self::LoadRegisters(vx) => 0xF065 | x(vx),
+            Self::StoreFlags(vx) => 0xF075 | x(vx),
+            Self::LoadFlags(vx) => 0xF085 | x(vx),
+        }
+    }
+
+    /// Length in bytes, which is 4 for the F000 NNNN long load and 2 for everything else.
+    pub fn length(&self) -> u16 {
+        match self {
+            Self::LoadIndexLong => 4,
+            _ => 2,
+        }
+    }
+
+    /// The earliest variant that supports this instruction.
+    pub fn variant(&self) -> Chip8Variant {
+        match self {
+            Self::ScrollDown(_)
+            | Self::ScrollRight
+            | Self::ScrollLeft
+            | Self::Exit
+            | Self::LowRes
+            | Self::HighRes
+            | Self::LoadBigFont(_)
+            | Self::StoreFlags(_)
+            | Self::LoadFlags(_) => Chip8Variant::SuperChip,
+            Self::ScrollUp(_)
+            | Self::SaveRange(_, _)
+            | Self::LoadRange(_, _)
+            | Self::LoadIndexLong
+            | Self::SelectPlanes(_)
+            | Self::LoadAudio
+            | Self::SetPitch(_) => Chip8Variant::XoChip,
+            _ => Chip8Variant::Chip8,
+        }
+    }
+
+    /// Address this instruction jumps or calls to, if it's a jump or call.
+    pub fn target(&self) -> Option<u16> {
+        match self {
+            Self::Jump(address) | Self::Call(address) | Self::JumpOffset(address) => Some(*address),
+            _ => None,
+        }
+    }
+
+    /// Formats the instruction with its jump or call target replaced by `target`, which is
+    /// how listings refer to labels.
+    pub fn format_with_target(&self, target: &str) -> String {
+        match self {
+            Self::Jump(_) => format!("JP {}", target),
+            Self::Call(_) => format!("CALL {}", target),
+            Self::JumpOffset(_) => format!("JP V0, {}", target),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Formats the instruction as the assembler mnemonic for its opcode. Listings don't depend on
+/// quirks, so they read the same and assemble back to the same program whichever interpreter
+/// they're run on.
+impl fmt::Display for Chip8Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sys(address) => write!(f, "SYS 0x{:03X}", address),
+            Self::ScrollDown(rows) => write!(f, "SCD {}", rows),
+            Self::ScrollUp(rows) => write!(f, "SCU {}", rows),
+            Self::Clear => f.write_str("CLS"),
+            Self::Return => f.write_str("RET"),
+            Self::ScrollRight => f.write_str("SCR"),
+            Self::ScrollLeft => f.write_str("SCL"),
+            Self::Exit => f.write_str("EXIT"),
+            Self::LowRes => f.write_str("LOW"),
+            Self::HighRes => f.write_str("HIGH"),
+            Self::Jump(address) => write!(f, "JP 0x{:03X}", address),
+            Self::Call(address) => write!(f, "CALL 0x{:03X}", address),
+            Self::SkipEqualByte(vx, byte) => write!(f, "SE V{:X}, 0x{:02X}", vx, byte),
+            Self::SkipNotEqualByte(vx, byte) => write!(f, "SNE V{:X}, 0x{:02X}", vx, byte),
+            Self::SkipEqual(vx, vy) => write!(f, "SE V{:X}, V{:X}", vx, vy),
+            Self::SaveRange(vx, vy) => write!(f, "SAVE V{:X}, V{:X}", vx, vy),
+            Self::LoadRange(vx, vy) => write!(f, "LOAD V{:X}, V{:X}", vx, vy),
+            Self::LoadByte(vx, byte) => write!(f, "LD V{:X}, 0x{:02X}", vx, byte),
+            Self::AddByte(vx, byte) => write!(f, "ADD V{:X}, 0x{:02X}", vx, byte),
+            Self::Move(vx, vy) => write!(f, "LD V{:X}, V{:X}", vx, vy),
+            Self::Or(vx, vy) => write!(f, "OR V{:X}, V{:X}", vx, vy),
+            Self::And(vx, vy) => write!(f, "AND V{:X}, V{:X}", vx, vy),
+            Self::Xor(vx, vy) => write!(f, "XOR V{:X}, V{:X}", vx, vy),
+            Self::Add(vx, vy) => write!(f, "ADD V{:X}, V{:X}", vx, vy),
+            Self::Sub(vx, vy) => write!(f, "SUB V{:X}, V{:X}", vx, vy),
+            Self::ShiftRight(vx, vy) => write!(f, "SHR V{:X}, V{:X}", vx, vy),
+            Self::SubReverse(vx, vy) => write!(f, "SUBN V{:X}, V{:X}", vx, vy),
+            Self::ShiftLeft(vx, vy) => write!(f, "SHL V{:X}, V{:X}", vx, vy),
+            Self::SkipNotEqual(vx, vy) => write!(f, "SNE V{:X}, V{:X}", vx, vy),
+            Self::LoadIndex(address) => write!(f, "LD I, 0x{:03X}", address),
+            Self::JumpOffset(address) => write!(f, "JP V0, 0x{:03X}", address),
+            Self::Random(vx, byte) => write!(f, "RND V{:X}, 0x{:02X}", vx, byte),
+            Self::Draw(vx, vy, rows) => write!(f, "DRW V{:X}, V{:X}, {}", vx, vy, rows),
+            Self::SkipKeyPressed(vx) => write!(f, "SKP V{:X}", vx),
+            Self::SkipKeyNotPressed(vx) => write!(f, "SKNP V{:X}", vx),
+            Self::LoadIndexLong => f.write_str("LD I, LONG"),
+            Self::SelectPlanes(planes) => write!(f, "PLANE {}", planes),
+            Self::LoadAudio => f.write_str("AUDIO"),
+            Self::LoadDelay(vx) => write!(f, "LD V{:X}, DT", vx),
+            Self::WaitKey(vx) => write!(f, "LD V{:X}, K", vx),
+            Self::SetDelay(vx) => write!(f, "LD DT, V{:X}", vx),
+            Self::SetSound(vx) => write!(f, "LD ST, V{:X}", vx),
+            Self::AddIndex(vx) => write!(f, "ADD I, V{:X}", vx),
+            Self::LoadFont(vx) => write!(f, "LD F, V{:X}", vx),
+            Self::LoadBigFont(vx) => write!(f, "LD HF, V{:X}", vx),
+            Self::StoreBcd(vx) => write!(f, "LD B, V{:X}", vx),
+            Self::SetPitch(vx) => write!(f, "PITCH V{:X}", vx),
+            Self::StoreRegisters(vx) => write!(f, "LD [I], V{:X}", vx),
+            Self::LoadRegisters(vx) => write!(f, "LD V{:X}, [I]", vx),
+            Self::StoreFlags(vx) => write!(f, "LD R, V{:X}", vx),
+            Self::LoadFlags(vx) => write!(f, "LD V{:X}, R", vx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chip8Instruction, DecodeError};
+    use crate::machine::Chip8Variant;
+
+    #[test]
+    fn decode_and_encode_round_trip() {
+        for opcode in 0..=u16::MAX {
+            if let Ok(instruction) = Chip8Instruction::decode(opcode) {
+                assert_eq!(
+                    instruction.encode(),
+                    opcode,
+                    "{} doesn't round trip",
+                    instruction
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decode_works() {
+        let decode = |opcode| Chip8Instruction::decode(opcode).unwrap().to_string();
+        assert_eq!(decode(0x00E0), "CLS");
+        assert_eq!(decode(0x0123), "SYS 0x123");
+        assert_eq!(decode(0x2ABC), "CALL 0xABC");
+        assert_eq!(decode(0x3A12), "SE VA, 0x12");
+        assert_eq!(decode(0x8AB6), "SHR VA, VB");
+        assert_eq!(decode(0xD125), "DRW V1, V2, 5");
+        assert_eq!(decode(0xF355), "LD [I], V3");
+        assert_eq!(decode(0x5122), "SAVE V1, V2");
+        assert_eq!(
+            Chip8Instruction::decode(0x5121),
+            Err(DecodeError::InvalidOpcode(0x5121))
+        );
+        assert_eq!(
+            Chip8Instruction::decode(0x800F),
+            Err(DecodeError::InvalidOpcode(0x800F))
+        );
+        assert_eq!(
+            Chip8Instruction::decode(0xE19F),
+            Err(DecodeError::InvalidOpcode(0xE19F))
+        );
+        assert_eq!(
+            Chip8Instruction::decode(0xF100),
+            Err(DecodeError::InvalidOpcode(0xF100))
+        );
+        assert_eq!(
+            Chip8Instruction::decode(0xF0FF),
+            Err(DecodeError::InvalidOpcode(0xF0FF))
+        );
+    }
+
+    #[test]
+    fn decode_for_respects_variants() {
+        let decode = Chip8Instruction::decode_for;
+        assert_eq!(
+            decode(0x00FF, Chip8Variant::Chip8),
+            Ok(Chip8Instruction::Sys(0x0FF))
+        );
+        assert_eq!(
+            decode(0x00FF, Chip8Variant::SuperChip),
+            Ok(Chip8Instruction::HighRes)
+        );
+        assert_eq!(
+            decode(0xF000, Chip8Variant::SuperChip),
+            Err(DecodeError::UnsupportedOpcode(
+                0xF000,
+                Chip8Variant::SuperChip
+            ))
+        );
+        assert_eq!(
+            decode(0xF000, Chip8Variant::XoChip),
+            Ok(Chip8Instruction::LoadIndexLong)
+        );
+        assert_eq!(Chip8Instruction::LoadIndexLong.length(), 4);
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use kaiseki_core::movie::InputEvent;
+use kaiseki_core::snapshot::{self, StateReader, StateWriter, StatefulComponent};
+use kaiseki_core::{Component, ComponentId, ResettableComponent};
+
+/// Number of keys on the Chip-8 hexadecimal keypad.
+pub const NUM_KEYS: usize = 16;
+
+#[derive(Clone, Debug, Default)]
+struct Chip8KeypadState {
+    pressed: [bool; NUM_KEYS],
+    waiting: bool,
+    released: Option<u8>,
+    /// Host key events waiting for the next cycle, as (key, pressed).
+    queued: Vec<(u8, bool)>,
+    /// Events applied so far, if recording.
+    recording: Option<Vec<InputEvent>>,
+    /// Whether events are still being added to `recording`.
+    recording_active: bool,
+    /// Events still to apply from a movie, if one is playing.
+    playback: Option<VecDeque<InputEvent>>,
+}
+
+impl Chip8KeypadState {
+    fn set_key(&mut self, key: u8, pressed: bool) {
+        let key = key & 0x0F;
+        tracing::debug!("key 0x{:X} {}", key, if pressed { "down" } else { "up" });
+        self.pressed[key as usize] = pressed;
+        if !pressed && self.waiting {
+            self.released = Some(key);
+        }
+    }
+}
+
+/// The 16-key hexadecimal keypad, with keys `0x0` - `0xF`.
+#[derive(Clone, Debug)]
+pub struct Chip8Keypad {
+    id: ComponentId,
+    state: Arc<Mutex<Chip8KeypadState>>,
+}
+
+impl Component for Chip8Keypad {
+    fn id(&self) -> &ComponentId {
+        &self.id
+    }
+}
+
+impl Default for Chip8Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl StatefulComponent for Chip8Keypad {
+    async fn save_state(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut writer = StateWriter::new();
+        for pressed in state.pressed {
+            writer.write_bool(pressed);
+        }
+        writer.write_bool(state.waiting);
+        writer.write_bool(state.released.is_some());
+        writer.write_u8(state.released.unwrap_or_default());
+        writer.finish()
+    }
+
+    async fn load_state(&self, data: &[u8]) -> snapshot::Result<()> {
+        let mut restored = Chip8KeypadState::default();
+        let mut reader = StateReader::new(data);
+        for pressed in restored.pressed.iter_mut() {
+            *pressed = reader.read_bool()?;
+        }
+        restored.waiting = reader.read_bool()?;
+        let has_released = reader.read_bool()?;
+        let released = reader.read_u8()? & 0x0F;
+        restored.released = has_released.then_some(released);
+        reader.finish()?;
+
+        // Input being recorded or played back isn't part of the machine's state.
+        let mut state = self.state.lock().unwrap();
+        state.pressed = restored.pressed;
+        state.waiting = restored.waiting;
+        state.released = restored.released;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ResettableComponent for Chip8Keypad {
+    /// Forgets any key the CPU was waiting on and stops any movie playing. A recording ends,
+    /// keeping the events recorded before the reset. Keys held on the host stay pressed.
+    async fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.waiting = false;
+        state.released = None;
+        state.playback = None;
+        if state.recording_active {
+            tracing::warn!("reset ended the recording");
+            state.recording_active = false;
+        }
+    }
+}
+
+impl Chip8Keypad {
+    pub fn new() -> Self {
+        Self {
+            id: ComponentId::new("Chip-8 Keypad"),
+            state: Arc::new(Mutex::new(Chip8KeypadState::default())),
+        }
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        let state = self.state.lock().unwrap();
+        state.pressed[(key & 0x0F) as usize]
+    }
+
+    pub fn key_down(&self, key: u8) {
+        self.state.lock().unwrap().set_key(key, true);
+    }
+
+    pub fn key_up(&self, key: u8) {
+        self.state.lock().unwrap().set_key(key, false);
+    }
+
+    /// Queues a host key press or release to take effect before the next cycle, so that it
+    /// can be recorded against that cycle. Ignored while a movie is playing.
+    pub fn queue_key(&self, key: u8, pressed: bool) {
+        let mut state = self.state.lock().unwrap();
+        match state.playback {
+            Some(_) => tracing::debug!("ignoring key 0x{:X} during movie playback", key),
+            None => state.queued.push((key & 0x0F, pressed)),
+        }
+    }
+
+    /// Applies the key events due before `cycle` runs: the movie's if one is playing, or the
+    /// queued host events otherwise, recording them if recording.
+    pub fn apply_input(&self, cycle: usize) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(mut playback) = state.playback.take() {
+            while let Some(event) = playback
+                .front()
+                .copied()
+                .filter(|event| event.cycle <= cycle)
+            {
+                state.set_key(event.key as u8, event.pressed);
+                playback.pop_front();
+            }
+            match playback.is_empty() {
+                true => tracing::info!("movie finished at cycle {}", cycle),
+                false => state.playback = Some(playback),
+            }
+            return;
+        }
+
+        let recording_active = state.recording_active;
+        for (key, pressed) in std::mem::take(&mut state.queued) {
+            state.set_key(key, pressed);
+            if let Some(recording) = state.recording.as_mut().filter(|_| recording_active) {
+                let key = key as usize;
+                recording.push(InputEvent {
+                    cycle,
+                    key,
+                    pressed,
+                });
+            }
+        }
+    }
+
+    /// Starts recording the events applied from now on, beginning with presses of any keys
+    /// already held so that the recording doesn't depend on them.
+    pub fn start_recording(&self) {
+        let mut state = self.state.lock().unwrap();
+        let held: Vec<(u8, bool)> = (0..NUM_KEYS as u8)
+            .filter(|key| state.pressed[*key as usize])
+            .map(|key| (key, true))
+            .collect();
+        state.queued.splice(0..0, held);
+        state.recording = Some(Vec::new());
+        state.recording_active = true;
+    }
+
+    /// Stops recording, returning the events recorded if recording, or if a reset ended the
+    /// recording.
+    pub fn stop_recording(&self) -> Option<Vec<InputEvent>> {
+        let mut state = self.state.lock().unwrap();
+        state.recording_active = false;
+        state.recording.take()
+    }
+
+    /// Plays `events` back in place of host input, starting with every key released.
+    pub fn play(&self, events: &[InputEvent]) {
+        let mut state = self.state.lock().unwrap();
+        state.pressed = [false; NUM_KEYS];
+        state.queued.clear();
+        state.playback = Some(events.iter().copied().collect());
+    }
+
+    /// Polls for a key to be pressed and then released, as the COSMAC VIP does for FX0A.
+    ///
+    /// The first call starts a new wait and always returns `None`; only keys released after
+    /// that point complete the wait.
+    pub fn wait_for_key(&self) -> Option<u8> {
+        let mut state = self.state.lock().unwrap();
+        if !state.waiting {
+            state.waiting = true;
+            state.released = None;
+            return None;
+        }
+
+        let key = state.released.take();
+        if key.is_some() {
+            state.waiting = false;
+        }
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kaiseki_core::movie::InputEvent;
+
+    use super::Chip8Keypad;
+
+    #[test]
+    fn key_down_up_works() {
+        let keypad = Chip8Keypad::new();
+        assert!(!keypad.is_pressed(0xA));
+
+        keypad.key_down(0xA);
+        assert!(keypad.is_pressed(0xA));
+        assert!(!keypad.is_pressed(0xB));
+
+        keypad.key_up(0xA);
+        assert!(!keypad.is_pressed(0xA));
+    }
+
+    #[test]
+    fn wait_for_key_requires_release_after_wait_starts() {
+        let keypad = Chip8Keypad::new();
+
+        // A key released before the wait starts doesn't satisfy it.
+        keypad.key_down(0x1);
+        keypad.key_up(0x1);
+        assert_eq!(keypad.wait_for_key(), None);
+        assert_eq!(keypad.wait_for_key(), None);
+
+        // Pressing a key isn't enough; it has to be released as well.
+        keypad.key_down(0x5);
+        assert_eq!(keypad.wait_for_key(), None);
+        keypad.key_up(0x5);
+        assert_eq!(keypad.wait_for_key(), Some(0x5));
+
+        // Once satisfied, the next call starts a fresh wait.
+        assert_eq!(keypad.wait_for_key(), None);
+    }
+
+    #[test]
+    fn queued_keys_are_recorded_against_the_cycle_they_apply_before() {
+        let keypad = Chip8Keypad::new();
+        keypad.key_down(0x2);
+        keypad.start_recording();
+        keypad.queue_key(0x7, true);
+        assert!(!keypad.is_pressed(0x7));
+
+        keypad.apply_input(10);
+        assert!(keypad.is_pressed(0x7));
+        keypad.queue_key(0x7, false);
+        keypad.apply_input(11);
+        keypad.apply_input(12);
+        assert!(!keypad.is_pressed(0x7));
+
+        // Keys held when recording starts are recorded as pressed.
+        let event = |cycle, key, pressed| InputEvent {
+            cycle,
+            key,
+            pressed,
+        };
+        let events = vec![
+            event(10, 0x2, true),
+            event(10, 0x7, true),
+            event(11, 0x7, false),
+        ];
+        assert_eq!(keypad.stop_recording(), Some(events.clone()));
+        assert_eq!(keypad.stop_recording(), None);
+
+        // Playback starts with every key released and ignores host input until it ends.
+        keypad.play(&events);
+        assert!(!keypad.is_pressed(0x2));
+        keypad.queue_key(0x3, true);
+        keypad.apply_input(10);
+        assert!(keypad.is_pressed(0x2) && keypad.is_pressed(0x7));
+        keypad.apply_input(11);
+        assert!(!keypad.is_pressed(0x7));
+        keypad.queue_key(0x3, true);
+        keypad.apply_input(12);
+        assert!(keypad.is_pressed(0x3));
+    }
+}
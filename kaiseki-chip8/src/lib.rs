@@ -1,6 +1,16 @@
+pub mod assembler;
 pub mod cpu;
+pub mod disassembler;
+pub mod font;
+pub mod gdb;
+pub mod instruction;
 pub mod machine;
+pub mod quirks;
 
+mod beeper;
 mod display;
+mod keypad;
 mod registers;
+mod rng;
 mod stack;
+mod timers;
@@ -1,32 +1,119 @@
 use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
-use futures::{stream::FuturesUnordered, StreamExt};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 
-use kaiseki_core::machine::{self, Machine};
+use kaiseki_core::audio::AudioSink;
+use kaiseki_core::debugger::Debugger;
+use kaiseki_core::machine::{self, FrameObserver, Machine, ResetKind};
+use kaiseki_core::movie::Movie;
+use kaiseki_core::snapshot::{self, Snapshot, SnapshotError, StateReader, StateWriter};
 use kaiseki_core::{
-    AddressableBus, AddressableComponent, Component, ComponentId, ExecutableComponent, Oscillator,
-    OscillatorBus, RAM, ROM,
+    AddressableBus, AddressableComponent, Component, ComponentId, ExecutableComponent, Fault,
+    Lifecycle, Oscillator, OscillatorBus, ResettableComponent, RAM, ROM,
 };
 
+use crate::beeper::Chip8Beeper;
 use crate::cpu::Chip8CPU;
-use crate::display::MonochromeDisplay;
+use crate::display::PlanarDisplay;
+use crate::font::Chip8FontSet;
+use crate::keypad::{Chip8Keypad, NUM_KEYS};
+use crate::quirks::Chip8Quirks;
+use crate::rng::Chip8Rng;
+use crate::timers::{Chip8Timers, TIMER_FREQUENCY_HZ};
+
+/// Chip-8 dialects, each a superset of the ones before it.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Chip8Variant {
+    /// The original COSMAC VIP Chip-8.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, adding a 128x64 high-resolution mode, scrolling, 16x16 sprites, a big
+    /// font and RPL user flags.
+    SuperChip,
+    /// XO-CHIP, adding a 64 KiB address space, a second display plane for 4 colors, register
+    /// range save/load and programmable audio patterns.
+    XoChip,
+}
+
+/// Frequency at which the CPU executes instructions.
+pub const CPU_FREQUENCY_HZ: usize = 500;
+
+/// Address at which programs are loaded and start executing.
+pub const PROGRAM_ADDRESS: usize = 0x200;
+
+/// Colors of the pixels in a monochrome frame, indexed by pixel value.
+const MONOCHROME_PALETTE: [[u8; 3]; 2] = [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]];
+
+/// Colors of the pixels in an XO-CHIP frame, indexed by the planes each pixel is lit in.
+const XO_CHIP_PALETTE: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+
+impl Chip8Variant {
+    /// Whether this variant supports all of the instructions of `other`.
+    pub fn supports(&self, other: Chip8Variant) -> bool {
+        *self >= other
+    }
+
+    pub fn max_resolution(&self) -> (usize, usize) {
+        match self {
+            Chip8Variant::Chip8 => (64, 32),
+            Chip8Variant::SuperChip | Chip8Variant::XoChip => (128, 64),
+        }
+    }
+
+    /// Size in bytes of the address space available to programs, including the interpreter
+    /// ROM. The display is mapped just past the end of it.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Chip8Variant::Chip8 | Chip8Variant::SuperChip => 0x1000,
+            Chip8Variant::XoChip => 0x10000,
+        }
+    }
+
+    pub fn num_planes(&self) -> usize {
+        match self {
+            Chip8Variant::Chip8 | Chip8Variant::SuperChip => 1,
+            Chip8Variant::XoChip => 2,
+        }
+    }
+
+    /// Colors of the pixels in a frame from [`Machine::get_frame`], indexed by the planes each
+    /// pixel is lit in.
+    pub fn palette(&self) -> &'static [[u8; 3]] {
+        match self {
+            Chip8Variant::Chip8 | Chip8Variant::SuperChip => &MONOCHROME_PALETTE,
+            Chip8Variant::XoChip => &XO_CHIP_PALETTE,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Chip8Machine {
     id: ComponentId,
     #[allow(dead_code)]
     clock_bus: OscillatorBus,
-    #[allow(dead_code)]
     memory_bus: AddressableBus,
     cpu: Chip8CPU,
-    #[allow(dead_code)]
-    display: MonochromeDisplay<2048>,
-    #[allow(dead_code)]
+    display: PlanarDisplay,
+    keypad: Chip8Keypad,
+    rng: Chip8Rng,
     interpreter_rom: ROM<0x200>,
-    #[allow(dead_code)]
-    ram: RAM<0xE00>,
+    ram: RAM<0xFE00>,
     system_clock: Oscillator,
+    timers: Chip8Timers,
+    variant: Chip8Variant,
+    debugger: Debugger,
+    /// Number of CPU cycles executed by `run_cycles` and `run_frame`, kept only to report;
+    /// cycles are numbered and frames ended by the system clock, whichever way they run.
+    cycles_run: AtomicUsize,
+    frame_observer: Mutex<Option<Arc<dyn FrameObserver>>>,
 }
 
 impl Component for Chip8Machine {
@@ -37,83 +124,338 @@ impl Component for Chip8Machine {
 
 #[async_trait]
 impl ExecutableComponent for Chip8Machine {
-    async fn start(&self) {
+    async fn start(&self, lifecycle: &Lifecycle) -> Result<(), Fault> {
         tracing::info!("starting Chip-8 machine");
 
         let mut futures = FuturesUnordered::new();
 
-        futures.push(self.cpu.start());
-        futures.push(self.system_clock.start());
+        futures.push(
+            self.cpu
+                .run(lifecycle, |cycle| async move {
+                    // Count cycles as they run rather than once the whole batch has, so that
+                    // frames end, tick the timers and are captured where the CPU actually is.
+                    self.system_clock.advance(1);
+                    self.end_cycle(cycle).await;
+                })
+                .boxed(),
+        );
+        futures.push(self.system_clock.start(lifecycle));
 
-        while futures.next().await.is_some() {
+        // Wind the other components down after a fault, and report the first one.
+        let mut result = Ok(());
+        while let Some(component_result) = futures.next().await {
             tracing::info!("component task finished");
+            if component_result.is_err() {
+                lifecycle.fault();
+            }
+            result = result.and(component_result);
         }
+
+        tracing::info!("Chip-8 machine stopped");
+        result
     }
 }
 
+#[async_trait]
 impl Machine for Chip8Machine {
     fn get_frame(&self) -> (usize, usize, Vec<u8>) {
-        let mono_frame = self.memory_bus.read(0x1000, 0x100).unwrap();
-        let mut rgb_frame = Vec::new();
+        let (width, height) = self.display.resolution();
+        let frame_size = width * height / 8;
+        let planes: Vec<Vec<u8>> = (0..self.display.num_planes())
+            .map(|plane| {
+                // Read the display directly so the frame doesn't trip any watchpoints.
+                let address = plane * self.display.plane_size();
+                self.display.read(address, frame_size).unwrap()
+            })
+            .collect();
+        let palette = self.variant.palette();
+        let mut rgb_frame = Vec::with_capacity(width * height * 3);
 
-        for byte in mono_frame {
+        for byte_idx in 0..frame_size {
             for bit_idx in 0..=7 {
-                let pixel = (byte >> (7 - bit_idx)) & 0x01;
-                match pixel {
-                    0 => {
-                        rgb_frame.push(0x00);
-                        rgb_frame.push(0x00);
-                        rgb_frame.push(0x00);
-                    }
-                    1 => {
-                        rgb_frame.push(0xFF);
-                        rgb_frame.push(0xFF);
-                        rgb_frame.push(0xFF);
-                    }
-                    _ => {
-                        panic!(
-                            "pixel value is {}; should be single bit, must be 0 or 1!",
-                            pixel
-                        );
-                    }
-                }
+                let color = planes.iter().enumerate().fold(0, |color, (plane, bytes)| {
+                    let pixel = (bytes[byte_idx] >> (7 - bit_idx)) & 0x01;
+                    color | (pixel as usize) << plane
+                });
+                rgb_frame.extend_from_slice(&palette[color]);
             }
         }
 
-        (64, 32, rgb_frame)
+        (width, height, rgb_frame)
     }
 
     fn load(&self, file: &str) -> machine::Result<()> {
-        tracing::info!("loading Chip-8 program");
+        tracing::info!("loading Chip-8 program '{}'", file);
         let program = fs::read(file)
-            .map_err(|_| machine::MachineError::FileLoad(String::from(file), 0x200))?;
-        self.memory_bus.write(0x200, &program)?;
+            .map_err(|e| machine::MachineError::FileRead(String::from(file), e.to_string()))?;
+
+        let available = self.variant.memory_size() - PROGRAM_ADDRESS;
+        if program.len() > available {
+            return Err(machine::MachineError::FileTooLarge(
+                String::from(file),
+                program.len(),
+                available,
+            ));
+        }
+
+        self.memory_bus
+            .write(PROGRAM_ADDRESS, &program)
+            .map_err(|_| machine::MachineError::FileLoad(String::from(file), PROGRAM_ADDRESS))
+    }
+
+    fn cycles_per_frame(&self) -> usize {
+        CPU_FREQUENCY_HZ / TIMER_FREQUENCY_HZ
+    }
+
+    async fn registers(&self) -> Vec<(String, usize)> {
+        self.cpu.register_values().await
+    }
+
+    fn read_memory(&self, address: usize, length: usize) -> machine::Result<Vec<u8>> {
+        Ok(self.memory_bus.inspect(address, length)?)
+    }
+
+    fn write_memory(&self, address: usize, data: &[u8]) -> machine::Result<()> {
+        Ok(self.memory_bus.patch(address, data)?)
+    }
+
+    fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    fn cycles_run(&self) -> usize {
+        self.cycles_run.load(Ordering::SeqCst)
+    }
+
+    fn current_frame(&self) -> usize {
+        self.system_clock.current_cycle() * TIMER_FREQUENCY_HZ / CPU_FREQUENCY_HZ
+    }
+
+    fn observe_frames(&self, observer: Arc<dyn FrameObserver>) {
+        *self.frame_observer.lock().unwrap() = Some(observer);
+    }
+
+    async fn run_cycles(&self, cycles: usize) -> Result<(), Fault> {
+        for _ in 0..cycles {
+            if self.cpu.check_breakpoints().await {
+                break;
+            }
+            self.run_cycle().await?;
+        }
         Ok(())
     }
+
+    async fn run_frame(&self) -> Result<usize, Fault> {
+        let mut cycles = 0;
+        while !self.cpu.check_breakpoints().await {
+            cycles += 1;
+            if self.run_cycle().await? {
+                break;
+            }
+        }
+        Ok(cycles)
+    }
+
+    async fn reset(&self, kind: ResetKind) {
+        let _cycles = self.cpu.lock_cycles().await;
+        self.cpu.reset().await;
+        self.display.reset().await;
+        self.keypad.reset().await;
+        self.rng.reset().await;
+        self.timers.reset().await;
+        self.system_clock.reset().await;
+        // The interpreter ROM holds the fonts, so only the program's memory is cleared.
+        if kind == ResetKind::Hard {
+            self.ram.reset().await;
+        }
+        self.cycles_run.store(0, Ordering::SeqCst);
+    }
+
+    async fn snapshot(&self) -> Snapshot {
+        let _cycles = self.cpu.lock_cycles().await;
+        self.capture_components().await
+    }
+
+    async fn restore(&self, snapshot: &Snapshot) -> machine::Result<()> {
+        let mut reader = StateReader::new(snapshot.get("machine")?);
+        let variant = reader.read_u8()?;
+        let cycles_run = reader.read_usize()?;
+        reader.finish()?;
+        if variant != self.variant as u8 {
+            return Err(SnapshotError::InvalidSection(
+                String::from("machine"),
+                format!("snapshot is not of a {:?} machine", self.variant),
+            )
+            .into());
+        }
+
+        let _cycles = self.cpu.lock_cycles().await;
+        // A section can turn out to be bad after others have been restored, so put the
+        // machine back as it was rather than leave it half restored.
+        let previous = self.capture_components().await;
+        if let Err(e) = self.restore_components(snapshot).await {
+            self.restore_components(&previous)
+                .await
+                .expect("machine's own state should restore");
+            return Err(e.into());
+        }
+        self.cycles_run.store(cycles_run, Ordering::SeqCst);
+        // The beeper's waveform isn't part of the state, so start it over.
+        self.timers.reset().await;
+        Ok(())
+    }
+
+    fn press_key(&self, key: usize) {
+        match key {
+            0..NUM_KEYS => self.keypad.queue_key(key as u8, true),
+            _ => tracing::warn!("ignoring press of nonexistent key {}", key),
+        }
+    }
+
+    fn release_key(&self, key: usize) {
+        match key {
+            0..NUM_KEYS => self.keypad.queue_key(key as u8, false),
+            _ => tracing::warn!("ignoring release of nonexistent key {}", key),
+        }
+    }
+
+    fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    fn start_recording(&self) {
+        tracing::info!(
+            "recording input from cycle {}",
+            self.system_clock.current_cycle()
+        );
+        self.keypad.start_recording();
+    }
+
+    fn stop_recording(&self) -> Option<Movie> {
+        let events = self.keypad.stop_recording()?;
+        tracing::info!("recorded {} input events", events.len());
+        Some(Movie::new(self.rng.seed(), events))
+    }
+
+    fn play_movie(&self, movie: &Movie) {
+        tracing::info!("playing {} input events", movie.events().len());
+        self.keypad.play(movie.events());
+    }
 }
 
 impl Chip8Machine {
-    pub fn new() -> machine::Result<Chip8Machine> {
+    /// Executes a single CPU cycle, numbered and ended just as it would be running freely.
+    /// Returns whether the cycle ended a frame. A cycle that faults doesn't count as run.
+    async fn run_cycle(&self) -> Result<bool, Fault> {
+        let cycle = self.system_clock.current_cycle();
+        self.cpu.run_cycle(cycle).await?;
+        self.system_clock.advance(1);
+        self.cycles_run.fetch_add(1, Ordering::SeqCst);
+        Ok(self.end_cycle(cycle).await)
+    }
+
+    /// Ticks the timers if the cycle numbered `cycle`, which has just been executed, was the
+    /// last of a frame, then hands the frame observer the machine's state if it wants the
+    /// frame. Returns whether the cycle ended a frame.
+    async fn end_cycle(&self, cycle: usize) -> bool {
+        // Frames end whenever the CPU crosses into the next timer period.
+        let frame = (cycle + 1) * TIMER_FREQUENCY_HZ / CPU_FREQUENCY_HZ;
+        if frame == cycle * TIMER_FREQUENCY_HZ / CPU_FREQUENCY_HZ {
+            return false;
+        }
+        self.timers.tick().await;
+        let observer = self.frame_observer.lock().unwrap().clone();
+        if let Some(observer) = observer.filter(|observer| observer.wants_frame(frame)) {
+            observer.frame_ended(frame, self.snapshot().await);
+        }
+        true
+    }
+
+    /// Captures every component's state, without holding off the CPU.
+    async fn capture_components(&self) -> Snapshot {
+        let mut writer = StateWriter::new();
+        writer.write_u8(self.variant as u8);
+        writer.write_usize(self.cycles_run.load(Ordering::SeqCst));
+
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("machine", writer.finish());
+        snapshot.capture("cpu", &self.cpu).await;
+        snapshot
+            .capture("interpreter rom", &self.interpreter_rom)
+            .await;
+        snapshot.capture("ram", &self.ram).await;
+        snapshot.capture("display", &self.display).await;
+        snapshot.capture("keypad", &self.keypad).await;
+        snapshot.capture("rng", &self.rng).await;
+        snapshot.capture("system clock", &self.system_clock).await;
+        snapshot
+    }
+
+    /// Restores every component's state from `snapshot`, stopping at the first bad section.
+    async fn restore_components(&self, snapshot: &Snapshot) -> snapshot::Result<()> {
+        snapshot.restore("cpu", &self.cpu).await?;
+        snapshot
+            .restore("interpreter rom", &self.interpreter_rom)
+            .await?;
+        snapshot.restore("ram", &self.ram).await?;
+        snapshot.restore("display", &self.display).await?;
+        snapshot.restore("keypad", &self.keypad).await?;
+        snapshot.restore("rng", &self.rng).await?;
+        snapshot.restore("system clock", &self.system_clock).await?;
+        Ok(())
+    }
+
+    /// Creates a machine whose random numbers come from a sequence starting at `seed`, so
+    /// that runs with the same seed and input play out identically.
+    pub fn new(
+        variant: Chip8Variant,
+        quirks: Chip8Quirks,
+        font_set: Chip8FontSet,
+        audio_sink: impl AudioSink,
+        seed: u64,
+    ) -> machine::Result<Chip8Machine> {
         let clock_bus = OscillatorBus::new("clock bus");
         let memory_bus = AddressableBus::new("memory bus");
 
-        let cpu = Chip8CPU::new(&clock_bus, &memory_bus, 0x200);
-        let display = MonochromeDisplay::new(&memory_bus, 64, 32);
+        let (max_width, max_height) = variant.max_resolution();
+        let display = PlanarDisplay::new(variant.num_planes(), 64, 32, max_width, max_height);
+        let memory_end = variant.memory_size() - 1;
+        let display_start = variant.memory_size();
+        let display_end = display_start + display.max_size() - 1;
+        let keypad = Chip8Keypad::new();
+        tracing::info!("seeding random number generator with {}", seed);
+        let rng = Chip8Rng::new(seed);
+        let debugger = Debugger::new();
+        let mut cpu = Chip8CPU::new(
+            &clock_bus,
+            &memory_bus,
+            &display,
+            &keypad,
+            variant,
+            quirks,
+            PROGRAM_ADDRESS as u16,
+        );
+        cpu.attach_debugger(&debugger);
+        cpu.attach_rng(&rng);
+        memory_bus.attach_debugger(&debugger);
         let ram = RAM::new("RAM");
-        let osc = Oscillator::new(&clock_bus, 500);
+        let osc = Oscillator::new(&clock_bus, CPU_FREQUENCY_HZ);
+        let beeper = Chip8Beeper::new(audio_sink);
+        let timers = Chip8Timers::new(cpu.registers(), &beeper);
 
-        let interpreter_rom = ROM::new("Interpreter ROM", &[]);
+        let interpreter_rom = ROM::new("Interpreter ROM", &font_set.interpreter_rom());
 
         // osc <----clock_bus----> cpu
         // cpu <---memory_bus----> rom[0x0000 - 0x01FF]
-        // cpu <---memory_bus----> ram[0x0200 - 0x0FFF]
-        // cpu <---memory_bus----> display[0x1000 - 0x1100]
+        // cpu <---memory_bus----> ram[0x0200 - 0x0FFF (0xFFFF on XO-CHIP)]
+        // cpu <---memory_bus----> display[0x1000 - 0x10FF (Chip-8) or 0x13FF (SUPER-CHIP)]
+        //                                 [0x10000 - 0x107FF (XO-CHIP)]
 
         let (_, _) = clock_bus.connect(osc.id(), cpu.id())?;
 
         memory_bus.map(0x0000..=0x01FF, interpreter_rom.clone())?;
-        memory_bus.map(0x0200..=0x0FFF, ram.clone())?;
-        memory_bus.map(0x1000..=0x1100, display.clone())?;
+        memory_bus.map(PROGRAM_ADDRESS..=memory_end, ram.clone())?;
+        memory_bus.map(display_start..=display_end, display.clone())?;
 
         let machine = Chip8Machine {
             id: ComponentId::new("Chip-8 Machine"),
@@ -121,10 +463,878 @@ impl Chip8Machine {
             memory_bus,
             cpu,
             display,
+            keypad,
+            rng,
             interpreter_rom,
             ram,
             system_clock: osc,
+            timers,
+            variant,
+            debugger,
+            cycles_run: AtomicUsize::new(0),
+            frame_observer: Mutex::new(None),
         };
         Ok(machine)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use kaiseki_core::audio::NullAudioSink;
+    use kaiseki_core::debugger::{
+        Breakpoint, Comparison, DebugEvent, MemoryAccess, RegisterCondition, Watchpoint,
+    };
+    use kaiseki_core::machine::{Machine, MachineError, ResetKind};
+    use kaiseki_core::movie::MovieError;
+    use kaiseki_core::snapshot::{Snapshot, SnapshotError};
+    use kaiseki_core::{
+        AddressableComponent, ExecutableComponent, Fault, Lifecycle, LifecycleState, Vex, VexError,
+    };
+
+    use super::{Chip8Machine, Chip8Variant, PROGRAM_ADDRESS};
+    use crate::assembler::assemble;
+    use crate::font::Chip8FontSet;
+    use crate::quirks::Chip8Quirks;
+
+    fn create_machine(variant: Chip8Variant) -> Chip8Machine {
+        create_seeded_machine(variant, 0)
+    }
+
+    fn create_seeded_machine(variant: Chip8Variant, seed: u64) -> Chip8Machine {
+        let sink = NullAudioSink::new(44100);
+        Chip8Machine::new(
+            variant,
+            Chip8Quirks::default(),
+            Chip8FontSet::default(),
+            sink,
+            seed,
+        )
+        .unwrap()
+    }
+
+    fn write_rom(name: &str, size: usize) -> String {
+        let path =
+            std::env::temp_dir().join(format!("kaiseki-{}-{}.ch8", name, std::process::id()));
+        fs::write(&path, vec![0xA5; size]).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn load_fills_available_memory() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let path = write_rom("load-fills", 0xE00);
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let memory = machine.memory_bus.read(PROGRAM_ADDRESS, 0xE00).unwrap();
+        assert!(memory.iter().all(|b| *b == 0xA5));
+        assert_eq!(machine.display.read(0, 1).unwrap(), vec![0x00]);
+    }
+
+    #[test]
+    fn load_rejects_oversized_programs() {
+        let path = write_rom("load-oversized", 0xE01);
+        let result = create_machine(Chip8Variant::SuperChip).load(&path);
+        assert_eq!(
+            result,
+            Err(MachineError::FileTooLarge(path.clone(), 0xE01, 0xE00))
+        );
+
+        // XO-CHIP has room for much larger programs.
+        create_machine(Chip8Variant::XoChip).load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_reports_missing_files() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let result = machine.load("does/not/exist.ch8");
+        assert!(
+            matches!(result, Err(MachineError::FileRead(path, _)) if path == "does/not/exist.ch8")
+        );
+    }
+
+    #[tokio::test]
+    async fn run_cycles_ticks_timers_once_per_frame() {
+        // Store 0xFF in DT, then spin.
+        let machine = create_machine(Chip8Variant::Chip8);
+        let path = write_rom("run-cycles", 0);
+        fs::write(&path, [0x6A, 0xFF, 0xFA, 0x15, 0x12, 0x04]).unwrap();
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let frames = 60;
+        machine
+            .run_cycles(frames * machine.cycles_per_frame())
+            .await
+            .unwrap();
+        machine.run_cycles(20).await.unwrap();
+
+        let registers = machine.registers().await;
+        let value = |name: &str| registers.iter().find(|(n, _)| n == name).unwrap().1;
+        assert_eq!(value("VA"), 0xFF);
+        assert_eq!(value("PC"), 0x204);
+        assert_eq!(value("DT"), 0xFF - 60);
+    }
+
+    /// Runs a program that counts in V0 and V1 while drawing digits, captures a snapshot, and
+    /// returns the snapshot along with the frame and registers after running some more.
+    async fn run_counter(machine: &Chip8Machine) -> (Snapshot, Vec<u8>, Vec<(String, usize)>) {
+        let path = write_rom("counter", 0);
+        let program = [
+            0x63, 0x01, 0x64, 0x05, 0x6A, 0xFF, 0xFA, 0x15, 0xF0, 0x29, 0xD1, 0x25, 0x80, 0x34,
+            0x81, 0x44, 0x12, 0x08,
+        ];
+        fs::write(&path, program).unwrap();
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        machine.run_cycles(300).await.unwrap();
+        let snapshot = machine.snapshot().await;
+        machine.run_cycles(500).await.unwrap();
+        (snapshot, machine.get_frame().2, machine.registers().await)
+    }
+
+    #[tokio::test]
+    async fn restore_continues_identically() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let (snapshot, frame, registers) = run_counter(&machine).await;
+
+        machine.restore(&snapshot).await.unwrap();
+        machine.run_cycles(500).await.unwrap();
+        assert_eq!(machine.get_frame().2, frame);
+        assert_eq!(machine.registers().await, registers);
+
+        // A snapshot also restores into a fresh machine, including through a file.
+        let path = std::env::temp_dir().join(format!("kaiseki-{}.ksnp", std::process::id()));
+        let path = path.to_str().unwrap();
+        snapshot.save(path).unwrap();
+        let loaded = Snapshot::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let fresh = create_machine(Chip8Variant::Chip8);
+        fresh.restore(&loaded).await.unwrap();
+        fresh.run_cycles(500).await.unwrap();
+        assert_eq!(fresh.get_frame().2, frame);
+        assert_eq!(fresh.registers().await, registers);
+    }
+
+    #[tokio::test]
+    async fn random_numbers_replay_from_seed() {
+        let path = write_rom("random", 0);
+        let source = "
+                    LD V1, 1
+                    LD I, 0x300
+            loop:   RND V0, 0xFF
+                    LD [I], V0
+                    ADD I, V1
+                    JP loop
+        ";
+        fs::write(&path, assemble(source, PROGRAM_ADDRESS as u16).unwrap()).unwrap();
+        let run = |machine: Chip8Machine, cycles| {
+            let path = path.clone();
+            async move {
+                machine.load(&path).unwrap();
+                machine.run_cycles(cycles).await.unwrap();
+                (machine.read_memory(0x300, 16).unwrap(), machine)
+            }
+        };
+
+        let (numbers, machine) = run(create_seeded_machine(Chip8Variant::Chip8, 7), 66).await;
+        let (same_seed, _) = run(create_seeded_machine(Chip8Variant::Chip8, 7), 66).await;
+        let (other_seed, _) = run(create_seeded_machine(Chip8Variant::Chip8, 8), 66).await;
+        fs::remove_file(&path).unwrap();
+        assert_eq!(numbers, same_seed);
+        assert_ne!(numbers, other_seed);
+
+        // The generator's state is part of a snapshot, and a reset starts it over.
+        let snapshot = machine.snapshot().await;
+        machine.run_cycles(64).await.unwrap();
+        let more_numbers = machine.read_memory(0x310, 16).unwrap();
+        let fresh = create_seeded_machine(Chip8Variant::Chip8, 99);
+        fresh.restore(&snapshot).await.unwrap();
+        fresh.run_cycles(64).await.unwrap();
+        assert_eq!(
+            fresh.read_memory(0x300, 32).unwrap(),
+            [numbers.clone(), more_numbers].concat()
+        );
+
+        fresh.write_memory(0x300, &[0; 16]).unwrap();
+        fresh.reset(ResetKind::Soft).await;
+        fresh.run_cycles(66).await.unwrap();
+        assert_eq!(fresh.read_memory(0x300, 16).unwrap(), numbers);
+    }
+
+    #[tokio::test]
+    async fn restore_leaves_machine_untouched_on_bad_section() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let (snapshot, _, _) = run_counter(&machine).await;
+        let before = machine.snapshot().await;
+        let registers = machine.registers().await;
+
+        // Sections before the display restore fine, but a zero-width display doesn't.
+        let mut display = snapshot.get("display").unwrap().to_vec();
+        display[..8].fill(0);
+        let mut corrupt = snapshot.clone();
+        corrupt.insert("display", display);
+        assert!(matches!(
+            machine.restore(&corrupt).await,
+            Err(MachineError::Snapshot(SnapshotError::InvalidSection(name, _))) if name == "display"
+        ));
+        assert_eq!(machine.registers().await, registers);
+        assert_eq!(machine.snapshot().await, before);
+    }
+
+    #[tokio::test]
+    async fn restore_rejects_other_variants() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let snapshot = machine.snapshot().await;
+
+        let other = create_machine(Chip8Variant::SuperChip);
+        assert!(matches!(
+            other.restore(&snapshot).await,
+            Err(MachineError::Snapshot(SnapshotError::InvalidSection(name, _))) if name == "machine"
+        ));
+    }
+
+    #[tokio::test]
+    async fn start_pauses_resumes_and_stops() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let path = write_rom("lifecycle", 0);
+        // Count up in V0 forever.
+        fs::write(&path, [0x63, 0x01, 0x80, 0x34, 0x12, 0x02]).unwrap();
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let v0 = || async {
+            let registers = machine.registers().await;
+            registers.iter().find(|(name, _)| name == "V0").unwrap().1
+        };
+        let lifecycle = Lifecycle::new();
+        let control = async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            lifecycle.pause();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let paused_v0 = v0().await;
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!(v0().await, paused_v0);
+
+            lifecycle.resume();
+            tokio::time::timeout(Duration::from_secs(3), async {
+                while v0().await == paused_v0 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("machine didn't resume");
+            lifecycle.stop();
+        };
+
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(machine.start(&lifecycle), control)
+        })
+        .await
+        .expect("machine didn't stop");
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn timers_count_down_with_the_cpu_while_running() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let path = write_rom("timers-running", 0);
+        // Set DT to 255, then keep a log of DT at 0x400 until it reaches 215.
+        let source = "
+                    LD V0, 255
+                    LD DT, V0
+                    LD I, 0x400
+            loop:   LD V0, DT
+                    LD [I], V0
+                    SNE V0, 215
+                    JP end
+                    JP loop
+            end:    JP end
+        ";
+        fs::write(&path, assemble(source, PROGRAM_ADDRESS as u16).unwrap()).unwrap();
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // The system clock hands out a second's worth of cycles at once, which is plenty.
+        let lifecycle = Lifecycle::new();
+        let control = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            lifecycle.stop();
+        };
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(machine.start(&lifecycle), control)
+        })
+        .await
+        .expect("machine didn't stop");
+        result.unwrap();
+
+        // Each pass of the loop takes 4 of the 8 or so cycles in a frame, so DT is seen at
+        // every value on the way down, rather than jumping as a batch of timer ticks lands.
+        let log = machine.read_memory(0x400, 0x100).unwrap();
+        let end = log.iter().position(|&dt| dt == 215).unwrap();
+        let log = &log[..=end];
+        assert_eq!(log[0], 255);
+        assert!(log.windows(2).all(|pair| pair[0] - pair[1] <= 1));
+        assert!(
+            (75..=90).contains(&log.len()),
+            "logged {} values",
+            log.len()
+        );
+    }
+
+    #[tokio::test]
+    async fn step_and_run_frame_work() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let path = write_rom("step", 0);
+        fs::write(&path, [0x6A, 0xFF, 0xFA, 0x15, 0x12, 0x04]).unwrap();
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        machine.step().await.unwrap();
+        machine.step().await.unwrap();
+        assert_eq!(machine.cycles_run(), 2);
+        let registers = machine.registers().await;
+        assert!(registers.contains(&(String::from("PC"), 0x204)));
+        assert!(registers.contains(&(String::from("DT"), 0xFF)));
+
+        // Frames are 8 or 9 cycles long at 500hz, and each ticks the timers once.
+        let cycles: Vec<usize> = vec![
+            machine.run_frame().await.unwrap(),
+            machine.run_frame().await.unwrap(),
+            machine.run_frame().await.unwrap(),
+        ];
+        assert_eq!(cycles, vec![7, 8, 8]);
+        assert_eq!(machine.cycles_run(), 2 + 7 + 8 + 8);
+        assert!(machine
+            .registers()
+            .await
+            .contains(&(String::from("DT"), 0xFF - 3)));
+    }
+
+    #[tokio::test]
+    async fn vex_execution_control_requires_pause() {
+        let path = write_rom("vex-control", 0);
+        fs::write(&path, [0x12, 0x00]).unwrap();
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Before starting, execution can be controlled freely.
+        vex.step().await.unwrap();
+        assert_eq!(vex.run_frames(2).await.unwrap(), 16);
+        assert_eq!(vex.cycles_run(), 17);
+
+        let control = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(vex.step().await, Err(VexError::Running));
+            vex.pause();
+            vex.run_cycles(3).await.unwrap();
+            assert_eq!(vex.cycles_run(), 20);
+            vex.stop();
+        };
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(vex.start(), control)
+        })
+        .await
+        .expect("vex didn't stop");
+        result.unwrap();
+    }
+
+    /// Writes a program that counts in V0, storing each count at 0x300.
+    fn write_store_counter(name: &str) -> String {
+        let path = write_rom(name, 0);
+        let source = "
+                    LD V3, 1
+            loop:   ADD V0, V3
+                    LD I, 0x300
+                    LD [I], V0
+                    JP loop
+        ";
+        let program = assemble(source, PROGRAM_ADDRESS as u16).unwrap();
+        fs::write(&path, program).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn breakpoints_and_watchpoints_halt_execution() {
+        let machine = create_machine(Chip8Variant::Chip8);
+        let path = write_store_counter("debugger");
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let debugger = machine.debugger();
+        let condition = RegisterCondition::new("V0", Comparison::Equal, 3);
+        let breakpoint = Breakpoint::at(0x206).with_condition(condition);
+        let id = debugger.add_breakpoint(breakpoint.clone());
+        machine.run_cycles(100).await.unwrap();
+        assert_eq!(machine.cycles_run(), 11);
+        assert_eq!(
+            debugger.halted(),
+            Some(DebugEvent::Breakpoint {
+                id,
+                breakpoint,
+                pc: 0x206
+            })
+        );
+
+        // Nothing runs while halted.
+        assert_eq!(machine.run_frame().await.unwrap(), 0);
+        assert_eq!(machine.cycles_run(), 11);
+
+        debugger.remove_breakpoint(id);
+        let id = debugger.add_watchpoint(Watchpoint::writes(0x300..=0x300));
+        debugger.resume();
+        machine.step().await.unwrap();
+        assert_eq!(machine.cycles_run(), 12);
+        assert!(matches!(
+            debugger.halted(),
+            Some(DebugEvent::Watchpoint { id: hit, access: MemoryAccess::Write, address: 0x300, .. })
+                if hit == id
+        ));
+
+        // The halting instruction completes, and the next store halts again.
+        debugger.resume();
+        machine.run_cycles(100).await.unwrap();
+        assert_eq!(machine.cycles_run(), 16);
+        assert!(debugger.halted().is_some());
+        assert_eq!(machine.memory_bus.read(0x300, 1).unwrap(), vec![4]);
+    }
+
+    #[tokio::test]
+    async fn breakpoint_pauses_started_vex() {
+        let path = write_store_counter("debugger-vex");
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let condition = RegisterCondition::new("V0", Comparison::GreaterOrEqual, 5);
+        vex.debugger().add_breakpoint(Breakpoint::when(condition));
+
+        let control = async {
+            tokio::time::timeout(Duration::from_secs(3), async {
+                while vex.state() != LifecycleState::Paused {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("breakpoint didn't pause the vex");
+            assert!(matches!(
+                vex.debugger().halted(),
+                Some(DebugEvent::Breakpoint { pc: 0x204, .. })
+            ));
+            assert!(vex.registers().await.contains(&(String::from("V0"), 5)));
+
+            // Stepping continues past the breakpoint, which still holds for the next instruction.
+            vex.step().await.unwrap();
+            let cycles_run = vex.cycles_run();
+            vex.run_cycles(10).await.unwrap();
+            assert_eq!(vex.cycles_run(), cycles_run);
+            assert!(matches!(
+                vex.debugger().halted(),
+                Some(DebugEvent::Breakpoint { pc: 0x206, .. })
+            ));
+            vex.stop();
+        };
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(vex.start(), control)
+        })
+        .await
+        .expect("vex didn't stop");
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn run_frames_ends_frames_where_running_freely_would() {
+        let path = write_rom("frames-after-running", 0);
+        let mut program = [0x60, 0x01].repeat(20);
+        program.extend_from_slice(&[0x12, 0x00]);
+        fs::write(&path, program).unwrap();
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let id = vex.debugger().add_breakpoint(Breakpoint::at(0x20A));
+
+        let control = async {
+            // Halt partway through the first frame, after 5 of its 9 cycles.
+            assert_eq!(vex.wait_while_running().await, LifecycleState::Paused);
+            vex.debugger().remove_breakpoint(id);
+
+            // Stepping picks up the frame where running freely left it.
+            assert_eq!(vex.run_frames(1).await.unwrap(), 4);
+            assert_eq!(vex.run_frames(1).await.unwrap(), 8);
+            assert_eq!(vex.cycles_run(), 12);
+            vex.stop();
+        };
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(vex.start(), control)
+        })
+        .await
+        .expect("vex didn't stop");
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn accesses_past_a_mapping_fault() {
+        let cases = [
+            // Past the end of the display, which follows RAM.
+            (
+                Chip8Variant::Chip8,
+                "LD I, 0xFFF\nLD V0, 0xFF\nADD I, V0\nLD V0, 1\nADD I, V0\nLD [I], V1",
+            ),
+            // Off the end of RAM and into the display.
+            (Chip8Variant::Chip8, "LD I, 0xFFF\nLD [I], V1"),
+            (Chip8Variant::XoChip, "LD I, LONG 0xFFFF\nLD [I], V1"),
+        ];
+        for (variant, source) in cases {
+            let machine = create_machine(variant);
+            let path = write_rom("out-of-mapping", 0);
+            fs::write(&path, assemble(source, PROGRAM_ADDRESS as u16).unwrap()).unwrap();
+            machine.load(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            let result = machine.run_cycles(10).await;
+            assert!(
+                matches!(&result, Err(fault) if fault.component == "Chip-8 CPU"),
+                "{:?} running '{}' gave {:?}",
+                variant,
+                source,
+                result
+            );
+            // The fault leaves memory usable.
+            machine.read_memory(PROGRAM_ADDRESS, 2).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn faults_halt_the_machine() {
+        // Recurse until the stack overflows.
+        let path = write_rom("fault", 0);
+        let program = assemble("CLS\nrecurse: CALL recurse", PROGRAM_ADDRESS as u16).unwrap();
+        fs::write(&path, program).unwrap();
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let result = tokio::time::timeout(Duration::from_secs(5), vex.start())
+            .await
+            .expect("fault didn't stop the vex");
+        let fault = Fault {
+            component: String::from("Chip-8 CPU"),
+            reason: String::from("stack overflow"),
+            address: Some(0x202),
+        };
+        assert_eq!(result, Err(VexError::Fault(fault.clone())));
+        assert_eq!(
+            fault.to_string(),
+            "Chip-8 CPU halted: stack overflow at 0x0202"
+        );
+        assert_eq!(vex.state(), LifecycleState::Faulted);
+        assert_eq!(vex.fault(), Some(fault.clone()));
+        assert!(vex.registers().await.contains(&(String::from("SP"), 16)));
+
+        // Running by hand faults at the same instruction without counting it as run.
+        let cycles_run = vex.cycles_run();
+        assert_eq!(vex.step().await, Err(VexError::Fault(fault)));
+        assert_eq!(vex.cycles_run(), cycles_run);
+    }
+
+    #[tokio::test]
+    async fn reset_starts_the_machine_over() {
+        let machine = create_machine(Chip8Variant::SuperChip);
+        let path = write_store_counter("reset");
+        machine.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        machine.run_cycles(9).await.unwrap();
+        machine.display.set_resolution(128, 64);
+        machine.display.write(0, &[0xFF]).unwrap();
+        assert_eq!(machine.memory_bus.read(0x300, 1).unwrap(), vec![2]);
+
+        // A soft reset keeps memory, so the program runs again from the start.
+        machine.reset(ResetKind::Soft).await;
+        assert_eq!(machine.cycles_run(), 0);
+        let registers = machine.registers().await;
+        assert!(registers.contains(&(String::from("PC"), PROGRAM_ADDRESS)));
+        assert!(registers.contains(&(String::from("V0"), 0)));
+        assert_eq!(machine.display.resolution(), (64, 32));
+        assert_eq!(machine.display.read(0, 1).unwrap(), vec![0x00]);
+        assert_eq!(machine.memory_bus.read(0x300, 1).unwrap(), vec![2]);
+        machine.run_cycles(5).await.unwrap();
+        assert_eq!(machine.memory_bus.read(0x300, 1).unwrap(), vec![1]);
+
+        // A hard reset clears it, leaving only the fonts in the interpreter ROM.
+        machine.reset(ResetKind::Hard).await;
+        let memory = machine.memory_bus.read(PROGRAM_ADDRESS, 0xE00).unwrap();
+        assert!(memory.iter().all(|b| *b == 0x00));
+        assert_ne!(machine.memory_bus.read(0x50, 5).unwrap(), vec![0x00; 5]);
+    }
+
+    #[tokio::test]
+    async fn reset_recovers_faulted_vex() {
+        let path = write_rom("reset-fault", 0);
+        let program = assemble("CLS\nrecurse: CALL recurse", PROGRAM_ADDRESS as u16).unwrap();
+        fs::write(&path, &program).unwrap();
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        let result = tokio::time::timeout(Duration::from_secs(5), vex.start())
+            .await
+            .expect("fault didn't stop the vex");
+        assert!(matches!(result, Err(VexError::Fault(_))));
+
+        // A hard reset reloads the program and clears the fault, so the vex can start again.
+        vex.write_memory(PROGRAM_ADDRESS, &[0x00, 0x00]).unwrap();
+        vex.reset(ResetKind::Hard).await.unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(vex.fault(), None);
+        assert_eq!(vex.debugger().halted(), None);
+        assert!(vex.registers().await.contains(&(String::from("SP"), 0)));
+        assert_eq!(vex.read_memory(PROGRAM_ADDRESS, 4).unwrap(), program);
+        let result = tokio::time::timeout(Duration::from_secs(5), vex.start())
+            .await
+            .expect("fault didn't stop the restarted vex");
+        assert!(matches!(result, Err(VexError::Fault(_))));
+    }
+
+    #[tokio::test]
+    async fn replayed_movie_reproduces_run() {
+        // Show each key pressed and released, counting them in V2.
+        let path = write_rom("movie", 0);
+        let source = "
+            loop:   LD V0, K
+                    LD F, V0
+                    CLS
+                    DRW V1, V1, 5
+                    ADD V2, 1
+                    JP loop
+        ";
+        fs::write(&path, assemble(source, PROGRAM_ADDRESS as u16).unwrap()).unwrap();
+        let create_vex =
+            |seed| Vex::create(create_seeded_machine(Chip8Variant::Chip8, seed), &path);
+
+        let vex = create_vex(3).unwrap();
+        vex.record().await.unwrap();
+        for (key, pressed) in [(0x5, true), (0x5, false), (0x9, true), (0x9, false)] {
+            vex.run_cycles(40).await.unwrap();
+            match pressed {
+                true => vex.press_key(key),
+                false => vex.release_key(key),
+            }
+        }
+        vex.run_cycles(100).await.unwrap();
+        let movie = vex.stop_recording().unwrap();
+        assert_eq!(movie.seed(), 3);
+        assert_eq!(movie.events().len(), 4);
+        assert_eq!(movie.events()[1].cycle, 80);
+        let registers = vex.registers().await;
+        assert!(registers.contains(&(String::from("V0"), 0x9)));
+        assert!(registers.contains(&(String::from("V2"), 2)));
+
+        let replay = create_vex(3).unwrap();
+        replay.run_cycles(25).await.unwrap();
+        replay.replay(&movie).await.unwrap();
+        replay.press_key(0x1);
+        replay.run_cycles(260).await.unwrap();
+        assert_eq!(replay.get_frame(), vex.get_frame());
+        assert_eq!(replay.registers().await, registers);
+
+        let other_seed = create_vex(4).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            other_seed.replay(&movie).await,
+            Err(VexError::Movie(MovieError::SeedMismatch(3, 4)))
+        );
+    }
+
+    #[tokio::test]
+    async fn movie_recorded_running_replays_stepped() {
+        // Wait for key 5, then count in V1 until DT runs down from 30 and show the count.
+        let path = write_rom("movie-running", 0);
+        let source = "
+                    LD V0, 5
+            wait:   SKP V0
+                    JP wait
+                    LD V2, 30
+                    LD DT, V2
+            count:  ADD V1, 1
+                    LD V2, DT
+                    SE V2, 0
+                    JP count
+                    LD F, V1
+                    DRW V3, V3, 5
+            end:    JP end
+        ";
+        fs::write(&path, assemble(source, PROGRAM_ADDRESS as u16).unwrap()).unwrap();
+        let create_vex = || {
+            let vex = Vex::create(create_seeded_machine(Chip8Variant::Chip8, 7), &path).unwrap();
+            vex.debugger().add_breakpoint(Breakpoint::at(0x216));
+            vex
+        };
+
+        let vex = create_vex();
+        vex.record().await.unwrap();
+        let control = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            vex.press_key(0x5);
+            assert_eq!(vex.wait_while_running().await, LifecycleState::Paused);
+            vex.stop();
+        };
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(vex.start(), control)
+        })
+        .await
+        .expect("vex didn't reach the end of the program");
+        result.unwrap();
+        let movie = vex.stop_recording().unwrap();
+        let registers = vex.registers().await;
+        // The count depends on DT running down one tick every 8 or 9 cycles.
+        let count = registers.iter().find(|(name, _)| name == "V1").unwrap().1;
+        assert!((55..70).contains(&count), "counted to {}", count);
+
+        let replay = create_vex();
+        replay.replay(&movie).await.unwrap();
+        fs::remove_file(&path).unwrap();
+        replay.run_frames(1000).await.unwrap();
+        assert_eq!(replay.registers().await, registers);
+        assert_eq!(replay.get_frame(), vex.get_frame());
+    }
+
+    #[tokio::test]
+    async fn rewind_returns_to_earlier_frames() {
+        let path = write_store_counter("rewind");
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        vex.enable_rewind(30, 5);
+
+        // history[n] is the state at the end of frame n + 1.
+        let mut history = Vec::new();
+        for _ in 0..60 {
+            vex.run_frames(1).await.unwrap();
+            history.push((vex.registers().await, vex.read_memory(0x300, 1).unwrap()));
+        }
+
+        // Rewinding lands on the newest capture at least as old as asked for.
+        let rewound = vex.rewind(12).await.unwrap();
+        assert!((12..17).contains(&rewound));
+        let state = (vex.registers().await, vex.read_memory(0x300, 1).unwrap());
+        assert_eq!(state, history[59 - rewound]);
+
+        // Only the last 30 frames or so are kept.
+        let rewound = rewound + vex.rewind(1000).await.unwrap();
+        assert!((30..=35).contains(&rewound));
+        let state = (vex.registers().await, vex.read_memory(0x300, 1).unwrap());
+        assert_eq!(state, history[59 - rewound]);
+
+        // Running on captures afresh from where the rewind left off, until a reset drops them.
+        vex.run_frames(10).await.unwrap();
+        assert!(vex.rewind(5).await.unwrap() >= 5);
+        vex.reset(ResetKind::Soft).await.unwrap();
+        assert_eq!(vex.rewind(1).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn rewind_captures_every_interval_while_running() {
+        let path = write_store_counter("rewind-running");
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        let reference = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        vex.enable_rewind(30, 2);
+
+        // history[n] is the state at the end of frame n + 1.
+        let mut history = Vec::new();
+        for _ in 0..60 {
+            reference.run_frames(1).await.unwrap();
+            history.push((
+                reference.registers().await,
+                reference.read_memory(0x300, 1).unwrap(),
+            ));
+        }
+
+        // The system clock hands out a second's worth of cycles at a time, which run well
+        // within this.
+        let control = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            vex.pause();
+            vex.wait_while_running().await;
+
+            // Each rewind lands on a capture from the end of a frame, two frames apart.
+            let mut frames = Vec::new();
+            for _ in 0..3 {
+                let rewound = vex.rewind(2).await.unwrap();
+                assert!((2..4).contains(&rewound));
+                let state = (vex.registers().await, vex.read_memory(0x300, 1).unwrap());
+                let frame = history
+                    .iter()
+                    .position(|captured| *captured == state)
+                    .expect("rewound to a state that didn't end a frame");
+                frames.push(frame);
+            }
+            assert_eq!(frames[0] - frames[1], 2);
+            assert_eq!(frames[1] - frames[2], 2);
+            vex.stop();
+        };
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(vex.start(), control)
+        })
+        .await
+        .expect("vex didn't stop");
+        result.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rewind_lines_up_after_stepping_and_running() {
+        // Count in V0 as DT runs down from 255, storing each count at 0x300.
+        let path = write_rom("rewind-mixed", 0);
+        let source = "
+                    LD V4, 255
+                    LD DT, V4
+                    LD V3, 1
+            loop:   ADD V0, V3
+                    LD I, 0x300
+                    LD [I], V0
+                    JP loop
+        ";
+        fs::write(&path, assemble(source, PROGRAM_ADDRESS as u16).unwrap()).unwrap();
+        let vex = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        let reference = Vex::create(create_machine(Chip8Variant::Chip8), &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        vex.enable_rewind(60, 1);
+
+        // history[n] is the state at the end of frame n + 1.
+        let mut history = Vec::new();
+        for _ in 0..60 {
+            reference.run_frames(1).await.unwrap();
+            history.push(reference.registers().await);
+        }
+
+        // Step a few frames, run freely until partway through a frame, then step again.
+        vex.run_frames(5).await.unwrap();
+        let condition = RegisterCondition::new("V0", Comparison::GreaterOrEqual, 100);
+        let id = vex.debugger().add_breakpoint(Breakpoint::when(condition));
+        let control = async {
+            assert_eq!(vex.wait_while_running().await, LifecycleState::Paused);
+            vex.stop();
+        };
+        let (result, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(vex.start(), control)
+        })
+        .await
+        .expect("breakpoint didn't pause the vex");
+        result.unwrap();
+        vex.debugger().remove_breakpoint(id);
+        vex.run_frames(3).await.unwrap();
+
+        // Every capture, from either kind of running, is the state at the end of its frame,
+        // timers included.
+        let mut frames = Vec::new();
+        while vex.rewind(1).await.unwrap() == 1 {
+            let state = vex.registers().await;
+            let frame = history
+                .iter()
+                .position(|captured| *captured == state)
+                .expect("rewound to a state that didn't end its frame");
+            frames.push(frame);
+        }
+        assert!(frames.windows(2).all(|pair| pair[0] == pair[1] + 1));
+        assert_eq!(frames.last(), Some(&0));
+    }
+}
@@ -0,0 +1,79 @@
+/// How FX55 and FX65 leave VI after storing or loading registers V0 - VX.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Chip8IndexIncrement {
+    /// VI is left unchanged.
+    Unchanged,
+    /// VI is incremented by X.
+    ByX,
+    /// VI is incremented by X + 1, pointing just past the last byte accessed.
+    ByXPlusOne,
+}
+
+/// Behaviors that differ between Chip-8 interpreters, and that ROMs written for a particular
+/// interpreter may rely on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Chip8Quirks {
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Effect of FX55 and FX65 on VI.
+    pub index_increment: Chip8IndexIncrement,
+    /// 8XY6 and 8XYE shift VY and store the result in VX, rather than shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// BNNN jumps to NNN + VX, where X is the high nybble of NNN, rather than NNN + V0.
+    pub jump_uses_vx: bool,
+    /// Sprite pixels past the edge of the display wrap around to the opposite edge, rather
+    /// than being clipped. Sprite origins always wrap.
+    pub sprites_wrap: bool,
+}
+
+impl Default for Chip8Quirks {
+    fn default() -> Self {
+        Self::cosmac_vip()
+    }
+}
+
+impl Chip8Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub fn cosmac_vip() -> Self {
+        Self {
+            logic_resets_vf: true,
+            index_increment: Chip8IndexIncrement::ByXPlusOne,
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            sprites_wrap: false,
+        }
+    }
+
+    /// CHIP-48 for the HP-48 calculators.
+    pub fn chip48() -> Self {
+        Self {
+            logic_resets_vf: false,
+            index_increment: Chip8IndexIncrement::ByX,
+            shift_uses_vy: false,
+            jump_uses_vx: true,
+            sprites_wrap: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 for the HP-48 calculators.
+    pub fn schip() -> Self {
+        Self {
+            logic_resets_vf: false,
+            index_increment: Chip8IndexIncrement::Unchanged,
+            shift_uses_vy: false,
+            jump_uses_vx: true,
+            sprites_wrap: false,
+        }
+    }
+
+    /// Modern interpreters such as Octo, which most recently-written ROMs target.
+    pub fn modern() -> Self {
+        Self {
+            logic_resets_vf: false,
+            index_increment: Chip8IndexIncrement::ByXPlusOne,
+            shift_uses_vy: true,
+            jump_uses_vx: false,
+            sprites_wrap: true,
+        }
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, Default)]
+use kaiseki_core::snapshot::{self, StateReader, StateWriter};
+
+#[derive(Clone, Debug, Default)]
 #[allow(non_snake_case)]
 #[allow(unused)]
 pub struct Chip8Registers {
@@ -29,17 +31,80 @@ pub struct Chip8Registers {
 
     /// Sound timer register.
     pub ST: u8,
+
+    /// SUPER-CHIP RPL user flags, saved and restored by FX75 and FX85.
+    pub RPL: [u8; 16],
+
+    /// XO-CHIP audio pattern buffer loaded by F002; the beeper plays a square wave until a
+    /// pattern is loaded.
+    pub PATTERN: Option<[u8; 16]>,
+
+    /// XO-CHIP audio pattern playback pitch set by FX3A.
+    pub PITCH: u8,
 }
 
+/// Pitch at which an XO-CHIP audio pattern plays back at 4000 bits per second.
+pub const DEFAULT_PITCH: u8 = 64;
+
 impl Chip8Registers {
     pub fn new() -> Self {
         Chip8Registers {
+            PITCH: DEFAULT_PITCH,
             ..Default::default()
         }
     }
 
-    pub fn get_register_ref(&self, index: u8) -> &u8 {
-        match index {
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        for value in (0x0..=0xF).filter_map(|idx| self.get_register_ref(idx)) {
+            writer.write_u8(*value);
+        }
+        writer.write_u16(self.VI);
+        writer.write_u16(self.PC);
+        writer.write_u8(self.SP);
+        writer.write_u8(self.DT);
+        writer.write_u8(self.ST);
+        writer.write_bytes(&self.RPL);
+        writer.write_bool(self.PATTERN.is_some());
+        writer.write_bytes(&self.PATTERN.unwrap_or_default());
+        writer.write_u8(self.PITCH);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> snapshot::Result<()> {
+        for idx in 0x0..=0xF {
+            if let Some(register) = self.get_register_mut(idx) {
+                *register = reader.read_u8()?;
+            }
+        }
+        self.VI = reader.read_u16()?;
+        self.PC = reader.read_u16()?;
+        self.SP = reader.read_u8()?;
+        self.DT = reader.read_u8()?;
+        self.ST = reader.read_u8()?;
+        self.RPL.copy_from_slice(reader.read_bytes_exact(16)?);
+        let has_pattern = reader.read_bool()?;
+        let pattern: [u8; 16] = reader.read_bytes_exact(16)?.try_into().unwrap();
+        self.PATTERN = has_pattern.then_some(pattern);
+        self.PITCH = reader.read_u8()?;
+        Ok(())
+    }
+
+    /// Names and values of V0 - VF, VI and the timers, for display and debugging.
+    pub fn named_values(&self) -> Vec<(String, usize)> {
+        let mut values: Vec<(String, usize)> = (0x0..=0xF)
+            .filter_map(|idx| {
+                let value = *self.get_register_ref(idx)?;
+                Some((format!("V{:X}", idx), value as usize))
+            })
+            .collect();
+        values.push((String::from("VI"), self.VI as usize));
+        values.push((String::from("DT"), self.DT as usize));
+        values.push((String::from("ST"), self.ST as usize));
+        values
+    }
+
+    /// VX for X of `index`, or `None` if `index` isn't 0x0 - 0xF.
+    pub fn get_register_ref(&self, index: u8) -> Option<&u8> {
+        let register = match index {
             0x0 => &self.V0,
             0x1 => &self.V1,
             0x2 => &self.V2,
@@ -56,12 +121,14 @@ impl Chip8Registers {
             0xD => &self.VD,
             0xE => &self.VE,
             0xF => &self.VF,
-            _ => panic!("invalid register index 0x{:02X}", index),
-        }
+            _ => return None,
+        };
+        Some(register)
     }
 
-    pub fn get_register_mut(&mut self, index: u8) -> &mut u8 {
-        match index {
+    /// VX for X of `index`, or `None` if `index` isn't 0x0 - 0xF.
+    pub fn get_register_mut(&mut self, index: u8) -> Option<&mut u8> {
+        let register = match index {
             0x0 => &mut self.V0,
             0x1 => &mut self.V1,
             0x2 => &mut self.V2,
@@ -78,7 +145,8 @@ impl Chip8Registers {
             0xD => &mut self.VD,
             0xE => &mut self.VE,
             0xF => &mut self.VF,
-            _ => panic!("invalid register index 0x{:02X}", index),
-        }
+            _ => return None,
+        };
+        Some(register)
     }
 }
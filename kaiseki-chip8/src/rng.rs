@@ -0,0 +1,124 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use kaiseki_core::snapshot::{self, StateReader, StateWriter, StatefulComponent};
+use kaiseki_core::{Component, ComponentId, ResettableComponent};
+
+/// Added to the state before every number is generated; the golden ratio in fixed point.
+const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+#[derive(Clone, Debug)]
+struct Chip8RngState {
+    seed: u64,
+    state: u64,
+}
+
+/// The random number source behind CXNN. Numbers are generated with SplitMix64, whose whole
+/// state is a single counter, so a run replays exactly given the same seed and the state
+/// fits in a snapshot.
+#[derive(Clone, Debug)]
+pub struct Chip8Rng {
+    id: ComponentId,
+    state: Arc<Mutex<Chip8RngState>>,
+}
+
+impl Component for Chip8Rng {
+    fn id(&self) -> &ComponentId {
+        &self.id
+    }
+}
+
+#[async_trait]
+impl StatefulComponent for Chip8Rng {
+    async fn save_state(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut writer = StateWriter::new();
+        writer.write_u64(state.seed);
+        writer.write_u64(state.state);
+        writer.finish()
+    }
+
+    async fn load_state(&self, data: &[u8]) -> snapshot::Result<()> {
+        let mut reader = StateReader::new(data);
+        let seed = reader.read_u64()?;
+        let state = reader.read_u64()?;
+        reader.finish()?;
+
+        *self.state.lock().unwrap() = Chip8RngState { seed, state };
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ResettableComponent for Chip8Rng {
+    /// Starts the sequence over from the seed.
+    async fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.state = state.seed;
+    }
+}
+
+impl Chip8Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            id: ComponentId::new("Chip-8 RNG"),
+            state: Arc::new(Mutex::new(Chip8RngState { seed, state: seed })),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.state.lock().unwrap().seed
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.state = state.state.wrapping_add(GAMMA);
+        let mut z = state.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Generates a byte from the high bits of the next number, which are the best mixed.
+    pub fn next_u8(&self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kaiseki_core::snapshot::StatefulComponent;
+    use kaiseki_core::ResettableComponent;
+
+    use super::Chip8Rng;
+
+    #[test]
+    fn matches_splitmix64() {
+        // Reference outputs for seed 1234567.
+        let rng = Chip8Rng::new(1234567);
+        assert_eq!(rng.next_u64(), 6457827717110365317);
+        assert_eq!(rng.next_u64(), 3203168211198807973);
+        assert_eq!(rng.next_u64(), 9817491932198370423);
+    }
+
+    #[tokio::test]
+    async fn sequence_replays_from_seed_and_state() {
+        let rng = Chip8Rng::new(42);
+        let first: Vec<u8> = (0..8).map(|_| rng.next_u8()).collect();
+        let state = rng.save_state().await;
+        let second: Vec<u8> = (0..8).map(|_| rng.next_u8()).collect();
+        assert_ne!(first, second);
+
+        rng.reset().await;
+        assert_eq!((0..8).map(|_| rng.next_u8()).collect::<Vec<u8>>(), first);
+
+        let restored = Chip8Rng::new(0);
+        restored.load_state(&state).await.unwrap();
+        assert_eq!(restored.seed(), 42);
+        assert_eq!(
+            (0..8).map(|_| restored.next_u8()).collect::<Vec<u8>>(),
+            second
+        );
+    }
+}
@@ -1,5 +1,19 @@
 use std::fmt;
 
+use thiserror::Error;
+
+use kaiseki_core::snapshot::{self, SnapshotError, StateReader, StateWriter};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Chip8StackError {
+    #[error("stack overflow")]
+    Overflow,
+    #[error("stack underflow")]
+    Underflow,
+}
+
+pub type Result<T> = std::result::Result<T, Chip8StackError>;
+
 pub struct Chip8Stack {
     stack_pointer: u8,
     slots: [u16; 16],
@@ -25,16 +39,67 @@ impl Chip8Stack {
         }
     }
 
-    pub fn pop(&mut self) -> u16 {
-        assert!(self.stack_pointer > 0);
-        let address = self.slots[self.stack_pointer as usize];
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.stack_pointer);
+        for slot in self.slots {
+            writer.write_u16(slot);
+        }
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> snapshot::Result<()> {
+        let stack_pointer = reader.read_u8()?;
+        if stack_pointer as usize > self.slots.len() {
+            return Err(SnapshotError::InvalidState(format!(
+                "stack pointer {} is out of range",
+                stack_pointer
+            )));
+        }
+        self.stack_pointer = stack_pointer;
+        for slot in self.slots.iter_mut() {
+            *slot = reader.read_u16()?;
+        }
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<u16> {
+        if self.stack_pointer == 0 {
+            return Err(Chip8StackError::Underflow);
+        }
         self.stack_pointer -= 1;
-        address
+        Ok(self.slots[self.stack_pointer as usize])
     }
 
-    pub fn push(&mut self, address: u16) {
-        assert!(self.stack_pointer < 16);
+    pub fn push(&mut self, address: u16) -> Result<()> {
+        if self.stack_pointer as usize == self.slots.len() {
+            return Err(Chip8StackError::Overflow);
+        }
         self.slots[self.stack_pointer as usize] = address;
         self.stack_pointer += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chip8Stack, Chip8StackError};
+
+    #[test]
+    fn push_and_pop_work() {
+        let mut stack = Chip8Stack::new();
+        assert_eq!(stack.pop(), Err(Chip8StackError::Underflow));
+
+        for address in 0..16 {
+            stack.push(0x200 + address).unwrap();
+        }
+        assert_eq!(stack.push(0x300), Err(Chip8StackError::Overflow));
+        assert_eq!(stack.stack_pointer(), 16);
+
+        assert_eq!(stack.pop(), Ok(0x20F));
+        assert_eq!(stack.pop(), Ok(0x20E));
+        assert_eq!(stack.stack_pointer(), 14);
     }
 }
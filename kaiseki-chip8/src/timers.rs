@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+
+use kaiseki_core::{Component, ComponentId, ResettableComponent};
+
+use super::beeper::Chip8Beeper;
+use super::registers::Chip8Registers;
+
+/// Frequency at which the delay and sound timers count down.
+pub const TIMER_FREQUENCY_HZ: usize = 60;
+
+/// Counts the delay (DT) and sound (ST) timer registers down towards zero each time it's
+/// ticked, which the machine does at the end of every frame of CPU cycles so the timers keep
+/// pace with the CPU whether it's running on its own or being stepped. The beeper renders one
+/// tick's worth of audio for every tick.
+#[derive(Debug)]
+pub struct Chip8Timers {
+    id: ComponentId,
+    regs: Arc<RwLock<Chip8Registers>>,
+    beeper: Chip8Beeper,
+}
+
+impl Component for Chip8Timers {
+    fn id(&self) -> &ComponentId {
+        &self.id
+    }
+}
+
+#[async_trait]
+impl ResettableComponent for Chip8Timers {
+    /// Resets the beeper; the timer registers themselves belong to the CPU, which resets them.
+    async fn reset(&self) {
+        self.beeper.reset().await;
+    }
+}
+
+impl Chip8Timers {
+    pub fn new(regs: &Arc<RwLock<Chip8Registers>>, beeper: &Chip8Beeper) -> Self {
+        Chip8Timers {
+            id: ComponentId::new("Chip-8 Timers"),
+            regs: regs.clone(),
+            beeper: beeper.clone(),
+        }
+    }
+
+    /// Decrements each non-zero timer register by one.
+    pub async fn tick(&self) {
+        let ticked = {
+            let mut regs = self.regs.write().await;
+            let ticked = regs.clone();
+            regs.DT = regs.DT.saturating_sub(1);
+            regs.ST = regs.ST.saturating_sub(1);
+            ticked
+        };
+        // Render from a copy so the CPU isn't held up while the sink writes its samples.
+        self.beeper.render_frame(&ticked);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+
+    use kaiseki_core::audio::NullAudioSink;
+
+    use super::{Chip8Beeper, Chip8Registers, Chip8Timers};
+
+    fn setup() -> (Chip8Timers, Arc<RwLock<Chip8Registers>>) {
+        let regs = Arc::new(RwLock::new(Chip8Registers::new()));
+        let beeper = Chip8Beeper::new(NullAudioSink::new(44100));
+        let timers = Chip8Timers::new(&regs, &beeper);
+        (timers, regs)
+    }
+
+    #[tokio::test]
+    async fn tick_saturates_at_zero() {
+        let (timers, regs) = setup();
+        {
+            let mut regs = regs.write().await;
+            regs.DT = 2;
+            regs.ST = 1;
+        }
+
+        timers.tick().await;
+        {
+            let regs = regs.read().await;
+            assert_eq!((regs.DT, regs.ST), (1, 0));
+        }
+
+        timers.tick().await;
+        timers.tick().await;
+        let regs = regs.read().await;
+        assert_eq!((regs.DT, regs.ST), (0, 0));
+    }
+}
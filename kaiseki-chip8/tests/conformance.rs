@@ -0,0 +1,179 @@
+//! Runs ROMs headlessly and checks the frame each one leaves on the display against a golden
+//! image in `tests/golden`.
+//!
+//! Golden images are text, with a line for each row of pixels and a character for each pixel:
+//! `.` for unlit pixels, then `#`, `+` and `@` for pixels lit in planes 1, 2 and both. Run the
+//! tests with `KAISEKI_BLESS=1` set to write the frames the ROMs produce as their golden images
+//! rather than checking them, then review the changes before committing them.
+//!
+//! ROMs ending in `.asm` are assembled before they're run, so test programs can be kept as
+//! source alongside the well-known test ROMs.
+//!
+//! The community test suites, such as Timendus' chip8-test-suite, aren't checked in: they're
+//! GPL-licensed, where this repository is MIT. Instead, `tests/roms` holds programs written
+//! for these tests, each documenting the results it should draw so its golden images can be
+//! checked by hand. Any other ROM can be checked the same way by adding a test for it.
+
+use std::fs;
+use std::path::PathBuf;
+
+use kaiseki_chip8::assembler::assemble;
+use kaiseki_chip8::font::Chip8FontSet;
+use kaiseki_chip8::machine::{Chip8Machine, Chip8Variant, PROGRAM_ADDRESS};
+use kaiseki_chip8::quirks::Chip8Quirks;
+use kaiseki_core::audio::NullAudioSink;
+use kaiseki_core::machine::Machine;
+
+const PIXELS: [char; 4] = ['.', '#', '+', '@'];
+
+/// A ROM to run and the golden image of the frame it should leave behind.
+struct Conformance {
+    /// Name of the golden image, without the `.txt`.
+    name: &'static str,
+    /// Path of the ROM, relative to the crate.
+    rom: &'static str,
+    variant: Chip8Variant,
+    quirks: Chip8Quirks,
+    cycles: usize,
+}
+
+impl Conformance {
+    fn new(name: &'static str, rom: &'static str) -> Self {
+        Self {
+            name,
+            rom,
+            variant: Chip8Variant::default(),
+            quirks: Chip8Quirks::default(),
+            cycles: 1000,
+        }
+    }
+
+    /// Runs the ROM for the given number of cycles, then renders the frame as text.
+    async fn run(&self) -> String {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(self.rom);
+        let mut rom = path.clone();
+        if path.extension().is_some_and(|ext| ext == "asm") {
+            let source = fs::read_to_string(&path).unwrap();
+            let program = assemble(&source, PROGRAM_ADDRESS as u16)
+                .unwrap_or_else(|e| panic!("failed to assemble '{}': {}", self.rom, e));
+            rom = std::env::temp_dir().join(format!(
+                "kaiseki-conformance-{}-{}.ch8",
+                self.name,
+                std::process::id()
+            ));
+            fs::write(&rom, program).unwrap();
+        }
+
+        let sink = NullAudioSink::new(44100);
+        let machine =
+            Chip8Machine::new(self.variant, self.quirks, Chip8FontSet::default(), sink, 0).unwrap();
+        machine.load(&rom.to_string_lossy()).unwrap();
+        if rom != path {
+            fs::remove_file(&rom).unwrap();
+        }
+        machine
+            .run_cycles(self.cycles)
+            .await
+            .unwrap_or_else(|fault| panic!("'{}' faulted: {}", self.rom, fault));
+
+        let (width, _, frame) = machine.get_frame();
+        let palette = self.variant.palette();
+        let pixels: Vec<char> = frame
+            .chunks(3)
+            .map(|rgb| PIXELS[palette.iter().position(|color| color == rgb).unwrap()])
+            .collect();
+        pixels
+            .chunks(width)
+            .map(|row| row.iter().collect::<String>() + "\n")
+            .collect()
+    }
+
+    /// Runs the ROM and checks its frame against the golden image, or writes the frame as the
+    /// golden image if blessing.
+    async fn check(&self) {
+        let frame = self.run().await;
+        let golden = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.txt", self.name));
+        if std::env::var_os("KAISEKI_BLESS").is_some() {
+            fs::write(&golden, &frame).unwrap();
+            return;
+        }
+
+        let expected = fs::read_to_string(&golden).unwrap_or_else(|e| {
+            panic!(
+                "failed to read '{}', set KAISEKI_BLESS=1 to create it: {}",
+                golden.display(),
+                e
+            )
+        });
+        if frame != expected {
+            let rows: Vec<usize> = frame
+                .lines()
+                .zip(expected.lines())
+                .enumerate()
+                .filter(|(_, (actual, expected))| actual != expected)
+                .map(|(row, _)| row)
+                .collect();
+            panic!(
+                "'{}' doesn't match '{}' in rows {:?}; frame was:\n{}",
+                self.rom,
+                golden.display(),
+                rows,
+                frame
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn chip8_picture() {
+    Conformance::new("chip8-picture", "assets/Chip8 Picture.ch8")
+        .check()
+        .await;
+}
+
+#[tokio::test]
+async fn flags() {
+    Conformance::new("flags", "tests/roms/flags.asm")
+        .check()
+        .await;
+}
+
+#[tokio::test]
+async fn quirks_cosmac_vip() {
+    Conformance::new("quirks-cosmac-vip", "tests/roms/quirks.asm")
+        .check()
+        .await;
+}
+
+#[tokio::test]
+async fn quirks_chip48() {
+    Conformance {
+        quirks: Chip8Quirks::chip48(),
+        ..Conformance::new("quirks-chip48", "tests/roms/quirks.asm")
+    }
+    .check()
+    .await;
+}
+
+#[tokio::test]
+async fn quirks_schip() {
+    Conformance {
+        variant: Chip8Variant::SuperChip,
+        quirks: Chip8Quirks::schip(),
+        ..Conformance::new("quirks-schip", "tests/roms/quirks.asm")
+    }
+    .check()
+    .await;
+}
+
+#[tokio::test]
+async fn quirks_modern() {
+    Conformance {
+        quirks: Chip8Quirks::modern(),
+        ..Conformance::new("quirks-modern", "tests/roms/quirks.asm")
+    }
+    .check()
+    .await;
+}
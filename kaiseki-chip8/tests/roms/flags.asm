@@ -0,0 +1,88 @@
+; Runs each instruction that sets VF, drawing the result it left in V0 as two hex digits and
+; then the flag it left in VF. Results fill a column of five rows before starting the next.
+;
+; Each result should read, in order:
+;   ADD  0xF0 + 0x20            10 1
+;   ADD  0x10 + 0x20            30 0
+;   SUB  0x30 - 0x10            20 1
+;   SUB  0x10 - 0x30            E0 0
+;   SUBN 0x30 - 0x10            20 1
+;   SUBN 0x10 - 0x30            E0 0
+;   SHR  0x05                   02 1
+;   SHL  0x81                   02 1
+;   OR   0x0F | 0xF0            FF 0    VF was 5 beforehand
+;   ADD  VF, VF with VF 0x90    01 1    the flag wins over the sum
+    CLS
+    LD V7, 0
+    LD V9, 0
+
+    LD V0, 0xF0
+    LD V1, 0x20
+    ADD V0, V1
+    CALL show
+    LD V0, 0x10
+    ADD V0, V1
+    CALL show
+
+    LD V0, 0x30
+    LD V1, 0x10
+    SUB V0, V1
+    CALL show
+    LD V0, 0x10
+    LD V1, 0x30
+    SUB V0, V1
+    CALL show
+
+    LD V0, 0x10
+    SUBN V0, V1
+    CALL show
+    LD V0, 0x30
+    LD V1, 0x10
+    SUBN V0, V1
+    CALL show
+
+    LD V0, 0x05
+    SHR V0, V0
+    CALL show
+    LD V0, 0x81
+    SHL V0, V0
+    CALL show
+
+    LD V0, 0x0F
+    LD V1, 0xF0
+    LD VF, 5
+    OR V0, V1
+    CALL show
+
+    LD VF, 0x90
+    ADD VF, VF
+    LD V0, VF
+    CALL show
+end:
+    JP end
+
+; Draws V0 and VF at the next free row, using V2, V3 and V8.
+show:
+    LD V2, VF
+    LD V3, V0
+    SHR V3, V3
+    SHR V3, V3
+    SHR V3, V3
+    SHR V3, V3
+    LD F, V3
+    LD V8, V7
+    DRW V8, V9, 5
+    LD V3, 0x0F
+    AND V3, V0
+    LD F, V3
+    ADD V8, 5
+    DRW V8, V9, 5
+    LD F, V2
+    ADD V8, 7
+    DRW V8, V9, 5
+    ADD V9, 6
+    SE V9, 30
+    RET
+    LD V9, 0
+    ADD V7, 24
+    RET
@@ -0,0 +1,57 @@
+; Draws a digit for each behavior that differs between interpreters, left to right, then a
+; sprite off the right edge of the display.
+;
+;                       COSMAC VIP  CHIP-48  SUPER-CHIP  Modern
+;   8XY6 shifts VY      4           0        0           4
+;   8XY1 resets VF      0           5        5           5
+;   FX65 after FX55     3           B        A           3
+;   BNNN adds VX        1           2        2           1
+;   sprites wrap        clipped     clipped  clipped     wrapped
+    CLS
+    LD V8, 0
+    LD V9, 0
+
+    LD V0, 0x01
+    LD V1, 0x08
+    SHR V0, V1
+    CALL show
+
+    LD VF, 5
+    OR V0, V0
+    LD V0, VF
+    CALL show
+
+    LD I, data
+    LD V0, 0x0A
+    LD V1, 0x0B
+    LD [I], V1
+    LD V0, [I]
+    CALL show
+
+    LD V0, 0
+    LD V2, 4
+    JP V0, jumps
+jumps:
+    LD V0, 1
+    JP jumped
+    LD V0, 2
+jumped:
+    CALL show
+
+    LD V0, 8
+    LD F, V0
+    LD V0, 62
+    LD V1, 20
+    DRW V0, V1, 5
+end:
+    JP end
+
+; Draws V0 at the next free column, using V8.
+show:
+    LD F, V0
+    DRW V8, V9, 5
+    ADD V8, 6
+    RET
+
+data:
+    db 0x01, 0x02, 0x03
@@ -0,0 +1,25 @@
+mod null;
+mod wav;
+
+pub use null::NullAudioSink;
+pub use wav::WavAudioSink;
+
+use thiserror::Error;
+
+use crate::component::{Component, ComponentId};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum AudioSinkError {
+    #[error("audio sink failed to open '{0}': {1}")]
+    OpenFailed(String, String),
+    #[error("audio sink {0} failed to write {1} samples: {2}")]
+    WriteFailed(ComponentId, usize, String),
+}
+
+pub type Result<T> = std::result::Result<T, AudioSinkError>;
+
+/// Destination for the audio a machine produces, as buffers of signed 16-bit mono PCM samples.
+pub trait AudioSink: Component {
+    fn sample_rate(&self) -> u32;
+    fn write(&self, samples: &[i16]) -> Result<()>;
+}
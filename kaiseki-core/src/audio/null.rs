@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex};
+
+use crate::audio::{AudioSink, Result};
+use crate::component::{Component, ComponentId};
+
+#[derive(Clone, Debug)]
+struct NullAudioSinkState {
+    samples_written: usize,
+}
+
+/// Discards every sample written to it, keeping only a count.
+#[derive(Clone, Debug)]
+pub struct NullAudioSink {
+    id: ComponentId,
+    sample_rate: u32,
+    state: Arc<Mutex<NullAudioSinkState>>,
+}
+
+impl Component for NullAudioSink {
+    fn id(&self) -> &ComponentId {
+        &self.id
+    }
+}
+
+impl AudioSink for NullAudioSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&self, samples: &[i16]) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.samples_written += samples.len();
+        Ok(())
+    }
+}
+
+impl NullAudioSink {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            id: ComponentId::new("Null Audio Sink"),
+            sample_rate,
+            state: Arc::new(Mutex::new(NullAudioSinkState { samples_written: 0 })),
+        }
+    }
+
+    pub fn samples_written(&self) -> usize {
+        self.state.lock().unwrap().samples_written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioSink, NullAudioSink};
+
+    #[test]
+    fn write_counts_samples() {
+        let sink = NullAudioSink::new(44100);
+        assert_eq!(sink.sample_rate(), 44100);
+        assert_eq!(sink.samples_written(), 0);
+
+        sink.write(&[0; 735]).unwrap();
+        sink.clone().write(&[1; 10]).unwrap();
+        assert_eq!(sink.samples_written(), 745);
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::audio::{AudioSink, AudioSinkError, Result};
+use crate::component::{Component, ComponentId};
+
+const HEADER_LENGTH: u32 = 44;
+const BYTES_PER_SAMPLE: u16 = 2;
+/// Most sample data a WAV file can hold, since the RIFF chunk's size must fit in 32 bits.
+const MAX_DATA_LENGTH: u32 = u32::MAX - (HEADER_LENGTH - 8);
+
+#[derive(Debug)]
+struct WavAudioSinkState {
+    file: File,
+    data_length: u32,
+}
+
+/// Writes samples to a 16-bit mono PCM WAV file. The header is kept up to date after every
+/// write, so the file is valid even if the machine never shuts down cleanly. Once the file
+/// reaches the 4 GiB limit of the format, further samples are dropped.
+#[derive(Clone, Debug)]
+pub struct WavAudioSink {
+    id: ComponentId,
+    sample_rate: u32,
+    state: Arc<Mutex<WavAudioSinkState>>,
+}
+
+impl Component for WavAudioSink {
+    fn id(&self) -> &ComponentId {
+        &self.id
+    }
+}
+
+impl AudioSink for WavAudioSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn write(&self, samples: &[i16]) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let room = (MAX_DATA_LENGTH - state.data_length) / BYTES_PER_SAMPLE as u32;
+        if room == 0 {
+            return Ok(());
+        }
+        if samples.len() > room as usize {
+            tracing::warn!("{} is full; dropping any further samples", self.id);
+        }
+        let bytes: Vec<u8> = samples
+            .iter()
+            .take(room as usize)
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        let data_length = state.data_length + bytes.len() as u32;
+
+        let file = &mut state.file;
+        file.seek(SeekFrom::End(0))
+            .and_then(|_| file.write_all(&bytes))
+            .and_then(|_| file.seek(SeekFrom::Start(0)))
+            .and_then(|_| file.write_all(&header(self.sample_rate, data_length)))
+            .map_err(|e| {
+                AudioSinkError::WriteFailed(self.id.clone(), samples.len(), e.to_string())
+            })?;
+        state.data_length = data_length;
+        Ok(())
+    }
+}
+
+impl WavAudioSink {
+    pub fn new(path: &str, sample_rate: u32) -> Result<Self> {
+        let open_failed =
+            |e: std::io::Error| AudioSinkError::OpenFailed(path.into(), e.to_string());
+        let mut file = File::create(path).map_err(open_failed)?;
+        file.write_all(&header(sample_rate, 0))
+            .map_err(open_failed)?;
+
+        Ok(Self {
+            id: ComponentId::new("WAV Audio Sink"),
+            sample_rate,
+            state: Arc::new(Mutex::new(WavAudioSinkState {
+                file,
+                data_length: 0,
+            })),
+        })
+    }
+}
+
+fn header(sample_rate: u32, data_length: u32) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LENGTH as usize);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(HEADER_LENGTH - 8 + data_length).to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&1u16.to_le_bytes()); // mono
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * BYTES_PER_SAMPLE as u32).to_le_bytes());
+    header.extend_from_slice(&BYTES_PER_SAMPLE.to_le_bytes());
+    header.extend_from_slice(&(BYTES_PER_SAMPLE * 8).to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_length.to_le_bytes());
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioSink, WavAudioSink, MAX_DATA_LENGTH};
+
+    #[test]
+    fn write_produces_valid_wav() {
+        let path = std::env::temp_dir().join(format!("kaiseki-{}.wav", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let sink = WavAudioSink::new(path, 8000).unwrap();
+        sink.write(&[1, -1]).unwrap();
+        sink.write(&[0x1234]).unwrap();
+
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(bytes.len(), 44 + 6);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 36 + 6);
+        assert_eq!(&bytes[8..12], b"WAVE");
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 8000);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 6);
+        assert_eq!(&bytes[44..], &[0x01, 0x00, 0xFF, 0xFF, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn write_stops_at_size_limit() {
+        let path = std::env::temp_dir().join(format!("kaiseki-{}.wav", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let sink = WavAudioSink::new(path, 8000).unwrap();
+        // Pretend the file is two samples short of full.
+        sink.state.lock().unwrap().data_length = MAX_DATA_LENGTH - 5;
+        sink.write(&[1, 2, 3]).unwrap();
+        sink.write(&[4]).unwrap();
+
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            u32::MAX - 1
+        );
+        assert_eq!(
+            u32::from_le_bytes(bytes[40..44].try_into().unwrap()),
+            MAX_DATA_LENGTH - 1
+        );
+        assert_eq!(&bytes[44..], &[0x01, 0x00, 0x02, 0x00]);
+    }
+
+    #[test]
+    fn new_fails_for_bad_path() {
+        assert!(WavAudioSink::new("/nonexistent/dir/out.wav", 8000).is_err());
+    }
+}
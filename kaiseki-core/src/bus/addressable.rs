@@ -7,15 +7,18 @@ use rangemap::RangeInclusiveMap;
 use crate::component::{
     AddressableComponent, AddressableComponentError, Component, ComponentId, Result,
 };
+use crate::debugger::{Debugger, MemoryAccess};
 
 struct AddressableBusState {
     mappings: RangeInclusiveMap<usize, Arc<dyn AddressableComponent>>,
+    debugger: Option<Debugger>,
 }
 
 impl AddressableBusState {
     pub fn new() -> Self {
         Self {
             mappings: RangeInclusiveMap::new(),
+            debugger: None,
         }
     }
 }
@@ -56,15 +59,66 @@ impl Component for AddressableBus {
 
 impl AddressableComponent for AddressableBus {
     fn read(&self, address: usize, length: usize) -> Result<Vec<u8>> {
-        tracing::trace!("bus: reading {} bytes from 0x{:08X}", length, address);
-        let state = self.state.read().unwrap();
+        self.read_traced(address, length, true)
+    }
+
+    fn write(&self, address: usize, data: &[u8]) -> Result<()> {
+        self.write_traced(address, data, true)
+    }
+}
+
+impl AddressableBus {
+    pub fn new(name: &str) -> Self {
+        Self {
+            id: ComponentId::new(name),
+            state: Arc::new(RwLock::new(AddressableBusState::new())),
+        }
+    }
+
+    /// Reads like [`AddressableComponent::read`], but without reporting the access to the
+    /// attached debugger, for tools inspecting memory rather than the machine itself.
+    pub fn inspect(&self, address: usize, length: usize) -> Result<Vec<u8>> {
+        self.read_traced(address, length, false)
+    }
+
+    /// Writes like [`AddressableComponent::write`], but without reporting the access to the
+    /// attached debugger, for tools modifying memory rather than the machine itself.
+    pub fn patch(&self, address: usize, data: &[u8]) -> Result<()> {
+        self.write_traced(address, data, false)
+    }
+
+    /// Finds the component mapped at `address`, along with where its mapping starts, as long
+    /// as the whole `length` bytes from there fall within the mapping.
+    fn find_mapping(
+        state: &AddressableBusState,
+        address: usize,
+        length: usize,
+    ) -> Result<(usize, &Arc<dyn AddressableComponent>)> {
         let (range, component) = state.mappings.get_key_value(&address).ok_or(
             AddressableComponentError::NoComponentMappedAtAddress(address),
         )?;
+        let last = address.checked_add(length.saturating_sub(1));
+        if last.is_none_or(|last| last > *range.end()) {
+            return Err(AddressableComponentError::AccessOutOfMapping(
+                address, length,
+            ));
+        }
+        Ok((*range.start(), component))
+    }
+
+    fn read_traced(&self, address: usize, length: usize, traced: bool) -> Result<Vec<u8>> {
+        tracing::trace!("bus: reading {} bytes from 0x{:08X}", length, address);
+        let state = self.state.read().unwrap();
+        let (start, component) = Self::find_mapping(&state, address, length)?;
 
-        let adjusted_address = address - range.start();
+        let adjusted_address = address - start;
         match component.read(adjusted_address, length) {
-            Ok(bytes) => Ok(bytes),
+            Ok(bytes) => {
+                if let Some(debugger) = state.debugger.as_ref().filter(|_| traced) {
+                    debugger.check_access(MemoryAccess::Read, address, length);
+                }
+                Ok(bytes)
+            }
             Err(_) => Err(AddressableComponentError::ComponentReadFailed(
                 component.id().clone(),
                 address,
@@ -73,16 +127,19 @@ impl AddressableComponent for AddressableBus {
         }
     }
 
-    fn write(&self, address: usize, data: &[u8]) -> Result<()> {
+    fn write_traced(&self, address: usize, data: &[u8], traced: bool) -> Result<()> {
         tracing::trace!("bus: writing {} bytes to 0x{:08X}", data.len(), address);
         let state = self.state.read().unwrap();
-        let (range, component) = state.mappings.get_key_value(&address).ok_or(
-            AddressableComponentError::NoComponentMappedAtAddress(address),
-        )?;
+        let (start, component) = Self::find_mapping(&state, address, data.len())?;
 
-        let adjusted_address = address - range.start();
+        let adjusted_address = address - start;
         match component.write(adjusted_address, data) {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                if let Some(debugger) = state.debugger.as_ref().filter(|_| traced) {
+                    debugger.check_access(MemoryAccess::Write, address, data.len());
+                }
+                Ok(())
+            }
             Err(_) => Err(AddressableComponentError::ComponentWriteFailed(
                 component.id().clone(),
                 address,
@@ -90,14 +147,11 @@ impl AddressableComponent for AddressableBus {
             )),
         }
     }
-}
 
-impl AddressableBus {
-    pub fn new(name: &str) -> Self {
-        Self {
-            id: ComponentId::new(name),
-            state: Arc::new(RwLock::new(AddressableBusState::new())),
-        }
+    /// Reports every successful access through the bus to `debugger`, so that its watchpoints
+    /// can halt the machine.
+    pub fn attach_debugger(&self, debugger: &Debugger) {
+        self.state.write().unwrap().debugger = Some(debugger.clone());
     }
 
     pub fn map(
@@ -152,6 +206,7 @@ mod tests {
         AddressableBus, AddressableComponent, AddressableComponentError, Component, ComponentId,
         Result,
     };
+    use crate::debugger::{DebugEvent, Debugger, MemoryAccess, Watchpoint};
 
     #[derive(Clone)]
     struct TestComponent {
@@ -220,6 +275,30 @@ mod tests {
         assert!(bus.read(0x0000, 4).is_ok());
     }
 
+    #[test]
+    fn accesses_must_fit_in_one_mapping() {
+        let ([a, b, _], bus) = setup();
+        bus.map(0x0000..=0x00FF, a.clone()).unwrap();
+        bus.map(0x0100..=0x01FF, b.clone()).unwrap();
+
+        assert!(bus.read(0x00FF, 1).is_ok());
+        assert_eq!(
+            bus.read(0x00FF, 2),
+            Err(AddressableComponentError::AccessOutOfMapping(0x00FF, 2))
+        );
+        assert_eq!(
+            bus.write(0x01FF, &[0x00, 0x00]),
+            Err(AddressableComponentError::AccessOutOfMapping(0x01FF, 2))
+        );
+        assert_eq!(
+            bus.inspect(0x0100, usize::MAX),
+            Err(AddressableComponentError::AccessOutOfMapping(
+                0x0100,
+                usize::MAX
+            ))
+        );
+    }
+
     #[test]
     fn map_prevents_conflicts() {
         let ([a, b, _], bus) = setup();
@@ -278,4 +357,26 @@ mod tests {
             AddressableComponentError::MappingConflict(b_id.clone(), b_range, a_id.clone(),)
         );
     }
+
+    #[test]
+    fn attached_debugger_sees_accesses() {
+        let ([a, _, _], bus) = setup();
+        bus.map(0x1000..=0x13FF, a.clone()).unwrap();
+        let debugger = Debugger::new();
+        let id = debugger.add_watchpoint(Watchpoint::writes(0x1100..=0x1100));
+        bus.write(0x1100, &[0xAA]).unwrap();
+        assert_eq!(debugger.halted(), None);
+
+        bus.attach_debugger(&debugger);
+        bus.read(0x1100, 1).unwrap();
+        bus.patch(0x1100, &[0xBB]).unwrap();
+        assert_eq!(bus.inspect(0x1100, 1).unwrap(), vec![0xBB]);
+        assert_eq!(debugger.halted(), None);
+        bus.write(0x10FF, &[0xBB, 0xCC]).unwrap();
+        assert!(matches!(
+            debugger.halted(),
+            Some(DebugEvent::Watchpoint { id: hit, access: MemoryAccess::Write, address: 0x10FF, .. })
+                if hit == id
+        ));
+    }
 }
@@ -5,6 +5,8 @@ use async_trait::async_trait;
 use thiserror::Error;
 use uuid::Uuid;
 
+use crate::lifecycle::Lifecycle;
+
 #[derive(Debug, Eq, Hash, PartialEq)]
 pub struct ComponentId {
     name: String,
@@ -54,6 +56,8 @@ pub enum AddressableComponentError {
     ComponentReadFailed(ComponentId, usize, usize),
     #[error("component {0} failed to write {2} bytes at address 0x{1:04X}")]
     ComponentWriteFailed(ComponentId, usize, usize),
+    #[error("access of {1} bytes at address 0x{0:04X} runs past the end of its mapping")]
+    AccessOutOfMapping(usize, usize),
     #[error("cannot map component {0} to {1:?}; conflicts with already-mapped component {2}")]
     MappingConflict(ComponentId, RangeInclusive<usize>, ComponentId),
 }
@@ -72,7 +76,48 @@ impl PartialEq for dyn AddressableComponent + '_ {
 }
 impl Eq for dyn AddressableComponent + '_ {}
 
+/// A component that can be returned to the state it was in when created.
+#[async_trait]
+pub trait ResettableComponent: Component {
+    async fn reset(&self);
+}
+
+/// Why an executable component stopped executing, such as a CPU hitting an invalid opcode.
+#[derive(Clone, Debug, Eq, Error, PartialEq)]
+pub struct Fault {
+    /// Name of the component that faulted.
+    pub component: String,
+    pub reason: String,
+    /// Address of the instruction that faulted, if the component executes instructions.
+    pub address: Option<usize>,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} halted: {}", self.component, self.reason)?;
+        if let Some(address) = self.address {
+            write!(f, " at 0x{:04X}", address)?;
+        }
+        Ok(())
+    }
+}
+
+impl Fault {
+    pub fn new(component: &ComponentId, reason: &str, address: Option<usize>) -> Self {
+        Self {
+            component: component.to_string(),
+            reason: String::from(reason),
+            address,
+        }
+    }
+}
+
 #[async_trait]
 pub trait ExecutableComponent: Component {
-    async fn start(&self);
+    /// Runs the component until `lifecycle` is stopped or faulted, idling while it's paused.
+    /// A component that faults moves `lifecycle` to [`LifecycleState::Faulted`] so the rest of
+    /// the machine winds down, and returns the fault.
+    ///
+    /// [`LifecycleState::Faulted`]: crate::LifecycleState::Faulted
+    async fn start(&self, lifecycle: &Lifecycle) -> std::result::Result<(), Fault>;
 }
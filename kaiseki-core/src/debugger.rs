@@ -0,0 +1,507 @@
+//! Breakpoints and watchpoints that halt a machine and report which of them fired.
+//!
+//! A [`Debugger`] is shared between a machine's CPU, which checks breakpoints before each
+//! instruction, and its [`AddressableBus`](crate::AddressableBus), which checks watchpoints on
+//! every access made through it. Once halted, the debugger stays halted until
+//! [`Debugger::resume`] is called.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn compare(&self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+            Comparison::Less => lhs < rhs,
+            Comparison::LessOrEqual => lhs <= rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterOrEqual => lhs >= rhs,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        })
+    }
+}
+
+/// A condition on one of the registers named by
+/// [`Machine::registers`](crate::machine::Machine::registers).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RegisterCondition {
+    pub register: String,
+    pub comparison: Comparison,
+    pub value: usize,
+}
+
+impl RegisterCondition {
+    pub fn new(register: &str, comparison: Comparison, value: usize) -> Self {
+        Self {
+            register: String::from(register),
+            comparison,
+            value,
+        }
+    }
+
+    /// Whether the condition holds for `registers`. Never holds for unknown registers.
+    fn holds(&self, registers: &[(String, usize)]) -> bool {
+        registers
+            .iter()
+            .find(|(name, _)| *name == self.register)
+            .is_some_and(|(_, value)| self.comparison.compare(*value, self.value))
+    }
+}
+
+impl fmt::Display for RegisterCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} 0x{:04X}",
+            self.register, self.comparison, self.value
+        )
+    }
+}
+
+/// Halts the machine before it executes an instruction at an address, when a register
+/// condition holds, or both.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Breakpoint {
+    pub address: Option<usize>,
+    pub condition: Option<RegisterCondition>,
+}
+
+impl Breakpoint {
+    /// Halts before the instruction at `address`.
+    pub fn at(address: usize) -> Self {
+        Self {
+            address: Some(address),
+            condition: None,
+        }
+    }
+
+    /// Halts before any instruction once `condition` holds.
+    pub fn when(condition: RegisterCondition) -> Self {
+        Self {
+            address: None,
+            condition: Some(condition),
+        }
+    }
+
+    /// Only halts if `condition` holds as well.
+    pub fn with_condition(mut self, condition: RegisterCondition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    fn matches(&self, pc: usize, registers: &[(String, usize)]) -> bool {
+        self.address.is_none_or(|address| address == pc)
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.holds(registers))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.address, &self.condition) {
+            (Some(address), None) => write!(f, "at 0x{:04X}", address),
+            (Some(address), Some(condition)) => {
+                write!(f, "at 0x{:04X} if {}", address, condition)
+            }
+            (None, Some(condition)) => write!(f, "when {}", condition),
+            (None, None) => f.write_str("on every instruction"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+}
+
+impl fmt::Display for MemoryAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MemoryAccess::Read => "read",
+            MemoryAccess::Write => "write",
+        })
+    }
+}
+
+/// Halts the machine after an instruction reads or writes any byte in a range of addresses
+/// through the memory bus. Instruction fetches count as reads.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<usize>,
+    pub on_read: bool,
+    pub on_write: bool,
+}
+
+impl Watchpoint {
+    pub fn reads(range: RangeInclusive<usize>) -> Self {
+        Self {
+            range,
+            on_read: true,
+            on_write: false,
+        }
+    }
+
+    pub fn writes(range: RangeInclusive<usize>) -> Self {
+        Self {
+            range,
+            on_read: false,
+            on_write: true,
+        }
+    }
+
+    pub fn accesses(range: RangeInclusive<usize>) -> Self {
+        Self {
+            range,
+            on_read: true,
+            on_write: true,
+        }
+    }
+
+    fn matches(&self, access: MemoryAccess, address: usize, length: usize) -> bool {
+        let watched = match access {
+            MemoryAccess::Read => self.on_read,
+            MemoryAccess::Write => self.on_write,
+        };
+        let last = address + length.max(1) - 1;
+        watched && address <= *self.range.end() && last >= *self.range.start()
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match (self.on_read, self.on_write) {
+            (true, true) => "accesses",
+            (true, false) => "reads",
+            (false, true) => "writes",
+            (false, false) => "nothing",
+        };
+        write!(
+            f,
+            "{} of 0x{:04X} - 0x{:04X}",
+            kind,
+            self.range.start(),
+            self.range.end()
+        )
+    }
+}
+
+/// Why a debugger halted the machine.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DebugEvent {
+    Breakpoint {
+        id: usize,
+        breakpoint: Breakpoint,
+        pc: usize,
+    },
+    Watchpoint {
+        id: usize,
+        watchpoint: Watchpoint,
+        access: MemoryAccess,
+        address: usize,
+        length: usize,
+    },
+}
+
+impl fmt::Display for DebugEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DebugEvent::Breakpoint { id, breakpoint, pc } => {
+                write!(f, "breakpoint {} ({}) hit at 0x{:04X}", id, breakpoint, pc)
+            }
+            DebugEvent::Watchpoint {
+                id,
+                watchpoint,
+                access,
+                address,
+                length,
+            } => write!(
+                f,
+                "watchpoint {} ({}) hit by {}-byte {} at 0x{:04X}",
+                id, watchpoint, length, access, address
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DebuggerState {
+    next_id: usize,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    halt: Option<DebugEvent>,
+    /// Address of the breakpoint the machine was resumed from, which mustn't immediately halt
+    /// it again.
+    resume_pc: Option<usize>,
+}
+
+impl DebuggerState {
+    fn halt(&mut self, event: DebugEvent) {
+        tracing::info!("debugger halted: {}", event);
+        self.halt = Some(event);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    state: Arc<Mutex<DebuggerState>>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a breakpoint, returning its ID. IDs are shared with watchpoints.
+    pub fn add_breakpoint(&self, breakpoint: Breakpoint) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.breakpoints.insert(id, breakpoint);
+        id
+    }
+
+    /// Removes the breakpoint with the given ID, returning whether there was one.
+    pub fn remove_breakpoint(&self, id: usize) -> bool {
+        self.state.lock().unwrap().breakpoints.remove(&id).is_some()
+    }
+
+    pub fn breakpoints(&self) -> Vec<(usize, Breakpoint)> {
+        let state = self.state.lock().unwrap();
+        state
+            .breakpoints
+            .iter()
+            .map(|(id, breakpoint)| (*id, breakpoint.clone()))
+            .collect()
+    }
+
+    /// Adds a watchpoint, returning its ID. IDs are shared with breakpoints.
+    pub fn add_watchpoint(&self, watchpoint: Watchpoint) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.watchpoints.insert(id, watchpoint);
+        id
+    }
+
+    /// Removes the watchpoint with the given ID, returning whether there was one.
+    pub fn remove_watchpoint(&self, id: usize) -> bool {
+        self.state.lock().unwrap().watchpoints.remove(&id).is_some()
+    }
+
+    pub fn watchpoints(&self) -> Vec<(usize, Watchpoint)> {
+        let state = self.state.lock().unwrap();
+        state
+            .watchpoints
+            .iter()
+            .map(|(id, watchpoint)| (*id, watchpoint.clone()))
+            .collect()
+    }
+
+    /// Whether any breakpoints are set, so that CPUs can skip gathering register values for
+    /// [`Debugger::check_breakpoints`] when there aren't.
+    pub fn has_breakpoints(&self) -> bool {
+        !self.state.lock().unwrap().breakpoints.is_empty()
+    }
+
+    /// Why the machine is halted, if it is.
+    pub fn halted(&self) -> Option<DebugEvent> {
+        self.state.lock().unwrap().halt.clone()
+    }
+
+    /// Clears the halt so the machine can continue. Resuming from a breakpoint lets the
+    /// instruction it halted on execute rather than halting on it again.
+    pub fn resume(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(DebugEvent::Breakpoint { pc, .. }) = state.halt.take() {
+            state.resume_pc = Some(pc);
+        }
+    }
+
+    /// Forgets any halt, along with the instruction a resume would let execute, so a machine
+    /// starting over halts on every breakpoint it reaches. Breakpoints themselves are kept.
+    pub fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.halt = None;
+        state.resume_pc = None;
+    }
+
+    /// Called by CPUs before executing the instruction at `pc`, with the machine's current
+    /// register values. Returns whether the machine is halted, either already or because a
+    /// breakpoint fired.
+    pub fn check_breakpoints(&self, pc: usize, registers: &[(String, usize)]) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.halt.is_some() {
+            return true;
+        }
+        if state.resume_pc.take() == Some(pc) {
+            return false;
+        }
+
+        let hit = state
+            .breakpoints
+            .iter()
+            .find(|(_, breakpoint)| breakpoint.matches(pc, registers))
+            .map(|(id, breakpoint)| (*id, breakpoint.clone()));
+        match hit {
+            Some((id, breakpoint)) => {
+                state.halt(DebugEvent::Breakpoint { id, breakpoint, pc });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Called by the memory bus for every access made through it. Halts the machine if the
+    /// access hits a watchpoint and it isn't halted already.
+    pub fn check_access(&self, access: MemoryAccess, address: usize, length: usize) {
+        let mut state = self.state.lock().unwrap();
+        if state.halt.is_some() {
+            return;
+        }
+
+        let hit = state
+            .watchpoints
+            .iter()
+            .find(|(_, watchpoint)| watchpoint.matches(access, address, length))
+            .map(|(id, watchpoint)| (*id, watchpoint.clone()));
+        if let Some((id, watchpoint)) = hit {
+            state.halt(DebugEvent::Watchpoint {
+                id,
+                watchpoint,
+                access,
+                address,
+                length,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Breakpoint, Comparison, DebugEvent, Debugger, MemoryAccess, RegisterCondition, Watchpoint,
+    };
+
+    fn registers(v0: usize) -> Vec<(String, usize)> {
+        vec![(String::from("V0"), v0), (String::from("PC"), 0x200)]
+    }
+
+    #[test]
+    fn breakpoints_halt_at_addresses_and_conditions() {
+        let debugger = Debugger::new();
+        assert!(!debugger.check_breakpoints(0x200, &registers(0)));
+
+        let at = debugger.add_breakpoint(Breakpoint::at(0x204));
+        let condition = RegisterCondition::new("V0", Comparison::GreaterOrEqual, 3);
+        let when = debugger.add_breakpoint(Breakpoint::when(condition.clone()));
+        assert!(!debugger.check_breakpoints(0x202, &registers(2)));
+
+        assert!(debugger.check_breakpoints(0x202, &registers(3)));
+        assert_eq!(
+            debugger.halted(),
+            Some(DebugEvent::Breakpoint {
+                id: when,
+                breakpoint: Breakpoint::when(condition),
+                pc: 0x202,
+            })
+        );
+        // Stays halted until resumed.
+        assert!(debugger.check_breakpoints(0x300, &registers(0)));
+
+        debugger.resume();
+        assert!(debugger.check_breakpoints(0x204, &registers(0)));
+        assert!(matches!(
+            debugger.halted(),
+            Some(DebugEvent::Breakpoint { id, pc: 0x204, .. }) if id == at
+        ));
+        assert_eq!(
+            debugger.halted().unwrap().to_string(),
+            "breakpoint 1 (at 0x0204) hit at 0x0204"
+        );
+    }
+
+    #[test]
+    fn resuming_skips_the_breakpoint_once() {
+        let debugger = Debugger::new();
+        let condition = RegisterCondition::new("V0", Comparison::Equal, 1);
+        let id = debugger.add_breakpoint(Breakpoint::at(0x200).with_condition(condition));
+        assert!(!debugger.check_breakpoints(0x200, &registers(0)));
+        assert!(debugger.check_breakpoints(0x200, &registers(1)));
+
+        debugger.resume();
+        assert!(!debugger.check_breakpoints(0x200, &registers(1)));
+        assert!(debugger.check_breakpoints(0x200, &registers(1)));
+
+        debugger.resume();
+        assert!(debugger.remove_breakpoint(id));
+        assert!(!debugger.remove_breakpoint(id));
+        assert!(!debugger.has_breakpoints());
+        assert!(!debugger.check_breakpoints(0x200, &registers(1)));
+    }
+
+    #[test]
+    fn watchpoints_halt_on_overlapping_accesses() {
+        let debugger = Debugger::new();
+        let writes = debugger.add_watchpoint(Watchpoint::writes(0x300..=0x303));
+        debugger.add_watchpoint(Watchpoint::reads(0x400..=0x400));
+
+        debugger.check_access(MemoryAccess::Read, 0x300, 4);
+        debugger.check_access(MemoryAccess::Write, 0x2FE, 2);
+        debugger.check_access(MemoryAccess::Write, 0x400, 1);
+        assert_eq!(debugger.halted(), None);
+
+        debugger.check_access(MemoryAccess::Write, 0x2FE, 3);
+        assert_eq!(
+            debugger.halted(),
+            Some(DebugEvent::Watchpoint {
+                id: writes,
+                watchpoint: Watchpoint::writes(0x300..=0x303),
+                access: MemoryAccess::Write,
+                address: 0x2FE,
+                length: 3,
+            })
+        );
+
+        // The first watchpoint to fire is the one reported.
+        debugger.check_access(MemoryAccess::Read, 0x400, 1);
+        assert!(matches!(
+            debugger.halted(),
+            Some(DebugEvent::Watchpoint { id, .. }) if id == writes
+        ));
+
+        debugger.resume();
+        debugger.check_access(MemoryAccess::Read, 0x400, 1);
+        assert_eq!(
+            debugger.halted().unwrap().to_string(),
+            "watchpoint 2 (reads of 0x0400 - 0x0400) hit by 1-byte read at 0x0400"
+        );
+    }
+}
@@ -1,7 +1,13 @@
+pub mod audio;
 mod bus;
 mod component;
+pub mod debugger;
+mod lifecycle;
 pub mod machine;
+pub mod movie;
 mod oscillator;
+mod rewind;
+pub mod snapshot;
 mod storage;
 mod vex;
 
@@ -10,8 +16,9 @@ pub use crate::bus::{
 };
 pub use crate::component::{
     AddressableComponent, AddressableComponentError, Component, ComponentId, ExecutableComponent,
-    Result,
+    Fault, ResettableComponent, Result,
 };
+pub use crate::lifecycle::{Lifecycle, LifecycleState};
 pub use crate::oscillator::{Oscillator, OscillatorBus, OscillatorBusMessage};
 pub use crate::storage::{RAM, ROM};
-pub use crate::vex::Vex;
+pub use crate::vex::{Vex, VexError};
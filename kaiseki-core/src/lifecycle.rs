@@ -0,0 +1,176 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LifecycleState {
+    Running,
+    Paused,
+    /// A component faulted. Components wind down as though stopped, but the machine can be
+    /// restarted once it's been dealt with.
+    Faulted,
+    /// Final; a stopped lifecycle can't be paused or resumed.
+    Stopped,
+}
+
+/// Shared run state of a machine and its executable components, which check it between
+/// units of work to pause, resume or stop.
+#[derive(Clone, Debug)]
+pub struct Lifecycle {
+    state: Arc<watch::Sender<LifecycleState>>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(LifecycleState::Running);
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    pub fn state(&self) -> LifecycleState {
+        *self.state.borrow()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state() == LifecycleState::Running
+    }
+
+    pub fn pause(&self) {
+        self.transition(LifecycleState::Paused, &[LifecycleState::Running]);
+    }
+
+    pub fn resume(&self) {
+        self.transition(LifecycleState::Running, &[LifecycleState::Paused]);
+    }
+
+    /// Marks the lifecycle as faulted, which winds components down like stopping does.
+    pub fn fault(&self) {
+        self.transition(
+            LifecycleState::Faulted,
+            &[LifecycleState::Running, LifecycleState::Paused],
+        );
+    }
+
+    /// Runs again after a fault, for a machine that's about to be started again.
+    pub fn restart(&self) {
+        self.transition(LifecycleState::Running, &[LifecycleState::Faulted]);
+    }
+
+    pub fn stop(&self) {
+        self.transition(
+            LifecycleState::Stopped,
+            &[
+                LifecycleState::Running,
+                LifecycleState::Paused,
+                LifecycleState::Faulted,
+            ],
+        );
+    }
+
+    /// Moves to `new_state` if currently in one of the states in `from`.
+    fn transition(&self, new_state: LifecycleState, from: &[LifecycleState]) {
+        self.state.send_if_modified(|state| {
+            if !from.contains(state) {
+                return false;
+            }
+            tracing::info!("lifecycle {:?} => {:?}", state, new_state);
+            *state = new_state;
+            true
+        });
+    }
+
+    /// Waits while paused, returning `false` if stopped or faulted and `true` once running.
+    pub async fn wait_until_running(&self) -> bool {
+        let mut receiver = self.state.subscribe();
+        let state = receiver
+            .wait_for(|state| *state != LifecycleState::Paused)
+            .await
+            .map(|state| *state)
+            .unwrap_or(LifecycleState::Stopped);
+        state == LifecycleState::Running
+    }
+
+    /// Waits while running, returning the state once paused, faulted or stopped.
+    pub async fn wait_while_running(&self) -> LifecycleState {
+        let mut receiver = self.state.subscribe();
+        receiver
+            .wait_for(|state| *state != LifecycleState::Running)
+            .await
+            .map(|state| *state)
+            .unwrap_or(LifecycleState::Stopped)
+    }
+
+    /// Waits until stopped or faulted, either of which should wind components down.
+    pub async fn stopped(&self) {
+        let mut receiver = self.state.subscribe();
+        let _ = receiver
+            .wait_for(|state| matches!(state, LifecycleState::Faulted | LifecycleState::Stopped))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Lifecycle, LifecycleState};
+
+    #[tokio::test]
+    async fn pause_resume_stop_works() {
+        let lifecycle = Lifecycle::new();
+        assert!(lifecycle.wait_until_running().await);
+
+        lifecycle.pause();
+        assert_eq!(lifecycle.state(), LifecycleState::Paused);
+        let waiter = {
+            let lifecycle = lifecycle.clone();
+            tokio::spawn(async move { lifecycle.wait_until_running().await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+        lifecycle.resume();
+        assert!(waiter.await.unwrap());
+
+        lifecycle.pause();
+        assert_eq!(lifecycle.wait_while_running().await, LifecycleState::Paused);
+        lifecycle.stop();
+        assert!(!lifecycle.wait_until_running().await);
+        lifecycle.stopped().await;
+
+        // Stopping is final.
+        lifecycle.resume();
+        lifecycle.restart();
+        assert_eq!(lifecycle.state(), LifecycleState::Stopped);
+    }
+
+    #[tokio::test]
+    async fn fault_winds_down_until_restarted() {
+        let lifecycle = Lifecycle::new();
+        lifecycle.fault();
+        assert_eq!(lifecycle.state(), LifecycleState::Faulted);
+        assert!(!lifecycle.wait_until_running().await);
+        assert_eq!(
+            lifecycle.wait_while_running().await,
+            LifecycleState::Faulted
+        );
+        lifecycle.stopped().await;
+
+        // Only a restart runs again.
+        lifecycle.pause();
+        lifecycle.resume();
+        assert_eq!(lifecycle.state(), LifecycleState::Faulted);
+        lifecycle.restart();
+        assert_eq!(lifecycle.state(), LifecycleState::Running);
+
+        lifecycle.fault();
+        lifecycle.stop();
+        assert_eq!(lifecycle.state(), LifecycleState::Stopped);
+    }
+}
@@ -1,7 +1,14 @@
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
 use thiserror::Error;
 
 use crate::{
-    component::{AddressableComponentError, ExecutableComponent},
+    component::{AddressableComponentError, ExecutableComponent, Fault},
+    debugger::Debugger,
+    movie::Movie,
+    snapshot::{Snapshot, SnapshotError},
     MessageBusError,
 };
 
@@ -13,11 +20,113 @@ pub enum MachineError {
     MessageBus(#[from] MessageBusError),
     #[error("failed to load '{0}' into memory at 0x{1:04X}")]
     FileLoad(String, usize),
+    #[error("failed to read '{0}': {1}")]
+    FileRead(String, String),
+    #[error("'{0}' is {1} bytes, but only {2} bytes of memory are available for programs")]
+    FileTooLarge(String, usize, usize),
+    #[error(transparent)]
+    Snapshot(#[from] SnapshotError),
 }
 
 pub type Result<T> = std::result::Result<T, MachineError>;
 
+/// How much of a machine [`Machine::reset`] resets.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ResetKind {
+    /// Like pressing a reset button: the CPU, display, input and timers start over, but memory
+    /// keeps its contents, including the program.
+    Soft,
+    /// Like cycling the power: memory is cleared as well, so the program has to be loaded
+    /// again.
+    Hard,
+}
+
+/// Receives a machine's state as frames end, such as to keep states to rewind to.
+pub trait FrameObserver: fmt::Debug + Send + Sync {
+    /// Whether to capture the machine's state at the end of `frame`; checked first, so frames
+    /// that aren't wanted cost nothing.
+    fn wants_frame(&self, frame: usize) -> bool;
+
+    /// Receives the machine's state as it was at the end of `frame`.
+    fn frame_ended(&self, frame: usize, snapshot: Snapshot);
+}
+
+#[async_trait]
 pub trait Machine: ExecutableComponent {
     fn get_frame(&self) -> (usize, usize, Vec<u8>);
     fn load(&self, file: &str) -> Result<()>;
+    fn press_key(&self, key: usize);
+    fn release_key(&self, key: usize);
+
+    /// Number of cycles of the machine's main clock in each frame the machine displays.
+    fn cycles_per_frame(&self) -> usize;
+
+    /// Names and current values of the machine's registers, in the machine's preferred order.
+    async fn registers(&self) -> Vec<(String, usize)>;
+
+    /// Reads memory as addressed by the machine's CPU, without tripping watchpoints.
+    fn read_memory(&self, address: usize, length: usize) -> Result<Vec<u8>>;
+
+    /// Writes memory as addressed by the machine's CPU, without tripping watchpoints.
+    fn write_memory(&self, address: usize, data: &[u8]) -> Result<()>;
+
+    /// Debugger whose breakpoints and watchpoints halt the machine. Halting pauses a started
+    /// machine, and ends [`Machine::run_cycles`] and [`Machine::run_frame`] early.
+    fn debugger(&self) -> &Debugger;
+
+    /// Number of cycles run so far by [`Machine::step`], [`Machine::run_cycles`] and
+    /// [`Machine::run_frame`].
+    fn cycles_run(&self) -> usize;
+
+    /// Number of the frame the machine is on, counting from the last reset whether it's
+    /// running on its own or being stepped.
+    fn current_frame(&self) -> usize;
+
+    /// Hands `observer` the machine's state at the end of each frame it wants, between cycles,
+    /// whether the machine is running on its own or being run by [`Machine::run_cycles`] and
+    /// [`Machine::run_frame`]. Replaces any observer set before.
+    fn observe_frames(&self, observer: Arc<dyn FrameObserver>);
+
+    /// Executes exactly one instruction. Defaults to running a single cycle, for machines that
+    /// execute one instruction per cycle.
+    async fn step(&self) -> std::result::Result<(), Fault> {
+        self.run_cycles(1).await
+    }
+
+    /// Runs `cycles` cycles of the machine's main clock as fast as possible, rather than at
+    /// the pace set by its oscillators. Must not be called while the machine is started and
+    /// running. Stops at the first fault, leaving the machine as it was when it faulted.
+    async fn run_cycles(&self, cycles: usize) -> std::result::Result<(), Fault>;
+
+    /// Runs until the end of the current frame as fast as possible, returning the number of
+    /// cycles run, which is 0 if the machine was halted before running any. Must not be
+    /// called while the machine is started and running. Stops at the first fault, like
+    /// [`Machine::run_cycles`].
+    async fn run_frame(&self) -> std::result::Result<usize, Fault>;
+
+    /// Resets the machine as described by `kind`, leaving its debugger's breakpoints and
+    /// watchpoints in place. Can be called while the machine is running.
+    async fn reset(&self, kind: ResetKind);
+
+    /// Captures the complete state of the machine.
+    async fn snapshot(&self) -> Snapshot;
+
+    /// Restores the machine to a state captured by [`Machine::snapshot`].
+    async fn restore(&self, snapshot: &Snapshot) -> Result<()>;
+
+    /// Seed the machine's random numbers are generated from.
+    fn seed(&self) -> u64;
+
+    /// Starts recording every key pressed or released from the next cycle on, discarding
+    /// anything recorded so far.
+    fn start_recording(&self);
+
+    /// Stops recording, returning everything recorded since [`Machine::start_recording`], or
+    /// `None` if the machine wasn't recording.
+    fn stop_recording(&self) -> Option<Movie>;
+
+    /// Presses and releases keys at the cycles `movie` recorded them at, which count from the
+    /// last hard reset. Keys pressed and released with [`Machine::press_key`] and
+    /// [`Machine::release_key`] are ignored until the movie ends or the machine is reset.
+    fn play_movie(&self, movie: &Movie);
 }
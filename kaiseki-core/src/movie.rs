@@ -0,0 +1,193 @@
+//! Recordings of a machine's input that replay it exactly.
+//!
+//! A [`Movie`] holds every key a machine saw pressed or released after a hard reset, each
+//! stamped with the number of the system oscillator cycle it took effect before. Replaying
+//! the events at the same cycles into a machine that was hard reset with the same program
+//! and seed reproduces the recorded run frame for frame, whether either run was stepped or
+//! left to run on its own.
+//!
+//! On disk, a movie is laid out as follows, with all values little-endian:
+//!
+//! | Size          | Contents                                          |
+//! |---------------|---------------------------------------------------|
+//! | 4 bytes       | magic: `KMOV`                                     |
+//! | 2 bytes       | format version, currently `1`                     |
+//! | 8 bytes       | seed of the machine's random numbers              |
+//! | 4 bytes       | number of events                                  |
+//! | for each event, in cycle order:                                   |
+//! | 8 bytes       | cycle the event took effect before                |
+//! | 2 bytes       | key                                               |
+//! | 1 byte        | `1` if the key was pressed, `0` if released       |
+
+use std::fs;
+
+use thiserror::Error;
+
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+
+const MOVIE_MAGIC: &[u8; 4] = b"KMOV";
+const MOVIE_VERSION: u16 = 1;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MovieError {
+    #[error("data is not a kaiseki movie")]
+    BadMagic,
+    #[error("movie format version {0} is not supported")]
+    UnsupportedVersion(u16),
+    #[error("invalid movie: {0}")]
+    Invalid(String),
+    #[error("movie was recorded with seed {0}, but the machine's seed is {1}")]
+    SeedMismatch(u64, u64),
+    #[error("failed to access movie file '{0}': {1}")]
+    File(String, String),
+}
+
+impl From<SnapshotError> for MovieError {
+    fn from(error: SnapshotError) -> Self {
+        MovieError::Invalid(error.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, MovieError>;
+
+/// A key being pressed or released before a given cycle.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InputEvent {
+    pub cycle: usize,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+/// Input recorded from a machine that started out freshly hard reset.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Movie {
+    seed: u64,
+    events: Vec<InputEvent>,
+}
+
+impl Movie {
+    /// Creates a movie of a machine seeded with `seed`. Events must be in cycle order.
+    pub fn new(seed: u64, events: Vec<InputEvent>) -> Self {
+        assert!(events.windows(2).all(|pair| pair[0].cycle <= pair[1].cycle));
+        Self { seed, events }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn events(&self) -> &[InputEvent] {
+        &self.events
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        MOVIE_MAGIC.iter().for_each(|b| writer.write_u8(*b));
+        writer.write_u16(MOVIE_VERSION);
+        writer.write_u64(self.seed);
+        writer.write_u32(self.events.len() as u32);
+        for event in self.events.iter() {
+            writer.write_usize(event.cycle);
+            writer.write_u16(event.key as u16);
+            writer.write_bool(event.pressed);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = StateReader::new(data);
+        if reader.take(4)? != MOVIE_MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+
+        let seed = reader.read_u64()?;
+        let mut events: Vec<InputEvent> = Vec::new();
+        for _ in 0..reader.read_u32()? {
+            let event = InputEvent {
+                cycle: reader.read_usize()?,
+                key: reader.read_u16()? as usize,
+                pressed: reader.read_bool()?,
+            };
+            if events.last().is_some_and(|last| last.cycle > event.cycle) {
+                return Err(MovieError::Invalid(format!(
+                    "event at cycle {} is out of order",
+                    event.cycle
+                )));
+            }
+            events.push(event);
+        }
+        reader.finish()?;
+        Ok(Self { seed, events })
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        fs::write(path, self.to_bytes())
+            .map_err(|e| MovieError::File(String::from(path), e.to_string()))
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let data =
+            fs::read(path).map_err(|e| MovieError::File(String::from(path), e.to_string()))?;
+        Self::from_bytes(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InputEvent, Movie, MovieError};
+
+    fn event(cycle: usize, key: usize, pressed: bool) -> InputEvent {
+        InputEvent {
+            cycle,
+            key,
+            pressed,
+        }
+    }
+
+    #[test]
+    fn movie_round_trips_through_bytes() {
+        let movie = Movie::new(
+            0x0123_4567_89AB_CDEF,
+            vec![
+                event(10, 0x5, true),
+                event(10, 0x6, true),
+                event(700, 0x5, false),
+            ],
+        );
+        let bytes = movie.to_bytes();
+        assert_eq!(&bytes[..6], b"KMOV\x01\x00");
+        assert_eq!(bytes.len(), 4 + 2 + 8 + 4 + 3 * 11);
+        assert_eq!(Movie::from_bytes(&bytes), Ok(movie));
+    }
+
+    #[test]
+    fn movie_rejects_bad_data() {
+        let bytes = Movie::new(1, vec![event(5, 1, true), event(9, 1, false)]).to_bytes();
+        assert_eq!(Movie::from_bytes(b"KSNP"), Err(MovieError::BadMagic));
+
+        let mut future = bytes.clone();
+        future[4] = 2;
+        assert_eq!(
+            Movie::from_bytes(&future),
+            Err(MovieError::UnsupportedVersion(2))
+        );
+
+        assert!(matches!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MovieError::Invalid(_))
+        ));
+
+        // Swap the events' cycles so they're out of order.
+        let mut unordered = bytes.clone();
+        unordered[18] = 9;
+        unordered[29] = 5;
+        assert!(matches!(
+            Movie::from_bytes(&unordered),
+            Err(MovieError::Invalid(_))
+        ));
+    }
+}
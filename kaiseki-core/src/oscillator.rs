@@ -1,9 +1,12 @@
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use async_trait::async_trait;
 
 use crate::bus::{BusMessage, MessageBus, MessageBusError};
-use crate::component::{Component, ComponentId, ExecutableComponent};
+use crate::component::{Component, ComponentId, ExecutableComponent, Fault, ResettableComponent};
+use crate::lifecycle::Lifecycle;
+use crate::snapshot::{self, StateReader, StateWriter, StatefulComponent};
 
 #[derive(Clone, Debug)]
 pub enum OscillatorBusMessage {
@@ -49,6 +52,9 @@ pub struct Oscillator {
     bus: OscillatorBus,
     frequency_hz: f64,
     period: std::time::Duration,
+    /// Number of the next cycle to hand out, which carries over between runs and is restored
+    /// from snapshots.
+    current_cycle: AtomicUsize,
 }
 
 impl Component for Oscillator {
@@ -59,36 +65,52 @@ impl Component for Oscillator {
 
 #[async_trait]
 impl ExecutableComponent for Oscillator {
-    async fn start(&self) {
+    async fn start(&self, lifecycle: &Lifecycle) -> Result<(), Fault> {
         tracing::info!(
             "starting oscillator with frequency {}hz / period {}ns",
             self.frequency_hz,
             self.period.as_nanos()
         );
 
-        let start_time = tokio::time::Instant::now();
+        let mut start_time = tokio::time::Instant::now();
         let mut current_period = self.period;
         let mut next_period = self.period;
-        let mut current_cycle: usize = 0;
+        let mut elapsed_cycles: usize = 0;
         let mut cycle_budget: usize = self.frequency_hz as usize;
 
         loop {
+            if !lifecycle.is_running() {
+                if !lifecycle.wait_until_running().await {
+                    break;
+                }
+                // Start timing afresh rather than trying to catch up on the time spent paused.
+                start_time = tokio::time::Instant::now();
+                elapsed_cycles = 0;
+                cycle_budget = self.frequency_hz as usize;
+            }
+
+            let current_cycle = self.current_cycle.load(Ordering::SeqCst);
             tracing::info!(
                 "starting cycles {} - {}",
                 current_cycle,
                 current_cycle + cycle_budget
             );
             let period_start = tokio::time::Instant::now();
-            let (start_cycle, cycles_spent) = self
-                .bus
-                .tick(&self.id, current_cycle, cycle_budget)
-                .await
-                .unwrap();
+            let (start_cycle, cycles_spent) = tokio::select! {
+                result = self.bus.tick(&self.id, current_cycle, cycle_budget) => result.unwrap(),
+                _ = lifecycle.stopped() => break,
+            };
 
             assert!(current_cycle == start_cycle);
+            // A component may pause the machine before spending any cycles, such as when the
+            // debugger halts it; hand out the same batch again once resumed.
+            if cycles_spent == 0 && !lifecycle.is_running() {
+                continue;
+            }
             assert!(cycles_spent > 0);
             let cycles_executed = cycles_spent;
             let end_cycle = start_cycle + cycles_executed;
+            elapsed_cycles += cycles_executed;
             match cycles_spent.cmp(&cycle_budget) {
                 std::cmp::Ordering::Less => {
                     tracing::info!(
@@ -110,7 +132,7 @@ impl ExecutableComponent for Oscillator {
             let period_end = tokio::time::Instant::now();
 
             let total_actual_elapsed = period_end - start_time;
-            let total_expected_elapsed = self.period.mul_f64(end_cycle as f64);
+            let total_expected_elapsed = self.period.mul_f64(elapsed_cycles as f64);
             let total_multiplier =
                 total_actual_elapsed.as_secs_f64() / total_expected_elapsed.as_secs_f64();
 
@@ -165,7 +187,7 @@ impl ExecutableComponent for Oscillator {
                 next_period_millis,
             );
 
-            if total_multiplier > 1.01 && current_cycle % 100_000 == 0 {
+            if total_multiplier > 1.01 && current_cycle.is_multiple_of(100_000) {
                 tracing::warn!(
                     "oscillator is lagging real-time by {:.3}s ({:.2}x slower)",
                     total_difference.as_secs_f64(),
@@ -173,10 +195,49 @@ impl ExecutableComponent for Oscillator {
                 );
             }
 
-            current_cycle += cycles_executed;
-            tokio::time::sleep(next_period).await;
+            // The cycles may have been counted as they ran, or a snapshot may have been
+            // restored while this batch ran; either way, the counter as it stands wins.
+            let _ = self.current_cycle.compare_exchange(
+                current_cycle,
+                end_cycle,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(next_period) => {}
+                _ = lifecycle.stopped() => break,
+            }
             current_period = next_period;
         }
+
+        tracing::info!("oscillator stopped");
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl StatefulComponent for Oscillator {
+    async fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        writer.write_usize(self.current_cycle.load(Ordering::SeqCst));
+        writer.finish()
+    }
+
+    async fn load_state(&self, state: &[u8]) -> snapshot::Result<()> {
+        let mut reader = StateReader::new(state);
+        let current_cycle = reader.read_usize()?;
+        reader.finish()?;
+        self.current_cycle.store(current_cycle, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ResettableComponent for Oscillator {
+    /// Counts cycles from zero again, even if a batch is running; its end doesn't override
+    /// the reset.
+    async fn reset(&self) {
+        self.current_cycle.store(0, Ordering::SeqCst);
     }
 }
 
@@ -196,6 +257,19 @@ impl Oscillator {
             bus: bus.clone(),
             frequency_hz: freq,
             period: period_duration,
+            current_cycle: AtomicUsize::new(0),
         }
     }
+
+    /// Number of the next cycle to hand out.
+    pub fn current_cycle(&self) -> usize {
+        self.current_cycle.load(Ordering::SeqCst)
+    }
+
+    /// Counts `cycles` that have run, such as when a machine is stepped by hand, or counts the
+    /// cycles in a batch as they run rather than once the batch ends, so that cycle numbers
+    /// keep increasing.
+    pub fn advance(&self, cycles: usize) {
+        self.current_cycle.fetch_add(cycles, Ordering::SeqCst);
+    }
 }
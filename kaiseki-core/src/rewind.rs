@@ -0,0 +1,228 @@
+//! A ring buffer of recent machine states for stepping backwards in time.
+//!
+//! Only the newest capture is kept whole. Each older one is kept as the bytes that differ
+//! from the capture after it, so that memory and display state, which barely change from one
+//! capture to the next, cost little to keep.
+
+use std::collections::VecDeque;
+
+use crate::snapshot::Snapshot;
+
+/// Changed bytes closer together than this are kept as a single run, since each run costs
+/// its own offset and length.
+const MERGE_GAP: usize = 8;
+
+/// How one section of a capture differs from the same section of the capture after it.
+#[derive(Clone, Debug, PartialEq)]
+enum SectionDelta {
+    /// Runs of bytes to write over the newer state, as (offset, bytes).
+    Patched(Vec<(usize, Vec<u8>)>),
+    /// The whole of the older state, for sections that changed length or weren't in the
+    /// newer capture.
+    Replaced(Vec<u8>),
+}
+
+/// A capture stored as the changes that turn the capture after it back into it.
+#[derive(Clone, Debug, PartialEq)]
+struct Delta {
+    frame: usize,
+    sections: Vec<(String, SectionDelta)>,
+}
+
+impl Delta {
+    fn between(frame: usize, older: &Snapshot, newer: &Snapshot) -> Self {
+        let sections = older
+            .sections()
+            .iter()
+            .map(|(name, old)| {
+                let delta = match newer.get(name) {
+                    Ok(new) if new.len() == old.len() => SectionDelta::Patched(diff(old, new)),
+                    _ => SectionDelta::Replaced(old.clone()),
+                };
+                (name.clone(), delta)
+            })
+            .collect();
+        Self { frame, sections }
+    }
+
+    fn apply(&self, newer: &Snapshot) -> Snapshot {
+        let mut older = Snapshot::new();
+        for (name, delta) in &self.sections {
+            let state = match delta {
+                SectionDelta::Patched(runs) => {
+                    let mut state = newer.get(name).unwrap().to_vec();
+                    for (offset, bytes) in runs {
+                        state[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+                    }
+                    state
+                }
+                SectionDelta::Replaced(state) => state.clone(),
+            };
+            older.insert(name, state);
+        }
+        older
+    }
+
+    /// Number of bytes of state the delta holds.
+    fn size(&self) -> usize {
+        self.sections
+            .iter()
+            .map(|(_, delta)| match delta {
+                SectionDelta::Patched(runs) => runs.iter().map(|(_, bytes)| bytes.len()).sum(),
+                SectionDelta::Replaced(state) => state.len(),
+            })
+            .sum()
+    }
+}
+
+/// Finds the runs of `old` that differ from `new`, which is the same length.
+fn diff(old: &[u8], new: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for offset in (0..old.len()).filter(|offset| old[*offset] != new[*offset]) {
+        match runs.last_mut() {
+            Some((_, end)) if offset - *end < MERGE_GAP => *end = offset + 1,
+            _ => runs.push((offset, offset + 1)),
+        }
+    }
+    runs.into_iter()
+        .map(|(start, end)| (start, old[start..end].to_vec()))
+        .collect()
+}
+
+/// Machine states captured every so many frames, dropping the oldest once full.
+#[derive(Clone, Debug)]
+pub struct RewindBuffer {
+    capacity: usize,
+    interval: usize,
+    newest: Option<(usize, Snapshot)>,
+    /// Captures before the newest, oldest first.
+    older: VecDeque<Delta>,
+}
+
+impl RewindBuffer {
+    /// Creates a buffer holding up to `capacity` captures, taken `interval` frames apart.
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        assert!(capacity > 0 && interval > 0);
+        Self {
+            capacity,
+            interval,
+            newest: None,
+            older: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.older.len() + self.newest.is_some() as usize
+    }
+
+    /// Number of bytes of machine state held, which is a whole capture plus the changes
+    /// needed to get back to each older one.
+    pub fn size(&self) -> usize {
+        let newest = self.newest.as_ref().map_or(0, |(_, snapshot)| {
+            snapshot
+                .sections()
+                .iter()
+                .map(|(_, state)| state.len())
+                .sum()
+        });
+        newest + self.older.iter().map(Delta::size).sum::<usize>()
+    }
+
+    /// Whether it's been long enough since the newest capture to take another at `frame`.
+    pub fn is_due(&self, frame: usize) -> bool {
+        self.newest
+            .as_ref()
+            .is_none_or(|(newest, _)| frame >= newest + self.interval)
+    }
+
+    /// Adds a capture of the machine at `frame`, which must be later than any capture kept.
+    pub fn push(&mut self, frame: usize, snapshot: Snapshot) {
+        if let Some((newest_frame, newest)) = self.newest.take() {
+            assert!(frame > newest_frame);
+            self.older
+                .push_back(Delta::between(newest_frame, &newest, &snapshot));
+        }
+        self.newest = Some((frame, snapshot));
+        while self.len() > self.capacity {
+            self.older.pop_front();
+        }
+    }
+
+    /// Drops every capture after `frame`, returning the newest capture left along with its
+    /// frame. If every capture is after `frame`, only the oldest is left.
+    pub fn rewind_to(&mut self, frame: usize) -> Option<(usize, Snapshot)> {
+        let (mut newest_frame, mut newest) = self.newest.take()?;
+        while newest_frame > frame {
+            let Some(delta) = self.older.pop_back() else {
+                break;
+            };
+            newest = delta.apply(&newest);
+            newest_frame = delta.frame;
+        }
+        self.newest = Some((newest_frame, newest.clone()));
+        Some((newest_frame, newest))
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.older.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, RewindBuffer};
+    use crate::snapshot::Snapshot;
+
+    fn capture(ram: &[u8], registers: &[u8]) -> Snapshot {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("ram", ram.to_vec());
+        snapshot.insert("registers", registers.to_vec());
+        snapshot
+    }
+
+    #[test]
+    fn diff_merges_nearby_changes() {
+        let old = [0u8; 32];
+        let mut new = old;
+        new[1] = 1;
+        new[4] = 1;
+        new[20] = 1;
+        assert_eq!(diff(&old, &new), vec![(1, vec![0, 0, 0, 0]), (20, vec![0])]);
+        assert_eq!(diff(&old, &old), vec![]);
+    }
+
+    #[test]
+    fn rewind_restores_older_captures() {
+        let mut buffer = RewindBuffer::new(3, 10);
+        let mut ram = vec![0u8; 4096];
+        let mut captures = Vec::new();
+        for frame in (0..50).step_by(10) {
+            assert!(buffer.is_due(frame));
+            ram[frame] = frame as u8 + 1;
+            let snapshot = capture(&ram, &vec![frame as u8; frame / 10 + 1]);
+            buffer.push(frame, snapshot.clone());
+            captures.push(snapshot);
+            assert!(!buffer.is_due(frame + 9));
+        }
+
+        // Only the newest capture is whole, so the buffer is barely bigger than one capture.
+        assert_eq!(buffer.len(), 3);
+        assert!(buffer.size() < 4096 + 64);
+
+        assert_eq!(buffer.rewind_to(45), Some((40, captures[4].clone())));
+        assert_eq!(buffer.rewind_to(39), Some((30, captures[3].clone())));
+        assert_eq!(buffer.len(), 2);
+
+        // Rewinding past the oldest capture stops at it, and capturing picks up from there.
+        assert_eq!(buffer.rewind_to(0), Some((20, captures[2].clone())));
+        assert_eq!(buffer.len(), 1);
+        assert!(buffer.is_due(30));
+        buffer.push(30, captures[3].clone());
+        assert_eq!(buffer.rewind_to(25), Some((20, captures[2].clone())));
+
+        buffer.clear();
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.rewind_to(0), None);
+    }
+}
@@ -0,0 +1,377 @@
+//! Save states for machines and their components.
+//!
+//! A [`Snapshot`] holds the serialized state of each stateful component of a machine as a
+//! named section, where the names are chosen by the machine and unique within it. Each
+//! component serializes itself with a [`StateWriter`] and restores itself with a
+//! [`StateReader`]; all values are little-endian.
+//!
+//! On disk, a snapshot is laid out as:
+//!
+//! | Size          | Contents                                          |
+//! |---------------|---------------------------------------------------|
+//! | 4 bytes       | magic: `KSNP`                                     |
+//! | 2 bytes       | format version, currently `1`                     |
+//! | 4 bytes       | number of sections                                |
+//! | for each section:                                                 |
+//! | 2 bytes       | length of the section name                        |
+//! | variable      | section name, UTF-8                               |
+//! | 4 bytes       | length of the section's state                     |
+//! | variable      | the component's state                             |
+
+use std::fs;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::component::Component;
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"KSNP";
+const SNAPSHOT_VERSION: u16 = 1;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SnapshotError {
+    #[error("state ended unexpectedly")]
+    Truncated,
+    #[error("state has {0} unexpected trailing bytes")]
+    TrailingBytes(usize),
+    #[error("data is not a kaiseki snapshot")]
+    BadMagic,
+    #[error("snapshot format version {0} is not supported")]
+    UnsupportedVersion(u16),
+    #[error("snapshot has no state for '{0}'")]
+    MissingSection(String),
+    #[error("snapshot state for '{0}' is invalid: {1}")]
+    InvalidSection(String, String),
+    #[error("invalid state: {0}")]
+    InvalidState(String),
+    #[error("failed to access snapshot file '{0}': {1}")]
+    File(String, String),
+}
+
+pub type Result<T> = std::result::Result<T, SnapshotError>;
+
+/// A component whose state can be captured into and restored from a snapshot.
+#[async_trait]
+pub trait StatefulComponent: Component {
+    async fn save_state(&self) -> Vec<u8>;
+    async fn load_state(&self, state: &[u8]) -> Result<()>;
+}
+
+/// Serializes component state.
+#[derive(Debug, Default)]
+pub struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a `usize` as 8 bytes, regardless of platform.
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    /// Writes a length-prefixed byte buffer.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+}
+
+/// Deserializes component state written by a [`StateWriter`].
+#[derive(Debug)]
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub(crate) fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err(SnapshotError::Truncated);
+        }
+        let slice = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(SnapshotError::InvalidState(format!(
+                "{} is not a boolean",
+                value
+            ))),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize> {
+        Ok(self.read_u64()? as usize)
+    }
+
+    /// Reads a length-prefixed byte buffer.
+    pub fn read_bytes(&mut self) -> Result<&'a [u8]> {
+        let length = self.read_u32()? as usize;
+        self.take(length)
+    }
+
+    /// Reads a length-prefixed byte buffer that must be exactly `length` bytes long.
+    pub fn read_bytes_exact(&mut self, length: usize) -> Result<&'a [u8]> {
+        let bytes = self.read_bytes()?;
+        if bytes.len() != length {
+            return Err(SnapshotError::InvalidState(format!(
+                "expected {} bytes, found {}",
+                length,
+                bytes.len()
+            )));
+        }
+        Ok(bytes)
+    }
+
+    /// Ensures all of the state has been read.
+    pub fn finish(self) -> Result<()> {
+        match self.data.len() - self.position {
+            0 => Ok(()),
+            remaining => Err(SnapshotError::TrailingBytes(remaining)),
+        }
+    }
+}
+
+/// The complete state of a machine at a point in time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    sections: Vec<(String, Vec<u8>)>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the state stored under `name`.
+    pub fn insert(&mut self, name: &str, state: Vec<u8>) {
+        match self.sections.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = state,
+            None => self.sections.push((String::from(name), state)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&[u8]> {
+        self.sections
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, state)| state.as_slice())
+            .ok_or_else(|| SnapshotError::MissingSection(String::from(name)))
+    }
+
+    /// Every section, in the order they were first inserted.
+    pub(crate) fn sections(&self) -> &[(String, Vec<u8>)] {
+        &self.sections
+    }
+
+    /// Captures `component`'s state under `name`.
+    pub async fn capture(&mut self, name: &str, component: &impl StatefulComponent) {
+        self.insert(name, component.save_state().await);
+    }
+
+    /// Restores `component`'s state from the section stored under `name`.
+    pub async fn restore(&self, name: &str, component: &impl StatefulComponent) -> Result<()> {
+        component
+            .load_state(self.get(name)?)
+            .await
+            .map_err(|e| SnapshotError::InvalidSection(String::from(name), e.to_string()))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+        SNAPSHOT_MAGIC.iter().for_each(|b| writer.write_u8(*b));
+        writer.write_u16(SNAPSHOT_VERSION);
+        writer.write_u32(self.sections.len() as u32);
+        for (name, state) in self.sections.iter() {
+            writer.write_u16(name.len() as u16);
+            name.bytes().for_each(|b| writer.write_u8(b));
+            writer.write_bytes(state);
+        }
+        writer.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = StateReader::new(data);
+        if reader.take(4)? != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut snapshot = Snapshot::new();
+        for _ in 0..reader.read_u32()? {
+            let name_length = reader.read_u16()? as usize;
+            let name = String::from_utf8(reader.take(name_length)?.to_vec())
+                .map_err(|e| SnapshotError::InvalidState(e.to_string()))?;
+            let state = reader.read_bytes()?;
+            snapshot.insert(&name, state.to_vec());
+        }
+        reader.finish()?;
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        fs::write(path, self.to_bytes())
+            .map_err(|e| SnapshotError::File(String::from(path), e.to_string()))
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let data =
+            fs::read(path).map_err(|e| SnapshotError::File(String::from(path), e.to_string()))?;
+        Self::from_bytes(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AddressableComponent, RAM, ROM};
+
+    use super::{Snapshot, SnapshotError, StateReader, StateWriter};
+
+    #[test]
+    fn state_writer_reader_round_trip() {
+        let mut writer = StateWriter::new();
+        writer.write_bool(true);
+        writer.write_u8(0x12);
+        writer.write_u16(0x3456);
+        writer.write_usize(0x789A_BCDE);
+        writer.write_bytes(&[0xAA, 0xBB]);
+        let state = writer.finish();
+
+        let mut reader = StateReader::new(&state);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_u8().unwrap(), 0x12);
+        assert_eq!(reader.read_u16().unwrap(), 0x3456);
+        assert_eq!(reader.read_usize().unwrap(), 0x789A_BCDE);
+        assert_eq!(reader.read_bytes().unwrap(), &[0xAA, 0xBB]);
+        reader.finish().unwrap();
+
+        let mut reader = StateReader::new(&state[..2]);
+        reader.read_bool().unwrap();
+        reader.read_u8().unwrap();
+        assert_eq!(reader.read_u16(), Err(SnapshotError::Truncated));
+        assert_eq!(
+            StateReader::new(&state).finish(),
+            Err(SnapshotError::TrailingBytes(state.len()))
+        );
+    }
+
+    #[tokio::test]
+    async fn storage_round_trip() {
+        let ram = RAM::<0x10>::new("test RAM");
+        let rom = ROM::<0x10>::new("test ROM", &[0x01, 0x02]);
+        ram.write(0x4, &[0xCA, 0xFE]).unwrap();
+
+        let mut snapshot = Snapshot::new();
+        snapshot.capture("ram", &ram).await;
+        snapshot.capture("rom", &rom).await;
+
+        ram.write(0x4, &[0x00, 0x00]).unwrap();
+        snapshot.restore("ram", &ram).await.unwrap();
+        snapshot.restore("rom", &rom).await.unwrap();
+        assert_eq!(ram.read(0x4, 2).unwrap(), vec![0xCA, 0xFE]);
+        assert_eq!(rom.read(0x0, 2).unwrap(), vec![0x01, 0x02]);
+
+        // State for a differently-sized component is rejected.
+        let small_ram = RAM::<0x8>::new("small RAM");
+        assert!(matches!(
+            snapshot.restore("ram", &small_ram).await,
+            Err(SnapshotError::InvalidSection(name, _)) if name == "ram"
+        ));
+        assert_eq!(
+            snapshot.restore("missing", &ram).await,
+            Err(SnapshotError::MissingSection(String::from("missing")))
+        );
+    }
+
+    #[test]
+    fn snapshot_bytes_round_trip() {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("first", vec![0x01, 0x02, 0x03]);
+        snapshot.insert("second", vec![]);
+        snapshot.insert("first", vec![0x04]);
+
+        let bytes = snapshot.to_bytes();
+        assert_eq!(&bytes[0..4], b"KSNP");
+        assert_eq!(Snapshot::from_bytes(&bytes).unwrap(), snapshot);
+        assert_eq!(snapshot.get("first").unwrap(), &[0x04]);
+
+        assert_eq!(Snapshot::from_bytes(b"NOPE"), Err(SnapshotError::BadMagic));
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::Truncated)
+        );
+    }
+
+    #[test]
+    fn snapshot_file_round_trip() {
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("state", vec![0x55; 64]);
+
+        let path = std::env::temp_dir().join(format!("kaiseki-{}.ksnp", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        snapshot.save(path).unwrap();
+        assert_eq!(Snapshot::load(path).unwrap(), snapshot);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(matches!(
+            Snapshot::load(path),
+            Err(SnapshotError::File(_, _))
+        ));
+    }
+}
@@ -1,6 +1,12 @@
 use std::sync::{Arc, Mutex};
 
-use crate::component::{AddressableComponent, Component, ComponentId, Result};
+use async_trait::async_trait;
+
+use crate::component::{
+    AddressableComponent, AddressableComponentError, Component, ComponentId, ResettableComponent,
+    Result,
+};
+use crate::snapshot::{self, StateReader, StateWriter, StatefulComponent};
 
 #[derive(Clone, Debug)]
 struct RAMState<const N: usize> {
@@ -26,17 +32,58 @@ impl<const N: usize> Component for RAM<N> {
 impl<const N: usize> AddressableComponent for RAM<N> {
     fn read(&self, address: usize, length: usize) -> Result<Vec<u8>> {
         let mut state = self.state.lock().unwrap();
+        let slice = state
+            .buffer
+            .get(address..address.saturating_add(length))
+            .ok_or_else(|| {
+                AddressableComponentError::ComponentReadFailed(self.id.clone(), address, length)
+            })?;
+        let bytes = Vec::from(slice);
         state.bytes_read += length;
         state.num_reads += 1;
-        let slice = &state.buffer[address..address + length];
-        Ok(Vec::from(slice))
+        Ok(bytes)
     }
 
     fn write(&self, address: usize, data: &[u8]) -> Result<()> {
         let mut state = self.state.lock().unwrap();
+        state
+            .buffer
+            .get_mut(address..address.saturating_add(data.len()))
+            .ok_or_else(|| {
+                AddressableComponentError::ComponentWriteFailed(
+                    self.id.clone(),
+                    address,
+                    data.len(),
+                )
+            })?
+            .clone_from_slice(data);
         state.bytes_written += data.len();
         state.num_writes += 1;
-        state.buffer[address..address + data.len()].clone_from_slice(data);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<const N: usize> ResettableComponent for RAM<N> {
+    async fn reset(&self) {
+        self.state.lock().unwrap().buffer.fill(0);
+    }
+}
+
+#[async_trait]
+impl<const N: usize> StatefulComponent for RAM<N> {
+    async fn save_state(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut writer = StateWriter::new();
+        writer.write_bytes(&state.buffer);
+        writer.finish()
+    }
+
+    async fn load_state(&self, data: &[u8]) -> snapshot::Result<()> {
+        let mut reader = StateReader::new(data);
+        let buffer = reader.read_bytes_exact(N)?;
+        reader.finish()?;
+        self.state.lock().unwrap().buffer.copy_from_slice(buffer);
         Ok(())
     }
 }
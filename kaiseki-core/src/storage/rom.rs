@@ -1,8 +1,11 @@
 use std::sync::{Arc, Mutex};
 
+use async_trait::async_trait;
+
 use crate::component::{
     AddressableComponent, AddressableComponentError, Component, ComponentId, Result,
 };
+use crate::snapshot::{self, StateReader, StateWriter, StatefulComponent};
 
 #[derive(Clone, Debug)]
 struct ROMState<const N: usize> {
@@ -26,10 +29,16 @@ impl<const N: usize> Component for ROM<N> {
 impl<const N: usize> AddressableComponent for ROM<N> {
     fn read(&self, address: usize, length: usize) -> Result<Vec<u8>> {
         let mut state = self.state.lock().unwrap();
+        let slice = state
+            .buffer
+            .get(address..address.saturating_add(length))
+            .ok_or_else(|| {
+                AddressableComponentError::ComponentReadFailed(self.id.clone(), address, length)
+            })?;
+        let bytes = Vec::from(slice);
         state.bytes_read += length;
         state.num_reads += 1;
-        let slice = &state.buffer[address..address + length];
-        Ok(Vec::from(slice))
+        Ok(bytes)
     }
 
     fn write(&self, address: usize, data: &[u8]) -> Result<()> {
@@ -41,6 +50,24 @@ impl<const N: usize> AddressableComponent for ROM<N> {
     }
 }
 
+#[async_trait]
+impl<const N: usize> StatefulComponent for ROM<N> {
+    async fn save_state(&self) -> Vec<u8> {
+        let state = self.state.lock().unwrap();
+        let mut writer = StateWriter::new();
+        writer.write_bytes(&state.buffer);
+        writer.finish()
+    }
+
+    async fn load_state(&self, data: &[u8]) -> snapshot::Result<()> {
+        let mut reader = StateReader::new(data);
+        let buffer = reader.read_bytes_exact(N)?;
+        reader.finish()?;
+        self.state.lock().unwrap().buffer.copy_from_slice(buffer);
+        Ok(())
+    }
+}
+
 impl<const N: usize> ROM<N> {
     pub fn new(name: &str, contents: &[u8]) -> Self {
         let mut buffer = [0; N];
@@ -1,13 +1,26 @@
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use thiserror::Error;
 
-use crate::machine::{Machine, MachineError};
+use crate::component::Fault;
+use crate::debugger::Debugger;
+use crate::lifecycle::{Lifecycle, LifecycleState};
+use crate::machine::{FrameObserver, Machine, MachineError, ResetKind};
+use crate::movie::{Movie, MovieError};
+use crate::rewind::RewindBuffer;
+use crate::snapshot::Snapshot;
 
 #[derive(Debug, Error, PartialEq)]
 pub enum VexError {
     #[error(transparent)]
-    Load(#[from] MachineError),
+    Machine(#[from] MachineError),
+    #[error(transparent)]
+    Fault(#[from] Fault),
+    #[error(transparent)]
+    Movie(#[from] MovieError),
+    #[error("the machine is running; pause it before controlling execution")]
+    Running,
 }
 
 pub type Result<T> = std::result::Result<T, VexError>;
@@ -16,30 +29,298 @@ pub type Result<T> = std::result::Result<T, VexError>;
 pub struct Vex {
     command: String,
     machine: Arc<dyn Machine>,
+    lifecycle: Lifecycle,
+    started: Arc<AtomicBool>,
+    fault: Arc<Mutex<Option<Fault>>>,
+    /// Recent states to rewind to, if enabled with [`Vex::enable_rewind`].
+    rewind: Arc<Mutex<Option<RewindBuffer>>>,
 }
 
 impl Vex {
-    pub fn create(machine: impl Machine, command: &str) -> Self {
-        Self {
+    /// Creates a Vex running `machine`, loading the program at `command` into it right away so
+    /// that a missing or oversized program is reported before anything starts.
+    pub fn create(machine: impl Machine, command: &str) -> Result<Self> {
+        machine.load(command)?;
+        let rewind: Arc<Mutex<Option<RewindBuffer>>> = Arc::new(Mutex::new(None));
+        machine.observe_frames(rewind.clone());
+        Ok(Self {
             command: String::from(command),
             machine: Arc::new(machine),
-        }
+            lifecycle: Lifecycle::new(),
+            started: Arc::new(AtomicBool::new(false)),
+            fault: Arc::new(Mutex::new(None)),
+            rewind,
+        })
     }
 
-    pub async fn destroy(&self) {}
+    /// Stops the machine for good; see [`Vex::stop`].
+    pub fn destroy(&self) {
+        tracing::info!("destroying '{}'", self.command);
+        self.stop();
+    }
 
     pub fn get_frame(&self) -> (usize, usize, Vec<u8>) {
         self.machine.get_frame()
     }
 
-    pub async fn revert(&self) {}
-    pub async fn snapshot(&self) {}
-    pub async fn start(&self) -> Result<()> {
-        let machine = &self.machine;
-        machine.load(self.command.as_str())?;
-        machine.start().await;
+    pub fn press_key(&self, key: usize) {
+        self.machine.press_key(key)
+    }
+
+    pub fn release_key(&self, key: usize) {
+        self.machine.release_key(key)
+    }
+
+    pub fn cycles_per_frame(&self) -> usize {
+        self.machine.cycles_per_frame()
+    }
+
+    pub async fn registers(&self) -> Vec<(String, usize)> {
+        self.machine.registers().await
+    }
+
+    pub fn read_memory(&self, address: usize, length: usize) -> Result<Vec<u8>> {
+        Ok(self.machine.read_memory(address, length)?)
+    }
+
+    pub fn write_memory(&self, address: usize, data: &[u8]) -> Result<()> {
+        Ok(self.machine.write_memory(address, data)?)
+    }
+
+    /// Debugger for setting breakpoints and watchpoints on the machine, and finding out which
+    /// one halted it.
+    pub fn debugger(&self) -> &Debugger {
+        self.machine.debugger()
+    }
+
+    /// The fault that last halted the machine, if it hasn't run since.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault.lock().unwrap().clone()
+    }
+
+    fn clear_fault(&self) {
+        *self.fault.lock().unwrap() = None;
+    }
+
+    /// Remembers `fault` for [`Vex::fault`] and turns it into an error.
+    fn record_fault(&self, fault: Fault) -> VexError {
+        tracing::error!("'{}' faulted: {}", self.command, fault);
+        *self.fault.lock().unwrap() = Some(fault.clone());
+        VexError::Fault(fault)
+    }
+
+    /// Fails if the machine was started by [`Vex::start`] and hasn't been paused or stopped,
+    /// since its oscillators are driving it.
+    fn ensure_not_running(&self) -> Result<()> {
+        match self.started.load(Ordering::SeqCst) && self.lifecycle.is_running() {
+            true => Err(VexError::Running),
+            false => Ok(()),
+        }
+    }
+
+    /// Number of cycles run by [`Vex::step`], [`Vex::run_cycles`] and [`Vex::run_frames`].
+    pub fn cycles_run(&self) -> usize {
+        self.machine.cycles_run()
+    }
+
+    /// Executes exactly one instruction, continuing from a halt if there was one.
+    pub async fn step(&self) -> Result<()> {
+        self.ensure_not_running()?;
+        self.machine.debugger().resume();
+        self.clear_fault();
+        self.machine
+            .step()
+            .await
+            .map_err(|fault| self.record_fault(fault))
+    }
+
+    /// Runs the machine for a fixed number of cycles without waiting on wall-clock time, as
+    /// an alternative to [`Vex::start`] or while paused. Continues from a halt if there was one,
+    /// and stops early if the debugger halts the machine again.
+    pub async fn run_cycles(&self, cycles: usize) -> Result<()> {
+        self.ensure_not_running()?;
+        self.machine.debugger().resume();
+        tracing::info!("running '{}' for {} cycles", self.command, cycles);
+        self.clear_fault();
+        self.machine
+            .run_cycles(cycles)
+            .await
+            .map_err(|fault| self.record_fault(fault))
+    }
+
+    /// Runs the machine to the end of the current frame and then `frames - 1` more frames
+    /// without waiting on wall-clock time, returning the number of cycles run. Like
+    /// [`Vex::run_cycles`], continues from a halt and stops early on the next one.
+    pub async fn run_frames(&self, frames: usize) -> Result<usize> {
+        self.ensure_not_running()?;
+        self.machine.debugger().resume();
+        tracing::info!("running '{}' for {} frames", self.command, frames);
+        self.clear_fault();
+        let mut cycles = 0;
+        for _ in 0..frames {
+            cycles += self
+                .machine
+                .run_frame()
+                .await
+                .map_err(|fault| self.record_fault(fault))?;
+            if self.machine.debugger().halted().is_some() {
+                break;
+            }
+        }
+        Ok(cycles)
+    }
+
+    /// Resets the machine, reloading its program after a hard reset, and clears any halt or
+    /// fault. A faulted machine runs again once it's started again.
+    pub async fn reset(&self, kind: ResetKind) -> Result<()> {
+        tracing::info!("resetting '{}' ({:?})", self.command, kind);
+        self.machine.reset(kind).await;
+        if kind == ResetKind::Hard {
+            self.machine.load(&self.command)?;
+        }
+        self.machine.debugger().reset();
+        self.clear_fault();
+        self.clear_rewind();
         Ok(())
     }
 
-    pub async fn stop(&self) {}
+    /// Hard resets the machine and records its input from then on, until
+    /// [`Vex::stop_recording`] or another reset. Fails if the machine is running.
+    pub async fn record(&self) -> Result<()> {
+        self.ensure_not_running()?;
+        self.reset(ResetKind::Hard).await?;
+        self.machine.start_recording();
+        Ok(())
+    }
+
+    /// Stops recording, returning the movie recorded by [`Vex::record`] if there is one.
+    pub fn stop_recording(&self) -> Option<Movie> {
+        self.machine.stop_recording()
+    }
+
+    /// Hard resets the machine and plays `movie`'s input into it in place of the host's.
+    /// Fails if the machine is running or its seed isn't the one `movie` was recorded with.
+    pub async fn replay(&self, movie: &Movie) -> Result<()> {
+        self.ensure_not_running()?;
+        if movie.seed() != self.machine.seed() {
+            return Err(MovieError::SeedMismatch(movie.seed(), self.machine.seed()).into());
+        }
+        self.reset(ResetKind::Hard).await?;
+        self.machine.play_movie(movie);
+        Ok(())
+    }
+
+    /// Restores the machine to a state captured by [`Vex::snapshot`].
+    pub async fn revert(&self, snapshot: &Snapshot) -> Result<()> {
+        tracing::info!("reverting '{}' to snapshot", self.command);
+        self.machine.restore(snapshot).await?;
+        self.clear_rewind();
+        Ok(())
+    }
+
+    /// Keeps enough states to rewind `frames` frames, capturing one every `interval` frames as
+    /// the machine runs, whether on its own or by [`Vex::run_frames`] and the like. Drops any
+    /// states kept so far.
+    pub fn enable_rewind(&self, frames: usize, interval: usize) {
+        let capacity = frames.div_ceil(interval) + 1;
+        *self.rewind.lock().unwrap() = Some(RewindBuffer::new(capacity, interval));
+    }
+
+    /// Restores the newest captured state from at least `frames` frames ago, or the oldest if
+    /// there isn't one, dropping any captured since. Returns the number of frames actually
+    /// rewound, which is zero if nothing was captured.
+    pub async fn rewind(&self, frames: usize) -> Result<usize> {
+        let current = self.machine.current_frame();
+        let capture = self
+            .rewind
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(|buffer| buffer.rewind_to(current.saturating_sub(frames)));
+        let Some((frame, snapshot)) = capture else {
+            return Ok(0);
+        };
+        tracing::debug!("rewinding '{}' to frame {}", self.command, frame);
+        self.machine.restore(&snapshot).await?;
+        Ok(current.saturating_sub(frame))
+    }
+
+    fn clear_rewind(&self) {
+        if let Some(buffer) = self.rewind.lock().unwrap().as_mut() {
+            buffer.clear();
+        }
+    }
+
+    /// Captures the complete state of the machine, which can be kept in memory or saved to
+    /// disk with [`Snapshot::save`].
+    pub async fn snapshot(&self) -> Snapshot {
+        tracing::info!("capturing snapshot of '{}'", self.command);
+        self.machine.snapshot().await
+    }
+
+    /// Runs the machine until [`Vex::stop`] is called or the machine faults. A faulted machine
+    /// can be started again, picking up where it left off.
+    pub async fn start(&self) -> Result<()> {
+        tracing::info!("starting '{}'", self.command);
+        self.started.store(true, Ordering::SeqCst);
+        self.lifecycle.restart();
+        self.clear_fault();
+        self.machine
+            .start(&self.lifecycle)
+            .await
+            .map_err(|fault| self.record_fault(fault))
+    }
+
+    pub fn state(&self) -> LifecycleState {
+        self.lifecycle.state()
+    }
+
+    pub fn pause(&self) {
+        self.lifecycle.pause();
+    }
+
+    /// Waits until the machine is paused, whether by [`Vex::pause`] or by the debugger halting
+    /// it, or stopped. Returns the state it ended up in.
+    pub async fn wait_while_running(&self) -> LifecycleState {
+        self.lifecycle.wait_while_running().await
+    }
+
+    /// Resumes a paused machine, continuing from a halt if there was one.
+    pub fn resume(&self) {
+        self.machine.debugger().resume();
+        self.lifecycle.resume();
+    }
+
+    /// Stops the machine, causing [`Vex::start`] to return once every component has wound
+    /// down. A stopped Vex can't be started again.
+    pub fn stop(&self) {
+        self.lifecycle.stop();
+    }
+}
+
+impl FrameObserver for Mutex<Option<RewindBuffer>> {
+    fn wants_frame(&self, frame: usize) -> bool {
+        self.lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|buffer| buffer.is_due(frame))
+    }
+
+    fn frame_ended(&self, frame: usize, snapshot: Snapshot) {
+        // Rewind may have been disabled or reset since the frame was wanted.
+        if let Some(buffer) = self
+            .lock()
+            .unwrap()
+            .as_mut()
+            .filter(|buffer| buffer.is_due(frame))
+        {
+            buffer.push(frame, snapshot);
+            tracing::debug!(
+                "captured frame {} for rewinding; holding {} captures in {} bytes",
+                frame,
+                buffer.len(),
+                buffer.size()
+            );
+        }
+    }
 }
@@ -0,0 +1,15 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use kaiseki_chip8::assembler;
+use kaiseki_chip8::machine::PROGRAM_ADDRESS;
+
+/// Assembles the source at `path` into a ROM written to `output`.
+pub fn run(path: &str, output: &str) -> Result<()> {
+    let source =
+        fs::read_to_string(path).map_err(|e| anyhow!("failed to read '{}': {}", path, e))?;
+    let program = assembler::assemble(&source, PROGRAM_ADDRESS as u16)
+        .map_err(|e| anyhow!("failed to assemble '{}': {}", path, e))?;
+    fs::write(output, program).map_err(|e| anyhow!("failed to write '{}': {}", output, e))?;
+    Ok(())
+}
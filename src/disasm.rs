@@ -0,0 +1,25 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use kaiseki_chip8::disassembler;
+use kaiseki_chip8::machine::{Chip8Variant, PROGRAM_ADDRESS};
+
+/// Prints a listing of the ROM at `path` as a `variant` machine would load and decode it.
+pub fn run(path: &str, variant: Chip8Variant) -> Result<()> {
+    let program = fs::read(path).map_err(|e| anyhow!("failed to read '{}': {}", path, e))?;
+    let available = variant.memory_size() - PROGRAM_ADDRESS;
+    if program.len() > available {
+        return Err(anyhow!(
+            "'{}' is {} bytes, but only {} bytes fit in {:?} memory",
+            path,
+            program.len(),
+            available,
+            variant
+        ));
+    }
+    print!(
+        "{}",
+        disassembler::disassemble(&program, PROGRAM_ADDRESS as u16, variant)
+    );
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::fs;
+
+use anyhow::{anyhow, Result};
+use kaiseki_core::Vex;
+
+use crate::{create_tokio_runtime, screenshot, Args};
+
+/// Runs the Vex for the number of cycles or frames given in `args` as fast as possible, then
+/// dumps the final frame and registers if asked to.
+pub fn run(args: &Args, vex: Vex) -> Result<()> {
+    let runtime = create_tokio_runtime();
+    match (args.cycles, args.frames) {
+        (Some(cycles), _) => runtime.block_on(vex.run_cycles(cycles))?,
+        (None, Some(frames)) => {
+            let cycles = runtime.block_on(vex.run_frames(frames))?;
+            tracing::info!("ran {} frames in {} cycles", frames, cycles);
+        }
+        (None, None) => return Err(anyhow!("--headless requires --cycles or --frames")),
+    }
+
+    if let Some(path) = &args.dump_frame {
+        let (width, height, frame) = vex.get_frame();
+        write_ppm(path, width, height, &frame)?;
+        tracing::info!("wrote final {}x{} frame to '{}'", width, height, path);
+    }
+
+    if let Some(path) = &args.screenshot {
+        let (width, height, frame) = vex.get_frame();
+        let scale = args.screenshot_scale as usize;
+        screenshot::save_png(path, width, height, &frame, scale)?;
+        tracing::info!(
+            "wrote final {}x{} frame to '{}' at {}x scale",
+            width,
+            height,
+            path,
+            scale
+        );
+    }
+
+    if args.dump_registers {
+        for (name, value) in runtime.block_on(vex.registers()) {
+            println!("{}: 0x{:04X}", name, value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes an RGB frame as a binary PPM (P6) image.
+fn write_ppm(path: &str, width: usize, height: usize, frame: &[u8]) -> Result<()> {
+    let mut contents = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    contents.extend_from_slice(frame);
+    fs::write(path, contents).map_err(|e| anyhow!("failed to write '{}': {}", path, e))
+}
@@ -1,35 +1,375 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use anyhow::{anyhow, Result};
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 
 use eframe::CreationContext;
-use egui::{ColorImage, TextureFilter, TextureOptions};
-use kaiseki_chip8::machine::Chip8Machine;
-use kaiseki_core::Vex;
+use egui::{ColorImage, Key, TextureFilter, TextureOptions};
+use kaiseki_chip8::font::Chip8FontSet;
+use kaiseki_chip8::gdb::GdbServer;
+use kaiseki_chip8::machine::{Chip8Machine, Chip8Variant};
+use kaiseki_chip8::quirks::Chip8Quirks;
+use kaiseki_core::audio::{NullAudioSink, WavAudioSink};
+use kaiseki_core::machine::ResetKind;
+use kaiseki_core::movie::Movie;
+use kaiseki_core::{LifecycleState, Vex};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::oneshot::Sender;
 use tracing_flame::FlameLayer;
 
+mod asm;
+mod disasm;
+mod headless;
+mod screenshot;
+
+const AUDIO_SAMPLE_RATE: u32 = 44100;
+const DISPLAY_WIDTH: f32 = 512.0;
+/// Toggles between pausing and resuming emulation; never one of the mappable machine keys.
+const PAUSE_KEY: Key = Key::Space;
+/// Resets the machine, or hard resets it with Shift held.
+const RESET_KEY: Key = Key::F5;
+/// Saves a screenshot at the machine's resolution, or as scaled on screen with Shift held.
+const SCREENSHOT_KEY: Key = Key::F12;
+/// Steps back in time while held, pausing the machine until it's released.
+const REWIND_KEY: Key = Key::Backspace;
+/// Number of frames the machine can be rewound, ten seconds at 60 frames per second.
+const REWIND_FRAMES: usize = 600;
+/// Frames between the states captured for rewinding, which is also how far each UI update
+/// rewinds while the rewind key is held.
+const REWIND_INTERVAL_FRAMES: usize = 2;
+
+/// Requests from the UI for the emulator thread to carry out.
+#[derive(Clone, Copy, Debug)]
+enum GuestRequest {
+    Reset(ResetKind),
+    Rewind(usize),
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum SupportedMachines {
     Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl SupportedMachines {
+    fn variant(&self) -> Chip8Variant {
+        match self {
+            SupportedMachines::Chip8 => Chip8Variant::Chip8,
+            SupportedMachines::SuperChip => Chip8Variant::SuperChip,
+            SupportedMachines::XoChip => Chip8Variant::XoChip,
+        }
+    }
+
+    /// Quirks of the interpreter that defined the machine.
+    fn default_quirks(&self) -> Chip8Quirks {
+        match self {
+            SupportedMachines::Chip8 => Chip8Quirks::cosmac_vip(),
+            SupportedMachines::SuperChip => Chip8Quirks::schip(),
+            SupportedMachines::XoChip => Chip8Quirks::modern(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SupportedQuirks {
+    CosmacVip,
+    Chip48,
+    Schip,
+    Modern,
+}
+
+impl From<SupportedQuirks> for Chip8Quirks {
+    fn from(quirks: SupportedQuirks) -> Self {
+        match quirks {
+            SupportedQuirks::CosmacVip => Chip8Quirks::cosmac_vip(),
+            SupportedQuirks::Chip48 => Chip8Quirks::chip48(),
+            SupportedQuirks::Schip => Chip8Quirks::schip(),
+            SupportedQuirks::Modern => Chip8Quirks::modern(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum SupportedFonts {
+    CosmacVip,
+    Chip48,
+}
+
+impl From<SupportedFonts> for Chip8FontSet {
+    fn from(font: SupportedFonts) -> Self {
+        match font {
+            SupportedFonts::CosmacVip => Chip8FontSet::CosmacVip,
+            SupportedFonts::Chip48 => Chip8FontSet::Chip48,
+        }
+    }
+}
+
+const KEYMAP_KEYS: [Key; 36] = [
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+];
+
+/// Number of keys on the Chip-8 keypad, each of which a keymap maps a keyboard key onto.
+const KEYMAP_LENGTH: usize = 16;
+
+/// Keyboard keys mapped onto machine keys; the key at index `N` drives machine key `N`.
+#[derive(Clone, Debug)]
+struct Keymap(Vec<Key>);
+
+fn parse_keymap(value: &str) -> Result<Keymap> {
+    let keys = value
+        .chars()
+        .map(|c| {
+            let name = c.to_ascii_uppercase().to_string();
+            KEYMAP_KEYS
+                .iter()
+                .find(|key| key.name() == name)
+                .copied()
+                .ok_or_else(|| anyhow!("'{}' is not a letter or digit key", c))
+        })
+        .collect::<Result<Vec<Key>>>()?;
+    if keys.len() != KEYMAP_LENGTH {
+        return Err(anyhow!(
+            "a keymap needs a key for each of the {} machine keys, but '{}' has {}",
+            KEYMAP_LENGTH,
+            value,
+            keys.len()
+        ));
+    }
+    for (index, key) in keys.iter().enumerate() {
+        if let Some(first) = keys[..index].iter().position(|other| other == key) {
+            return Err(anyhow!(
+                "'{}' is mapped to both machine keys {:X} and {:X}",
+                key.name(),
+                first,
+                index
+            ));
+        }
+    }
+    Ok(Keymap(keys))
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Assemble source into a ROM.
+    Asm {
+        /// Path to the source to assemble.
+        source: String,
+
+        /// Path to write the ROM to.
+        #[clap(short, long)]
+        output: String,
+    },
+    /// Print an assembler listing of a ROM.
+    Disasm {
+        /// Path to the ROM to disassemble.
+        rom: String,
+
+        /// Machine whose instructions to decode.
+        #[clap(value_enum, value_parser, short, long, default_value = "xo-chip")]
+        machine: SupportedMachines,
+    },
 }
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    #[clap(value_enum, value_parser, short, long)]
-    machine: SupportedMachines,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Machine to run; required unless running a subcommand.
+    #[clap(value_enum, value_parser, short, long, required = true)]
+    machine: Option<SupportedMachines>,
+
+    /// Path to the ROM to run; required unless running a subcommand.
+    #[clap(required = true)]
+    rom: Option<String>,
+
+    /// Keyboard keys for each machine key in order; the Chip-8 default maps keypad 0 - F
+    /// onto the 4x4 block of keys from 1 to V.
+    #[clap(long, value_parser = parse_keymap, default_value = "X123QWEASDZC4RFV")]
+    keymap: Keymap,
+
+    /// Interpreter whose behavior to emulate where Chip-8 interpreters disagree; defaults to
+    /// the interpreter matching the machine.
+    #[clap(value_enum, value_parser, long)]
+    quirks: Option<SupportedQuirks>,
+
+    /// Glyph set for the built-in hexadecimal digit sprites.
+    #[clap(value_enum, value_parser, long, default_value = "chip48")]
+    font: SupportedFonts,
+
+    /// Seed for the machine's random numbers, so that a run can be replayed exactly; a random
+    /// seed is picked and logged otherwise.
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Record the keys pressed and released into this movie file, from launch until the
+    /// first reset or exit.
+    #[clap(long, conflicts_with_all = ["headless", "replay"])]
+    record: Option<String>,
+
+    /// Play the input recorded in this movie file back in place of the keyboard's, using the
+    /// seed it was recorded with.
+    #[clap(long, conflicts_with = "seed")]
+    replay: Option<String>,
+
+    /// Record the machine's audio output to this WAV file instead of discarding it.
+    #[clap(long)]
+    wav: Option<String>,
+
+    /// Serve the GDB remote serial protocol on this local TCP port, so that a debugger can
+    /// attach to the running machine.
+    #[clap(long, conflicts_with = "headless")]
+    gdb: Option<u16>,
+
+    /// Run without a UI for a fixed number of cycles or frames, as fast as possible.
+    #[clap(long)]
+    headless: bool,
+
+    /// Number of cycles to run in headless mode.
+    #[clap(long, requires = "headless", conflicts_with = "frames")]
+    cycles: Option<usize>,
+
+    /// Number of frames to run in headless mode.
+    #[clap(long, requires = "headless")]
+    frames: Option<usize>,
+
+    /// Write the final frame to this file as a binary PPM image in headless mode.
+    #[clap(long, requires = "headless")]
+    dump_frame: Option<String>,
+
+    /// Write the final frame to this file as a PNG image in headless mode.
+    #[clap(long, requires = "headless")]
+    screenshot: Option<String>,
+
+    /// Factor to scale the headless screenshot up by; 1 keeps the machine's resolution.
+    #[clap(long, requires = "screenshot", default_value_t = 1)]
+    #[clap(value_parser = clap::value_parser!(u32).range(1..=64))]
+    screenshot_scale: u32,
+
+    /// Print the final register values in headless mode.
+    #[clap(long, requires = "headless")]
+    dump_registers: bool,
+}
+
+impl Args {
+    fn machine(&self) -> SupportedMachines {
+        self.machine
+            .expect("clap requires a machine without a subcommand")
+    }
+
+    fn rom(&self) -> &str {
+        self.rom
+            .as_deref()
+            .expect("clap requires a ROM without a subcommand")
+    }
 }
 
 struct KaisekiApp {
     args: Args,
     vex: Vex,
     start_tx: Option<Sender<bool>>,
+    request_tx: UnboundedSender<GuestRequest>,
+    keys_down: Vec<bool>,
+    /// While the rewind key is held, whether the machine was running when it went down.
+    rewinding: Option<bool>,
 }
 
 impl eframe::App for KaisekiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         ctx.set_visuals(egui::Visuals::dark());
 
+        if ctx.input(|input| input.key_pressed(PAUSE_KEY)) {
+            self.toggle_pause();
+        }
+        let reset = ctx.input(|input| {
+            input
+                .key_pressed(RESET_KEY)
+                .then_some(match input.modifiers.shift {
+                    true => ResetKind::Hard,
+                    false => ResetKind::Soft,
+                })
+        });
+        if let Some(kind) = reset {
+            self.request(GuestRequest::Reset(kind));
+        }
+        let screenshot = ctx.input(|input| {
+            input
+                .key_pressed(SCREENSHOT_KEY)
+                .then_some(input.modifiers.shift)
+        });
+
+        let rewind_held = ctx.input(|input| input.key_down(REWIND_KEY));
+        match (rewind_held, self.rewinding) {
+            (true, None) => {
+                let running = self.vex.state() == LifecycleState::Running;
+                if running {
+                    self.vex.pause();
+                }
+                self.rewinding = Some(running);
+            }
+            (false, Some(was_running)) => {
+                self.rewinding = None;
+                if was_running {
+                    self.vex.resume();
+                }
+            }
+            _ => {}
+        }
+        if self.rewinding.is_some() {
+            self.request(GuestRequest::Rewind(REWIND_INTERVAL_FRAMES));
+            ctx.request_repaint();
+        }
+
+        let keymap = &self.args.keymap.0;
+        for (machine_key, key) in keymap.iter().enumerate() {
+            let is_down = ctx.input(|input| input.key_down(*key));
+            if is_down != self.keys_down[machine_key] {
+                self.keys_down[machine_key] = is_down;
+                match is_down {
+                    true => self.vex.press_key(machine_key),
+                    false => self.vex.release_key(machine_key),
+                }
+            }
+        }
+
         let (width, height, frame) = self.vex.get_frame();
         let image = ColorImage::from_rgb([width, height], &frame);
         let options = TextureOptions {
@@ -38,14 +378,45 @@ impl eframe::App for KaisekiApp {
         };
         let texture = ctx.load_texture("display", image, options);
 
-        let title = format!("{:?} Display", self.args.machine);
+        // Keep the display the same size on screen regardless of the machine's resolution.
+        let scale = DISPLAY_WIDTH / width as f32;
+        if let Some(scaled) = screenshot {
+            let scale = match scaled {
+                true => scale as usize,
+                false => 1,
+            };
+            self.save_screenshot(width, height, frame, scale);
+        }
+        let title = format!("{:?} Display", self.args.machine());
         egui::Window::new(title)
             .collapsible(false)
-            .default_size((64.0 * 8.0, 32.0 * 8.0))
+            .default_size((DISPLAY_WIDTH, DISPLAY_WIDTH / 2.0))
             .resizable(false)
             .show(ctx, |ui| {
-                ui.image(texture.id(), [width as f32 * 8.0, height as f32 * 8.0]);
-                ui.label(format!("Frame number: {:?}", ctx.frame_nr()));
+                ui.image(texture.id(), [width as f32 * scale, height as f32 * scale]);
+                ui.horizontal(|ui| {
+                    let paused = self.vex.state() == LifecycleState::Paused;
+                    let label = match paused {
+                        true => "Resume",
+                        false => "Pause",
+                    };
+                    if ui.button(label).clicked() {
+                        self.toggle_pause();
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.request(GuestRequest::Reset(ResetKind::Soft));
+                    }
+                    if ui.button("Hard reset").clicked() {
+                        self.request(GuestRequest::Reset(ResetKind::Hard));
+                    }
+                    ui.label(format!("Frame number: {:?}", ctx.frame_nr()));
+                    if let Some(event) = self.vex.debugger().halted() {
+                        ui.label(format!("Halted: {}", event));
+                    }
+                    if let Some(fault) = self.vex.fault() {
+                        ui.colored_label(ui.visuals().error_fg_color, fault.to_string());
+                    }
+                });
                 ui.allocate_space(ui.available_size());
             });
 
@@ -62,13 +433,48 @@ impl KaisekiApp {
         args: Args,
         vex: Vex,
         start_tx: Sender<bool>,
+        request_tx: UnboundedSender<GuestRequest>,
     ) -> Self {
+        let keys_down = vec![false; args.keymap.0.len()];
         Self {
             args,
             vex,
             start_tx: Some(start_tx),
+            request_tx,
+            keys_down,
+            rewinding: None,
+        }
+    }
+
+    fn toggle_pause(&self) {
+        match self.vex.state() {
+            LifecycleState::Running => self.vex.pause(),
+            LifecycleState::Paused => self.vex.resume(),
+            LifecycleState::Faulted | LifecycleState::Stopped => {}
         }
     }
+
+    /// Saves `frame` as a PNG in the working directory, named for the time it was taken.
+    /// Encoding and writing happen on a thread of their own so the UI doesn't stall.
+    fn save_screenshot(&self, width: usize, height: usize, frame: Vec<u8>, scale: usize) {
+        let taken = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = format!("kaiseki-{}-{}x.png", taken, scale);
+        std::thread::spawn(move || {
+            match screenshot::save_png(&path, width, height, &frame, scale) {
+                Ok(()) => tracing::info!("saved screenshot to '{}'", path),
+                Err(e) => tracing::error!("failed to save screenshot: {}", e),
+            }
+        });
+    }
+
+    /// Asks the emulator thread to carry out `request`. A reset also restarts the machine
+    /// after a fault.
+    fn request(&self, request: GuestRequest) {
+        let _ = self.request_tx.send(request);
+    }
 }
 
 fn create_tokio_runtime() -> tokio::runtime::Runtime {
@@ -78,12 +484,17 @@ fn create_tokio_runtime() -> tokio::runtime::Runtime {
         .unwrap()
 }
 
-fn create_ui(args: Args, vex: Vex, start_tx: Sender<bool>) -> Result<()> {
+fn create_ui(
+    args: Args,
+    vex: Vex,
+    start_tx: Sender<bool>,
+    request_tx: UnboundedSender<GuestRequest>,
+) -> Result<()> {
     let options = eframe::NativeOptions::default();
     let res = eframe::run_native(
         "Kaiseki",
         options,
-        Box::new(|cc| Box::new(KaisekiApp::new(cc, args, vex, start_tx))),
+        Box::new(|cc| Box::new(KaisekiApp::new(cc, args, vex, start_tx, request_tx))),
     );
     match res {
         Ok(_) => Ok(()),
@@ -91,41 +502,149 @@ fn create_ui(args: Args, vex: Vex, start_tx: Sender<bool>) -> Result<()> {
     }
 }
 
+/// Carries out `request`, returning whether it was a reset.
+async fn handle_request(guest: &Vex, request: GuestRequest) -> bool {
+    let result = match request {
+        GuestRequest::Reset(kind) => guest.reset(kind).await,
+        GuestRequest::Rewind(frames) => guest.rewind(frames).await.map(|_| ()),
+    };
+    if let Err(e) = result {
+        tracing::error!("failed to carry out {:?}: {}", request, e);
+    }
+    matches!(request, GuestRequest::Reset(_))
+}
+
+/// Runs `guest` until it's stopped, carrying out whatever the UI asks. A faulted guest sits
+/// idle until it's reset, then starts running again.
+async fn run_guest(guest: Vex, mut request_rx: UnboundedReceiver<GuestRequest>) {
+    loop {
+        // Run on its own task so a reset can take the CPU's locks while it's mid-instruction.
+        let run_guest = guest.clone();
+        let mut running = tokio::spawn(async move { run_guest.start().await });
+        loop {
+            tokio::select! {
+                // A fault is logged and shown in the UI; there's nothing more to do with it here.
+                _ = &mut running => break,
+                Some(request) = request_rx.recv() => {
+                    handle_request(&guest, request).await;
+                }
+            }
+        }
+        if guest.state() != LifecycleState::Faulted {
+            break;
+        }
+        loop {
+            match request_rx.recv().await {
+                Some(request) if handle_request(&guest, request).await => break,
+                Some(_) => {}
+                None => return,
+            }
+        }
+    }
+}
+
+fn create_chip8_vex(
+    args: &Args,
+    variant: Chip8Variant,
+    default_quirks: Chip8Quirks,
+    movie: Option<&Movie>,
+) -> Result<Vex> {
+    let quirks = args.quirks.map_or(default_quirks, Chip8Quirks::from);
+    let font_set = args.font.into();
+    let seed = movie
+        .map(Movie::seed)
+        .or(args.seed)
+        .unwrap_or_else(rand::random);
+    let machine = match &args.wav {
+        Some(path) => {
+            let sink = WavAudioSink::new(path, AUDIO_SAMPLE_RATE)?;
+            Chip8Machine::new(variant, quirks, font_set, sink, seed)?
+        }
+        None => {
+            let sink = NullAudioSink::new(AUDIO_SAMPLE_RATE);
+            Chip8Machine::new(variant, quirks, font_set, sink, seed)?
+        }
+    };
+    Ok(Vex::create(machine, args.rom())?)
+}
+
 fn main() -> Result<()> {
     let _guard = config_tracing();
 
     let args = Args::parse();
-    let machine_type = args.machine;
-    let guest = match machine_type {
-        SupportedMachines::Chip8 => {
-            let machine = Chip8Machine::new()?;
-            Vex::create(machine, "kaiseki-chip8/assets/Chip8 Picture.ch8")
-        }
+    if let Some(command) = &args.command {
+        return match command {
+            Command::Asm { source, output } => asm::run(source, output),
+            Command::Disasm { rom, machine } => disasm::run(rom, machine.variant()),
+        };
+    }
+
+    let movie = args.replay.as_deref().map(Movie::load).transpose()?;
+    let machine = args.machine();
+    let guest = create_chip8_vex(
+        &args,
+        machine.variant(),
+        machine.default_quirks(),
+        movie.as_ref(),
+    )?;
+    if let Some(movie) = &movie {
+        create_tokio_runtime().block_on(guest.replay(movie))?;
+    }
+    if args.record.is_some() {
+        create_tokio_runtime().block_on(guest.record())?;
+    }
+
+    if args.headless {
+        return headless::run(&args, guest);
+    }
+
+    let gdb_server = match args.gdb {
+        Some(port) => Some(GdbServer::bind(guest.clone(), port)?),
+        None => None,
     };
 
     let (start_tx, start_rx) = tokio::sync::oneshot::channel::<bool>();
+    let (request_tx, request_rx) = tokio::sync::mpsc::unbounded_channel();
+    guest.enable_rewind(REWIND_FRAMES, REWIND_INTERVAL_FRAMES);
     let uiguest = guest.clone();
+    let stop_guest = guest.clone();
 
     tracing::info!("starting emulator thread");
     let emulator_thread = std::thread::spawn(move || {
         let runtime = create_tokio_runtime();
         runtime.block_on(async {
             let _ = start_rx.await;
-            guest.start().await.unwrap();
+            if let Some(server) = gdb_server {
+                tokio::spawn(async move {
+                    if let Err(e) = server.serve().await {
+                        tracing::error!("GDB stub failed: {}", e);
+                    }
+                });
+            }
+            run_guest(guest, request_rx).await;
         });
     });
 
     tracing::info!("creating ui");
-    create_ui(args, uiguest, start_tx)?;
+    let record = args.record.clone();
+    let ui_result = create_ui(args, uiguest, start_tx, request_tx);
+    stop_guest.stop();
 
     tracing::info!("waiting for emulator thread");
     let _ = emulator_thread.join();
-    Ok(())
+    if let (Some(path), Some(movie)) = (record, stop_guest.stop_recording()) {
+        movie.save(&path)?;
+        tracing::info!("saved {} input events to '{}'", movie.events().len(), path);
+    }
+    ui_result
 }
 
 #[cfg(not(debug_assertions))]
 fn config_tracing() {
-    tracing_subscriber::fmt::init();
+    // Log to stderr so that headless output on stdout can be piped.
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
 }
 
 #[cfg(debug_assertions)]
@@ -139,6 +658,7 @@ fn config_tracing() -> impl Drop {
         .from_env_lossy();
     let fmt_layer = tracing_subscriber::fmt::layer()
         .compact()
+        .with_writer(std::io::stderr)
         .with_filter(fmt_filter);
 
     let (flame_layer, _guard) = FlameLayer::with_file("./tracing.folded").unwrap();
@@ -151,3 +671,40 @@ fn config_tracing() -> impl Drop {
 
     _guard
 }
+
+#[cfg(test)]
+mod tests {
+    use egui::Key;
+
+    use super::parse_keymap;
+
+    #[test]
+    fn keymap_maps_each_machine_key() {
+        let keymap = parse_keymap("x123qweasdzc4rfv").unwrap();
+        assert_eq!(keymap.0.len(), 16);
+        assert_eq!(keymap.0[0x0], Key::X);
+        assert_eq!(keymap.0[0xF], Key::V);
+    }
+
+    #[test]
+    fn keymap_rejects_wrong_lengths() {
+        let short = parse_keymap("X123QWEASDZC4RF").unwrap_err();
+        assert!(short.to_string().contains("has 15"));
+        let long = parse_keymap("X123QWEASDZC4RFVB").unwrap_err();
+        assert!(long.to_string().contains("has 17"));
+    }
+
+    #[test]
+    fn keymap_rejects_repeated_keys() {
+        let error = parse_keymap("X123QWEASDZC4RFX").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "'X' is mapped to both machine keys 0 and F"
+        );
+    }
+
+    #[test]
+    fn keymap_rejects_unknown_keys() {
+        assert!(parse_keymap("X123QWEASDZC4RF!").is_err());
+    }
+}
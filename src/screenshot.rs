@@ -0,0 +1,29 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use anyhow::{anyhow, Result};
+
+/// Writes an RGB frame as a PNG image, with each pixel scaled up to a `scale` x `scale`
+/// square.
+pub fn save_png(path: &str, width: usize, height: usize, frame: &[u8], scale: usize) -> Result<()> {
+    let (width, height) = (width * scale, height * scale);
+    let image: Vec<u8> = frame
+        .chunks(width / scale * 3)
+        .flat_map(|row| {
+            let row: Vec<u8> = row
+                .chunks(3)
+                .flat_map(|pixel| pixel.repeat(scale))
+                .collect();
+            row.repeat(scale)
+        })
+        .collect();
+
+    let file = File::create(path).map_err(|e| anyhow!("failed to create '{}': {}", path, e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let write_error = |e: png::EncodingError| anyhow!("failed to write '{}': {}", path, e);
+    let mut writer = encoder.write_header().map_err(write_error)?;
+    writer.write_image_data(&image).map_err(write_error)?;
+    writer.finish().map_err(write_error)
+}